@@ -0,0 +1,362 @@
+//! Copy, cut and paste, through floating selections.
+//!
+//! A `FloatingSelection` is a piece of one layer lifted out of its canvas: the values of some tiles, and where they sit
+//! relative to an anchor tile. It can be moved and transformed freely without touching any project, and then stamped
+//! back down onto any layer of the same kind, in this project or another.
+//!
+//! On hexagonal grids, tiles are kept relative to the anchor in cube coordinates. Moving by an odd number of columns
+//! flips which columns are shifted down, so offsets between tiles change, but cube offsets don't, and the shape
+//! survives the move intact. Cube coordinates also make the six 60° rotations and the mirrorings exact.
+use serde::{Deserialize, Serialize};
+
+use super::palette_edit::nearest_palette_index;
+use super::{EditError, GridType, LayerKind, LayerV1Canvas, Palette, ProjectV2, Selection};
+use crate::grid::{CubeCoord, OffsetCoord};
+use crate::tools::{write_each, BACKGROUND_INDEX};
+
+pub mod system;
+
+/// The values of the tiles of a `FloatingSelection`, one per tile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FloatingValues {
+    /// Palette indices, along with the palette they index into.
+    BaseColor {
+        palette: Palette,
+        indices: Vec<u32>,
+    },
+    Alpha(Vec<f32>),
+    Shading(Vec<i32>),
+}
+
+impl FloatingValues {
+    pub fn kind(&self) -> LayerKind {
+        match self {
+            FloatingValues::BaseColor { .. } => LayerKind::BaseColor,
+            FloatingValues::Alpha(_) => LayerKind::Alpha,
+            FloatingValues::Shading(_) => LayerKind::Shading,
+        }
+    }
+}
+
+/// Tiles lifted out of a layer, ready to be moved, transformed and stamped back down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatingSelection {
+    gridtype: GridType,
+    /// Where the anchor tile will be stamped.
+    anchor: OffsetCoord,
+    /// Each tile's position relative to the anchor: a cube offset `[q, r]` on hexagonal grids, or a column and row
+    /// offset on square grids.
+    offsets: Vec<[i32; 2]>,
+    /// One value per entry of `offsets`.
+    values: FloatingValues,
+}
+
+impl FloatingSelection {
+    /// Tiles with `values` at `tiles`, which must be the same length, anchored at the one nearest to their middle.
+    pub(crate) fn new(gridtype: GridType, tiles: &[OffsetCoord], values: FloatingValues) -> Self {
+        let anchor = middle_tile(tiles, gridtype);
+        Self {
+            gridtype,
+            anchor,
+            offsets: tiles
+                .iter()
+                .map(|tile| offset_between(gridtype, anchor, *tile))
+                .collect(),
+            values,
+        }
+    }
+
+    pub fn gridtype(&self) -> GridType {
+        self.gridtype
+    }
+
+    /// Where the anchor tile will be stamped. Rotations and mirrorings turn the selection around this tile, which
+    /// starts out as the lifted tile nearest to the middle of the lifted tiles.
+    pub fn anchor(&self) -> OffsetCoord {
+        self.anchor
+    }
+
+    pub fn values(&self) -> &FloatingValues {
+        &self.values
+    }
+
+    /// The number of tiles.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Where every tile will be stamped, in the same order as the values. Tiles can be off any particular canvas.
+    pub fn tiles(&self) -> impl Iterator<Item = OffsetCoord> + '_ {
+        self.offsets
+            .iter()
+            .map(|offset| apply_offset(self.gridtype, self.anchor, *offset))
+    }
+
+    /// Moves the selection by whole tiles: `columns` to the right and `rows` down, as measured at the anchor. The
+    /// anchor stops at the limits of `i32` rather than wrapping around.
+    pub fn move_by(&mut self, columns: i32, rows: i32) {
+        self.anchor = OffsetCoord::new(
+            self.anchor.col.saturating_add(columns),
+            self.anchor.row.saturating_add(rows),
+        );
+    }
+
+    /// Moves the selection so that its anchor is at `anchor`.
+    pub fn move_to(&mut self, anchor: OffsetCoord) {
+        self.anchor = anchor;
+    }
+
+    /// Turns the selection clockwise on screen around its anchor, by 60° on hexagonal grids and 90° on square grids.
+    pub fn rotate_clockwise(&mut self) {
+        self.transform(|[q, r]| [-r, q + r], |[x, y]| [-y, x]);
+    }
+
+    /// Turns the selection anticlockwise on screen around its anchor, by 60° on hexagonal grids and 90° on square
+    /// grids.
+    pub fn rotate_anticlockwise(&mut self) {
+        self.transform(|[q, r]| [q + r, -q], |[x, y]| [y, -x]);
+    }
+
+    /// Mirrors the selection left to right, around the column of its anchor.
+    pub fn flip_horizontally(&mut self) {
+        self.transform(|[q, r]| [-q, q + r], |[x, y]| [-x, y]);
+    }
+
+    /// Mirrors the selection top to bottom, around the row of its anchor.
+    pub fn flip_vertically(&mut self) {
+        self.transform(|[q, r]| [q, -q - r], |[x, y]| [x, -y]);
+    }
+
+    /// Applies `hexagonal` or `square` to the offset of every tile, depending on the grid type.
+    fn transform(&mut self, hexagonal: fn([i32; 2]) -> [i32; 2], square: fn([i32; 2]) -> [i32; 2]) {
+        let transform = match self.gridtype {
+            GridType::Hexagonal => hexagonal,
+            GridType::Square => square,
+        };
+        for offset in &mut self.offsets {
+            *offset = transform(*offset);
+        }
+    }
+}
+
+/// The tile `offset` away from `anchor`, as stored in `FloatingSelection::offsets`. Tiles past the limits of `i32` are
+/// clamped to them, which puts them off any canvas.
+fn apply_offset(gridtype: GridType, anchor: OffsetCoord, offset: [i32; 2]) -> OffsetCoord {
+    let [col, row, a, b] = [anchor.col, anchor.row, offset[0], offset[1]].map(i64::from);
+    let (col, row) = match gridtype {
+        // The same conversions to and from axial coordinates as `grid` makes, but in `i64`, so anchors moved far off
+        // the canvas can't overflow.
+        GridType::Hexagonal => {
+            let q = col + a;
+            let r = row - (col - (col & 1)) / 2 + b;
+            (q, r + (q - (q & 1)) / 2)
+        }
+        GridType::Square => (col + a, row + b),
+    };
+    let clamp = |x: i64| x.clamp(i32::MIN.into(), i32::MAX.into()) as i32;
+    OffsetCoord::new(clamp(col), clamp(row))
+}
+
+/// How far `tile` is from `anchor`, as stored in `FloatingSelection::offsets`.
+fn offset_between(gridtype: GridType, anchor: OffsetCoord, tile: OffsetCoord) -> [i32; 2] {
+    match gridtype {
+        GridType::Hexagonal => {
+            let offset = CubeCoord::from(tile).sub(CubeCoord::from(anchor));
+            [offset.q(), offset.r()]
+        }
+        GridType::Square => [tile.col - anchor.col, tile.row - anchor.row],
+    }
+}
+
+/// The tile of `tiles` nearest to their average position, or the origin if there are none.
+fn middle_tile(tiles: &[OffsetCoord], gridtype: GridType) -> OffsetCoord {
+    let centre = |tile: &OffsetCoord| {
+        let shift = match gridtype {
+            GridType::Hexagonal if tile.is_shifted() => 0.5,
+            _ => 0.0,
+        };
+        [tile.col as f64, tile.row as f64 + shift]
+    };
+    let n = tiles.len().max(1) as f64;
+    let [x, y] = tiles
+        .iter()
+        .map(centre)
+        .fold([0.0, 0.0], |[x, y], [tx, ty]| [x + tx / n, y + ty / n]);
+    tiles
+        .iter()
+        .copied()
+        .min_by(|a, b| {
+            let distance = |tile: &OffsetCoord| {
+                let [tx, ty] = centre(tile);
+                (tx - x).powi(2) + (ty - y).powi(2)
+            };
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap_or_default()
+}
+
+impl ProjectV2 {
+    /// Copies the selected tiles of `layer`, or every tile if there's no active selection, into a floating selection
+    /// at the same place.
+    pub fn copy_selection(&self, layer: usize) -> Result<FloatingSelection, EditError> {
+        let canvas = &self
+            .layers
+            .get(layer)
+            .ok_or(EditError::NoSuchLayer(layer))?
+            .canvas;
+        let positions: Vec<usize> = match &self.selection {
+            Some(selection) => selection.positions().collect(),
+            None => (0..self.size.area() as usize).collect(),
+        };
+        let positions: Vec<usize> = positions
+            .into_iter()
+            .filter(|position| *position < canvas.len())
+            .collect();
+        let tiles: Vec<OffsetCoord> = positions
+            .iter()
+            .filter_map(|position| OffsetCoord::from_index(*position, self.size))
+            .collect();
+        let values = match canvas {
+            LayerV1Canvas::BaseColor { palette, canvas } => {
+                let canvas = canvas.read();
+                FloatingValues::BaseColor {
+                    palette: palette.read().clone(),
+                    indices: positions.iter().map(|position| canvas[*position]).collect(),
+                }
+            }
+            LayerV1Canvas::Alpha(alpha) => {
+                FloatingValues::Alpha(positions.iter().map(|position| alpha[*position]).collect())
+            }
+            LayerV1Canvas::Shading(shading) => FloatingValues::Shading(
+                positions
+                    .iter()
+                    .map(|position| shading[*position])
+                    .collect(),
+            ),
+        };
+        Ok(FloatingSelection::new(self.gridtype, &tiles, values))
+    }
+
+    /// Like `copy_selection`, but also clears the copied tiles to the background colour, full opacity, or no shading.
+    /// The clearing is recorded as one entry in the history.
+    pub fn cut_selection(&mut self, layer: usize) -> Result<FloatingSelection, EditError> {
+        let floating = self.copy_selection(layer)?;
+        let positions: Vec<usize> = floating
+            .tiles()
+            .filter_map(|tile| tile.to_index(self.size))
+            .collect();
+        self.begin_edit_group();
+        let result = match &mut self.layers[layer].canvas {
+            LayerV1Canvas::BaseColor { canvas, .. } => {
+                let changes = write_each(
+                    &mut canvas.write(),
+                    positions
+                        .iter()
+                        .map(|position| (*position, BACKGROUND_INDEX)),
+                );
+                self.record_tile_changes(layer, changes)
+            }
+            LayerV1Canvas::Alpha(alpha) => {
+                let changes = write_each(alpha, positions.iter().map(|position| (*position, 1.0)));
+                self.record_alpha_changes(layer, changes)
+            }
+            LayerV1Canvas::Shading(shading) => {
+                let changes = write_each(shading, positions.iter().map(|position| (*position, 0)));
+                self.record_shading_changes(layer, changes)
+            }
+        };
+        self.end_edit_group();
+        result.map(|_| floating)
+    }
+
+    /// Stamps `floating` onto `layer`, which must be of the same kind, and selects the stamped tiles. Tiles off the
+    /// canvas are dropped, and if that leaves none, the selection is left as it was. Palette indices are remapped to the nearest colour in the layer's palette, unless the
+    /// palettes are identical, and indices out of range of their own palette are dropped. The stamp is recorded as one
+    /// entry in the history.
+    pub fn paste(&mut self, layer: usize, floating: &FloatingSelection) -> Result<(), EditError> {
+        if floating.gridtype != self.gridtype {
+            return Err(EditError::GridTypeMismatch(
+                floating.gridtype,
+                self.gridtype,
+            ));
+        }
+        let canvas = &self
+            .layers
+            .get(layer)
+            .ok_or(EditError::NoSuchLayer(layer))?
+            .canvas;
+        if canvas.kind() != floating.values.kind() {
+            return Err(EditError::KindMismatch {
+                layer,
+                kind: canvas.kind(),
+                pasted: floating.values.kind(),
+            });
+        }
+        let tiles: Vec<(usize, usize)> = floating
+            .tiles()
+            .enumerate()
+            .filter_map(|(i, tile)| Some((i, tile.to_index(self.size)?)))
+            .filter(|(_, position)| *position < canvas.len())
+            .collect();
+
+        self.begin_edit_group();
+        let result = match (&mut self.layers[layer].canvas, &floating.values) {
+            (
+                LayerV1Canvas::BaseColor { palette, canvas },
+                FloatingValues::BaseColor {
+                    palette: source,
+                    indices,
+                },
+            ) => {
+                let palette = palette.read();
+                let remap: Vec<Option<u32>> = if *palette == *source {
+                    (0..source.len() as u32).map(Some).collect()
+                } else {
+                    source
+                        .iter()
+                        .map(|color| nearest_palette_index(&palette, *color))
+                        .collect()
+                };
+                let changes = write_each(
+                    &mut canvas.write(),
+                    tiles.iter().filter_map(|(i, position)| {
+                        Some((*position, (*remap.get(indices[*i] as usize)?)?))
+                    }),
+                );
+                drop(palette);
+                self.record_tile_changes(layer, changes)
+            }
+            (LayerV1Canvas::Alpha(alpha), FloatingValues::Alpha(values)) => {
+                let changes = write_each(
+                    alpha,
+                    tiles.iter().map(|(i, position)| (*position, values[*i])),
+                );
+                self.record_alpha_changes(layer, changes)
+            }
+            (LayerV1Canvas::Shading(shading), FloatingValues::Shading(values)) => {
+                let changes = write_each(
+                    shading,
+                    tiles.iter().map(|(i, position)| (*position, values[*i])),
+                );
+                self.record_shading_changes(layer, changes)
+            }
+            _ => unreachable!("We just checked that the kinds match."),
+        };
+        let result = result.and_then(|_| {
+            if tiles.is_empty() {
+                return Ok(());
+            }
+            self.set_selection(Some(Selection::from_positions(
+                self.size,
+                self.gridtype,
+                tiles.iter().map(|(_, position)| *position),
+            )))
+        });
+        self.end_edit_group();
+        result
+    }
+}
@@ -0,0 +1,420 @@
+//! Exchanging tiles with other applications through the system clipboard.
+//!
+//! Copying puts two representations of a floating selection on the clipboard: the tiles themselves as
+//! `TILES_MIME`, for pasting into Hexil without losing anything, and a PNG of them as `PNG_MIME`, for everything else.
+//! Pasting prefers the tiles, and otherwise takes a PNG, or any other image, and turns each of its pixels into a tile
+//! of the nearest colour in the palette of the layer being pasted onto.
+//!
+//! The clipboard itself is reached through `ClipboardBackend`, so that the windowing side can use the real
+//! `SystemClipboard` and everything else can use a `MemoryClipboard`.
+//!
+//! The `TILES_MIME` payload is an 8 byte `TILES_MAGIC`, a little endian `u32` format version, and then the tiles
+//! encoded with `bincode`: their grid type, the anchor, each tile's offset from it, and their values, which for base
+//! colour tiles include the palette. Payloads come from other processes, so anchors and offsets further than
+//! `MAX_PASTED_COORD` from the origin are rejected, rather than left to overflow once the tiles are placed.
+use bincode::Options;
+use clipboard_rs::common::RustImage;
+use clipboard_rs::{Clipboard, ClipboardContent, ClipboardContext, ContentFormat, RustImageData};
+use palette::{FromColor, Srgb};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{FloatingSelection, FloatingValues};
+use crate::app::palette_edit::nearest_palette_index;
+use crate::app::{
+    CanvasSize, Color, EditError, GridType, LayerV1Canvas, Palette, ProjectV2, TileColor,
+};
+use crate::export::png::{write_raster, PngExportError};
+use crate::export::{rasterize_tiles, Raster, RasterOptions};
+use crate::grid::OffsetCoord;
+
+/// The MIME type of tiles copied from Hexil.
+pub const TILES_MIME: &str = "application/x-hexil-tiles";
+
+/// The MIME type of the image of copied tiles.
+pub const PNG_MIME: &str = "image/png";
+
+/// The first 8 bytes of every `TILES_MIME` payload.
+pub const TILES_MAGIC: [u8; 8] = *b"HEXTILES";
+
+/// The version of the `TILES_MIME` payload this Hexil writes, and the only one it reads.
+pub const TILES_VERSION: u32 = 1;
+
+/// The furthest from the origin, in either coordinate, that the anchor of pasted tiles or any tile's offset from it may
+/// be. This keeps every pasted tile well within the range of `i32`, however it is turned and moved afterwards, while
+/// leaving room for copies from canvases far larger than any real one.
+pub const MAX_PASTED_COORD: i32 = 1 << 28;
+
+/// Everything that can go wrong while copying to or pasting from the clipboard.
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error("The clipboard is unavailable: {0}")]
+    Backend(String),
+    #[error("There is nothing on the clipboard that can be pasted!")]
+    NothingToPaste,
+    #[error("Copied tiles of version {0} are not supported by this version of Hexil!")]
+    UnsupportedVersion(u32),
+    #[error("The copied tiles are corrupt!")]
+    Corrupt,
+    #[error(transparent)]
+    Decode(#[from] bincode::Error),
+    #[error(transparent)]
+    Image(#[from] ::image::ImageError),
+    #[error(transparent)]
+    Png(#[from] PngExportError),
+    #[error(transparent)]
+    Edit(#[from] EditError),
+}
+
+/// The same data in one particular format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardItem {
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+impl ClipboardItem {
+    pub fn new(mime: &str, data: Vec<u8>) -> Self {
+        Self {
+            mime: mime.to_owned(),
+            data,
+        }
+    }
+}
+
+/// A clipboard that holds one piece of data at a time, in as many formats as it was given.
+pub trait ClipboardBackend {
+    /// Replaces everything on the clipboard with `items`, which are all the same data in different formats.
+    fn write(&mut self, items: Vec<ClipboardItem>) -> Result<(), ClipboardError>;
+
+    /// What's on the clipboard in the format `mime`, if anything.
+    fn read(&mut self, mime: &str) -> Result<Option<Vec<u8>>, ClipboardError>;
+}
+
+/// A clipboard that only exists inside Hexil. Used wherever there is no system clipboard, such as in tests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryClipboard {
+    items: Vec<ClipboardItem>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything on the clipboard, in the order it was written.
+    pub fn items(&self) -> &[ClipboardItem] {
+        &self.items
+    }
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn write(&mut self, items: Vec<ClipboardItem>) -> Result<(), ClipboardError> {
+        self.items = items;
+        Ok(())
+    }
+
+    fn read(&mut self, mime: &str) -> Result<Option<Vec<u8>>, ClipboardError> {
+        Ok(self
+            .items
+            .iter()
+            .find(|item| item.mime == mime)
+            .map(|item| item.data.clone()))
+    }
+}
+
+/// The system clipboard, through `clipboard-rs`, which can put formats of Hexil's own on it next to the image. Tiles
+/// go on as `TILES_MIME` exactly as they were written, so other instances of Hexil get everything that was copied. The
+/// image goes on in whatever format the platform uses for images, and comes back off as a PNG.
+///
+/// On some platforms the clipboard only keeps what was copied while the process that copied it is running, so this
+/// should live as long as the window.
+pub struct SystemClipboard {
+    clipboard: ClipboardContext,
+}
+
+impl SystemClipboard {
+    /// Connects to the system clipboard.
+    pub fn new() -> Result<Self, ClipboardError> {
+        Ok(Self {
+            clipboard: ClipboardContext::new().map_err(backend_error)?,
+        })
+    }
+}
+
+fn backend_error(error: impl std::fmt::Display) -> ClipboardError {
+    ClipboardError::Backend(error.to_string())
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn write(&mut self, items: Vec<ClipboardItem>) -> Result<(), ClipboardError> {
+        let contents = items
+            .into_iter()
+            .map(|item| match item.mime.as_str() {
+                PNG_MIME => RustImageData::from_bytes(&item.data)
+                    .map(ClipboardContent::Image)
+                    .map_err(backend_error),
+                _ => Ok(ClipboardContent::Other(item.mime, item.data)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if contents.is_empty() {
+            self.clipboard.clear()
+        } else {
+            self.clipboard.set(contents)
+        }
+        .map_err(backend_error)
+    }
+
+    fn read(&mut self, mime: &str) -> Result<Option<Vec<u8>>, ClipboardError> {
+        if mime == PNG_MIME {
+            if !self.clipboard.has(ContentFormat::Image) {
+                return Ok(None);
+            }
+            let image = self.clipboard.get_image().map_err(backend_error)?;
+            let png = image.to_png().map_err(backend_error)?;
+            return Ok(Some(png.get_bytes().to_vec()));
+        }
+        if !self.clipboard.has(ContentFormat::Other(mime.to_owned())) {
+            return Ok(None);
+        }
+        self.clipboard
+            .get_buffer(mime)
+            .map(Some)
+            .map_err(backend_error)
+    }
+}
+
+/// The `TILES_MIME` payload after the header.
+#[derive(Serialize, Deserialize)]
+struct TilesPayload {
+    gridtype: GridType,
+    anchor: [i32; 2],
+    offsets: Vec<[i32; 2]>,
+    values: FloatingValues,
+}
+
+/// The `bincode` configuration used for every `TILES_MIME` payload. This must never change for an already released
+/// version.
+fn payload_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .reject_trailing_bytes()
+}
+
+/// Encodes `floating` as a `TILES_MIME` payload.
+pub fn encode_tiles(floating: &FloatingSelection) -> Result<Vec<u8>, ClipboardError> {
+    let payload = TilesPayload {
+        gridtype: floating.gridtype,
+        anchor: [floating.anchor.col, floating.anchor.row],
+        offsets: floating.offsets.clone(),
+        values: floating.values.clone(),
+    };
+    let mut data = TILES_MAGIC.to_vec();
+    data.extend_from_slice(&TILES_VERSION.to_le_bytes());
+    payload_options().serialize_into(&mut data, &payload)?;
+    Ok(data)
+}
+
+/// Decodes a `TILES_MIME` payload.
+pub fn decode_tiles(data: &[u8]) -> Result<FloatingSelection, ClipboardError> {
+    let header_len = TILES_MAGIC.len() + 4;
+    if data.len() < header_len || data[..TILES_MAGIC.len()] != TILES_MAGIC {
+        return Err(ClipboardError::Corrupt);
+    }
+    let version = u32::from_le_bytes(data[TILES_MAGIC.len()..header_len].try_into().unwrap());
+    if version != TILES_VERSION {
+        return Err(ClipboardError::UnsupportedVersion(version));
+    }
+    let payload: TilesPayload = payload_options().deserialize(&data[header_len..])?;
+    let len = match &payload.values {
+        FloatingValues::BaseColor { indices, .. } => indices.len(),
+        FloatingValues::Alpha(alpha) => alpha.len(),
+        FloatingValues::Shading(shading) => shading.len(),
+    };
+    if len != payload.offsets.len() {
+        return Err(ClipboardError::Corrupt);
+    }
+    let in_range = |[a, b]: [i32; 2]| {
+        a.unsigned_abs() <= MAX_PASTED_COORD as u32 && b.unsigned_abs() <= MAX_PASTED_COORD as u32
+    };
+    if !in_range(payload.anchor) || !payload.offsets.iter().copied().all(in_range) {
+        return Err(ClipboardError::Corrupt);
+    }
+    let [col, row] = payload.anchor;
+    Ok(FloatingSelection {
+        gridtype: payload.gridtype,
+        anchor: OffsetCoord::new(col, row),
+        offsets: payload.offsets,
+        values: payload.values,
+    })
+}
+
+/// Draws the tiles of `floating` on their own, cropped to them. Base colour tiles are drawn in their colour, alpha
+/// tiles as white at their opacity, and shading tiles as grey lightened or darkened by their shading. On hexagonal
+/// grids the crop always starts on an even column, so the columns stay shifted the way they are on the canvas.
+pub fn rasterize_floating(floating: &FloatingSelection, pixels_per_tile: u32) -> Raster {
+    let tiles: Vec<OffsetCoord> = floating.tiles().collect();
+    let min_col = tiles.iter().map(|tile| tile.col).min().unwrap_or(0);
+    let min_col = match floating.gridtype {
+        GridType::Hexagonal => min_col - min_col.rem_euclid(2),
+        GridType::Square => min_col,
+    };
+    let min_row = tiles.iter().map(|tile| tile.row).min().unwrap_or(0);
+    let size = CanvasSize {
+        width: tiles
+            .iter()
+            .map(|tile| (tile.col - min_col + 1) as u64)
+            .max()
+            .unwrap_or(0),
+        height: tiles
+            .iter()
+            .map(|tile| (tile.row - min_row + 1) as u64)
+            .max()
+            .unwrap_or(0),
+    };
+    let mut colors = vec![TileColor::UNCOVERED; size.area() as usize];
+    for (i, tile) in tiles.iter().enumerate() {
+        let position = OffsetCoord::new(tile.col - min_col, tile.row - min_row)
+            .to_index(size)
+            .expect("Every tile is inside the crop.");
+        colors[position] = match &floating.values {
+            FloatingValues::BaseColor { palette, indices } => {
+                match palette.get(indices[i] as usize) {
+                    Some(color) => TileColor::UNCOVERED.paint(*color, 1.0),
+                    None => TileColor::UNCOVERED,
+                }
+            }
+            FloatingValues::Alpha(alpha) => TileColor::UNCOVERED
+                .paint(Color::new(1.0, 0.0, 0.0), 1.0)
+                .mask(alpha[i], 1.0),
+            FloatingValues::Shading(shading) => TileColor::UNCOVERED
+                .paint(Color::new(0.5, 0.0, 0.0), 1.0)
+                .shade(shading[i], 1.0),
+        };
+    }
+    rasterize_tiles(
+        &colors,
+        size,
+        floating.gridtype,
+        RasterOptions {
+            pixels_per_tile,
+            background: None,
+        },
+    )
+}
+
+/// Turns an image into base colour tiles, one per pixel, with the top left pixel at the top left of the canvas. Each
+/// pixel becomes the entry of `palette` nearest to it in Oklab, and pixels that are more than half transparent are
+/// left out.
+pub fn quantize_image(
+    image: &::image::RgbaImage,
+    palette: &Palette,
+    gridtype: GridType,
+) -> FloatingSelection {
+    let mut tiles = Vec::new();
+    let mut indices = Vec::new();
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let [r, g, b, a] = pixel.0;
+        let color = Color::from_color(Srgb::new(r, g, b).into_format::<f32>());
+        let (Some(index), true) = (nearest_palette_index(palette, color), a >= 128) else {
+            continue;
+        };
+        tiles.push(OffsetCoord::new(x as i32, y as i32));
+        indices.push(index);
+    }
+    FloatingSelection::new(
+        gridtype,
+        &tiles,
+        FloatingValues::BaseColor {
+            palette: palette.clone(),
+            indices,
+        },
+    )
+}
+
+/// Puts `floating` on the clipboard, both as tiles and as a PNG drawn at `pixels_per_tile`.
+#[instrument(skip_all, err)]
+pub fn copy_to_clipboard(
+    floating: &FloatingSelection,
+    pixels_per_tile: u32,
+    clipboard: &mut (impl ClipboardBackend + ?Sized),
+) -> Result<(), ClipboardError> {
+    let mut items = vec![ClipboardItem::new(TILES_MIME, encode_tiles(floating)?)];
+    let mut png = Vec::new();
+    // An empty selection has no image, but its tiles can still be pasted.
+    match write_raster(&rasterize_floating(floating, pixels_per_tile), &mut png) {
+        Ok(()) => items.push(ClipboardItem::new(PNG_MIME, png)),
+        Err(PngExportError::Empty) => {}
+        Err(error) => return Err(error.into()),
+    }
+    clipboard.write(items)
+}
+
+impl ProjectV2 {
+    /// Copies the selected tiles of `layer`, or every tile if there's no active selection, onto the clipboard.
+    pub fn copy_to_clipboard(
+        &self,
+        layer: usize,
+        clipboard: &mut (impl ClipboardBackend + ?Sized),
+    ) -> Result<(), ClipboardError> {
+        let floating = self.copy_selection(layer)?;
+        copy_to_clipboard(
+            &floating,
+            RasterOptions::default().pixels_per_tile,
+            clipboard,
+        )
+    }
+
+    /// Like `copy_to_clipboard`, but also clears the copied tiles, as `cut_selection` does. Nothing is cleared if the
+    /// clipboard can't be written to.
+    pub fn cut_to_clipboard(
+        &mut self,
+        layer: usize,
+        clipboard: &mut (impl ClipboardBackend + ?Sized),
+    ) -> Result<(), ClipboardError> {
+        self.copy_to_clipboard(layer, clipboard)?;
+        self.cut_selection(layer)?;
+        Ok(())
+    }
+
+    /// Reads whatever is on the clipboard as tiles that could be pasted onto `layer`. Images are quantised to the
+    /// palette of `layer`, so can only be pasted onto base colour layers.
+    pub fn read_clipboard(
+        &self,
+        layer: usize,
+        clipboard: &mut (impl ClipboardBackend + ?Sized),
+    ) -> Result<FloatingSelection, ClipboardError> {
+        if let Some(data) = clipboard.read(TILES_MIME)? {
+            return decode_tiles(&data);
+        }
+        let Some(data) = clipboard.read(PNG_MIME)? else {
+            return Err(ClipboardError::NothingToPaste);
+        };
+        let image = ::image::load_from_memory(&data)?.into_rgba8();
+        match &self
+            .layers
+            .get(layer)
+            .ok_or(EditError::NoSuchLayer(layer))?
+            .canvas
+        {
+            LayerV1Canvas::BaseColor { palette, .. } => {
+                Ok(quantize_image(&image, &palette.read(), self.gridtype))
+            }
+            _ => Err(EditError::NotBaseColor(layer).into()),
+        }
+    }
+
+    /// Pastes whatever is on the clipboard onto `layer`, where it was copied from, as `paste` does. Returns what was
+    /// pasted, so that it can be moved on from there.
+    pub fn paste_from_clipboard(
+        &mut self,
+        layer: usize,
+        clipboard: &mut (impl ClipboardBackend + ?Sized),
+    ) -> Result<FloatingSelection, ClipboardError> {
+        let floating = self.read_clipboard(layer, clipboard)?;
+        self.paste(layer, &floating)?;
+        Ok(floating)
+    }
+}
@@ -0,0 +1,252 @@
+//! The model behind the colour picker, independent of how it's drawn.
+//!
+//! The picker edits colours as Oklch: lightness, chroma and hue, the polar form of Oklab. Equal steps along any of
+//! them look like equal steps, which is what makes ramps picked this way even. Not every Oklch colour can be shown on
+//! an sRGB display, so the picker keeps whatever the user dialled in, and maps it into the sRGB gamut by reducing its
+//! chroma only when a colour is actually needed.
+use palette::convert::FromColorUnclamped;
+use palette::{FromColor, Oklch, Srgb};
+use thiserror::Error;
+
+use super::Color;
+use crate::export::{oklab_to_hex, oklab_to_srgb8, parse_hex_srgb8};
+
+/// The largest chroma the chroma slider goes up to. Every sRGB colour has less chroma than this.
+pub const MAX_CHROMA: f32 = 0.37;
+
+/// How far outside `[0, 1]` a channel may be before a colour counts as out of gamut. Lets colours that went through
+/// `f32` rounding still count as in gamut.
+const GAMUT_EPSILON: f32 = 1e-4;
+
+/// The text given to `ColorPicker::set_hex` wasn't a colour.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{0:?} is not a colour of the form #rrggbb!")]
+pub struct InvalidHexColor(pub String);
+
+/// The state of a colour picker: a lightness, chroma and hue slider, and the colour they make.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorPicker {
+    /// From 0 to 1.
+    lightness: f32,
+    /// From 0 to `MAX_CHROMA`.
+    chroma: f32,
+    /// In degrees, from 0 up to but not including 360. Kept even when chroma is 0, so that greys don't forget which
+    /// way the chroma slider was heading.
+    hue: f32,
+}
+
+impl Default for ColorPicker {
+    /// A picker on black.
+    fn default() -> Self {
+        Self {
+            lightness: 0.0,
+            chroma: 0.0,
+            hue: 0.0,
+        }
+    }
+}
+
+impl ColorPicker {
+    /// A picker on `color`. Colours with more chroma than the slider allows are clamped to `MAX_CHROMA`.
+    pub fn new(color: Color) -> Self {
+        let mut picker = Self::default();
+        picker.set_color(color);
+        picker
+    }
+
+    pub fn lightness(&self) -> f32 {
+        self.lightness
+    }
+
+    pub fn chroma(&self) -> f32 {
+        self.chroma
+    }
+
+    /// The hue in degrees, from 0 up to but not including 360.
+    pub fn hue(&self) -> f32 {
+        self.hue
+    }
+
+    /// Sets the lightness, clamped to `[0, 1]`.
+    pub fn set_lightness(&mut self, lightness: f32) {
+        self.lightness = clamp_or_zero(lightness, 1.0);
+    }
+
+    /// Sets the chroma, clamped to `[0, MAX_CHROMA]`.
+    pub fn set_chroma(&mut self, chroma: f32) {
+        self.chroma = clamp_or_zero(chroma, MAX_CHROMA);
+    }
+
+    /// Sets the hue in degrees, wrapping around to `[0, 360)`.
+    pub fn set_hue(&mut self, hue: f32) {
+        self.hue = if hue.is_finite() {
+            hue.rem_euclid(360.0) % 360.0
+        } else {
+            0.0
+        };
+    }
+
+    /// Moves the picker to `color`. Greys keep the current hue.
+    pub fn set_color(&mut self, color: Color) {
+        let oklch = Oklch::from_color(color);
+        self.set_lightness(oklch.l);
+        self.set_chroma(oklch.chroma);
+        if self.chroma > GAMUT_EPSILON {
+            self.set_hue(oklch.hue.into_positive_degrees());
+        }
+    }
+
+    pub fn oklch(&self) -> Oklch {
+        Oklch::new(self.lightness, self.chroma, self.hue)
+    }
+
+    /// The colour exactly as picked, which may be outside of the sRGB gamut.
+    pub fn color(&self) -> Color {
+        Color::from_color(self.oklch())
+    }
+
+    /// Whether the picked colour can be shown on an sRGB display.
+    pub fn in_gamut(&self) -> bool {
+        in_srgb_gamut(self.color())
+    }
+
+    /// The picked colour, with its chroma reduced just enough to bring it into the sRGB gamut. Lightness and hue are
+    /// unchanged.
+    pub fn gamut_mapped(&self) -> Color {
+        gamut_map(self.oklch())
+    }
+
+    /// The gamut mapped colour as `#rrggbb`.
+    pub fn hex(&self) -> String {
+        oklab_to_hex(self.gamut_mapped())
+    }
+
+    /// Moves the picker to a colour given as `#rrggbb` or `#rgb`, with or without the `#`.
+    pub fn set_hex(&mut self, hex: &str) -> Result<(), InvalidHexColor> {
+        let [r, g, b] = parse_hex_srgb8(hex).ok_or_else(|| InvalidHexColor(hex.to_owned()))?;
+        self.set_color(Color::from_color(Srgb::new(r, g, b).into_format::<f32>()));
+        Ok(())
+    }
+
+    /// Where the picker sits in a slice along `axes`, as `[x, y]` from 0 to 1, with `y` going down.
+    pub fn slice_position(&self, axes: SliceAxes) -> [f32; 2] {
+        let [x, y] = axes.values(self);
+        [x, 1.0 - y]
+    }
+
+    /// Moves the picker to `[x, y]` in a slice along `axes`, as from `slice_position`. The slider the slice doesn't show
+    /// is unchanged.
+    pub fn set_slice_position(&mut self, axes: SliceAxes, [x, y]: [f32; 2]) {
+        let (x, y) = (x.clamp(0.0, 1.0), 1.0 - y.clamp(0.0, 1.0));
+        match axes {
+            SliceAxes::ChromaLightness => {
+                self.set_chroma(x * MAX_CHROMA);
+                self.set_lightness(y);
+            }
+            SliceAxes::HueLightness => {
+                self.set_hue(x * 360.0);
+                self.set_lightness(y);
+            }
+            SliceAxes::HueChroma => {
+                self.set_hue(x * 360.0);
+                self.set_chroma(y * MAX_CHROMA);
+            }
+        }
+    }
+
+    /// Renders the 2D field of the picker along `axes`, through the picker's current value of the remaining slider.
+    pub fn gradient_slice(&self, axes: SliceAxes, width: u32, height: u32) -> GradientSlice {
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        let mut picker = *self;
+        for row in 0..height {
+            for col in 0..width {
+                picker.set_slice_position(
+                    axes,
+                    [
+                        (col as f32 + 0.5) / width as f32,
+                        (row as f32 + 0.5) / height as f32,
+                    ],
+                );
+                let [r, g, b] = oklab_to_srgb8(picker.gamut_mapped());
+                let a = if picker.in_gamut() { 255 } else { 0 };
+                pixels.push([r, g, b, a]);
+            }
+        }
+        GradientSlice {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// Which two sliders a gradient slice spans. The first is horizontal, increasing to the right, and the second is
+/// vertical, increasing upwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SliceAxes {
+    ChromaLightness,
+    HueLightness,
+    HueChroma,
+}
+
+impl SliceAxes {
+    /// The picker's position along both axes, each from 0 to 1.
+    fn values(self, picker: &ColorPicker) -> [f32; 2] {
+        let lightness = picker.lightness;
+        let chroma = picker.chroma / MAX_CHROMA;
+        let hue = picker.hue / 360.0;
+        match self {
+            SliceAxes::ChromaLightness => [chroma, lightness],
+            SliceAxes::HueLightness => [hue, lightness],
+            SliceAxes::HueChroma => [hue, chroma],
+        }
+    }
+}
+
+/// A 2D field of colours from the picker, ready to upload as an sRGB texture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GradientSlice {
+    pub width: u32,
+    pub height: u32,
+    /// 8 bit sRGB with alpha, row by row from the top left. Colours outside the sRGB gamut are gamut mapped, and have
+    /// an alpha of 0, so the field can show exactly which colours the display can't.
+    pub pixels: Vec<[u8; 4]>,
+}
+
+fn clamp_or_zero(value: f32, max: f32) -> f32 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, max)
+    }
+}
+
+/// Whether every channel of `color` in sRGB is within `[0, 1]`, give or take rounding.
+pub fn in_srgb_gamut(color: Color) -> bool {
+    // `from_color` would clamp, making everything look in gamut.
+    let srgb = Srgb::from_color_unclamped(color);
+    [srgb.red, srgb.green, srgb.blue]
+        .into_iter()
+        .all(|channel| (-GAMUT_EPSILON..=1.0 + GAMUT_EPSILON).contains(&channel))
+}
+
+/// Brings `color` into the sRGB gamut by reducing its chroma, keeping its lightness and hue. Lightness is clamped to
+/// `[0, 1]` first, as nothing brighter than white or darker than black is in gamut at any chroma.
+pub fn gamut_map(color: Oklch) -> Color {
+    let l = color.l.clamp(0.0, 1.0);
+    let with_chroma = |chroma: f32| Color::from_color(Oklch::new(l, chroma, color.hue));
+    if in_srgb_gamut(with_chroma(color.chroma)) {
+        return with_chroma(color.chroma);
+    }
+    // Bisect for the largest chroma in gamut. Greys are always in gamut, so `low` always is.
+    let (mut low, mut high) = (0.0, color.chroma);
+    for _ in 0..24 {
+        let mid = (low + high) / 2.0;
+        if in_srgb_gamut(with_chroma(mid)) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    with_chroma(low)
+}
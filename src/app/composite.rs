@@ -0,0 +1,96 @@
+//! How the layers of a project combine into the colour of each tile. This is the reference implementation: exports are
+//! made from it, and `canvas_vert.glsl` must match it.
+//!
+//! Layers are applied from the bottom of the stack to the top, skipping hidden ones. Every tile starts out uncovered,
+//! and then each layer changes it according to its kind:
+//! - A base colour layer paints the colour its palette index refers to over the tile, covering what's below by the
+//!   layer's opacity. Tiles with an index outside of the palette are left alone.
+//! - A shading layer shifts the tile's Oklab lightness by `SHADING_STEP` per unit, scaled by the layer's opacity.
+//! - An alpha layer multiplies the coverage of everything below it by the tile's value, clamped to `[0, 1]`. At partial
+//!   opacity, it masks proportionally less.
+use super::{Color, LayerV1Canvas, ProjectV2};
+
+/// How far one unit of a shading layer shifts lightness, in Oklab `L`.
+pub const SHADING_STEP: f32 = 0.01;
+
+/// The colour of a single tile, as the layers it has been through so far left it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileColor {
+    /// The colour of the covered part of the tile. Meaningless while `alpha` is 0.
+    pub color: Color,
+    /// How much of the tile is covered, from 0 to 1. `color` is not premultiplied by this.
+    pub alpha: f32,
+}
+
+impl TileColor {
+    /// A tile nothing has been painted on yet.
+    pub const UNCOVERED: Self = Self {
+        color: Color::new(0.0, 0.0, 0.0),
+        alpha: 0.0,
+    };
+
+    /// Paints `color` over the tile, covering it by `opacity`.
+    pub fn paint(self, color: Color, opacity: f32) -> Self {
+        let alpha = opacity + self.alpha * (1.0 - opacity);
+        if alpha <= 0.0 {
+            return self;
+        }
+        Self {
+            color: color * (opacity / alpha) + self.color * (self.alpha * (1.0 - opacity) / alpha),
+            alpha,
+        }
+    }
+
+    /// Shifts the tile's lightness by `amount` steps of `SHADING_STEP`, scaled by `opacity`.
+    pub fn shade(mut self, amount: i32, opacity: f32) -> Self {
+        self.color.l += amount as f32 * SHADING_STEP * opacity;
+        self
+    }
+
+    /// Multiplies the tile's coverage by `coverage`, scaled by `opacity`. NaN covers nothing.
+    pub fn mask(mut self, coverage: f32, opacity: f32) -> Self {
+        let coverage = if coverage.is_nan() {
+            0.0
+        } else {
+            coverage.clamp(0.0, 1.0)
+        };
+        self.alpha *= 1.0 - opacity * (1.0 - coverage);
+        self
+    }
+
+    /// Whether any of the tile is covered.
+    pub fn is_covered(&self) -> bool {
+        self.alpha > 0.0
+    }
+}
+
+impl ProjectV2 {
+    /// The colour of every tile, with every visible layer applied.
+    pub fn composite(&self) -> Vec<TileColor> {
+        let mut tiles = vec![TileColor::UNCOVERED; self.size.area() as usize];
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            let opacity = layer.opacity;
+            match &layer.canvas {
+                LayerV1Canvas::BaseColor { palette, canvas } => {
+                    let palette = palette.read();
+                    for (tile, index) in tiles.iter_mut().zip(canvas.read().iter()) {
+                        if let Some(color) = palette.get(*index as usize) {
+                            *tile = tile.paint(*color, opacity);
+                        }
+                    }
+                }
+                LayerV1Canvas::Shading(shading) => {
+                    for (tile, amount) in tiles.iter_mut().zip(shading) {
+                        *tile = tile.shade(*amount, opacity);
+                    }
+                }
+                LayerV1Canvas::Alpha(alpha) => {
+                    for (tile, coverage) in tiles.iter_mut().zip(alpha) {
+                        *tile = tile.mask(*coverage, opacity);
+                    }
+                }
+            }
+        }
+        tiles
+    }
+}
@@ -0,0 +1,676 @@
+//! Undo and redo for `ProjectV2`.
+//!
+//! Every `Edit` stores just enough to swap the project between its state before and after the edit. Reverting an edit
+//! applies that swap and turns the edit into its own inverse, so undoing and redoing are the same operation applied
+//! to different stacks.
+use std::collections::VecDeque;
+
+use thiserror::Error;
+use tracing::{error, instrument, trace};
+
+use super::{CanvasSize, LayerV1Canvas, LayerV2, Palette, ProjectV2, Selection};
+use crate::tools::TileChanges;
+
+/// The default limit on the memory used by a project's history: 64 MiB.
+pub const DEFAULT_MEMORY_CAP: usize = 64 * 1024 * 1024;
+
+/// Everything that can go wrong while editing a project through its history.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum EditError {
+    #[error("There is no layer {0}!")]
+    NoSuchLayer(usize),
+    #[error("Layer {0} is not a base colour layer!")]
+    NotBaseColor(usize),
+    #[error("Layer {0} is not an alpha layer!")]
+    NotAlpha(usize),
+    #[error("Layer {0} is not a shading layer!")]
+    NotShading(usize),
+    #[error("Canvas size {0}x{1} is too large!")]
+    CanvasTooLarge(u64, u64),
+    #[error("The layer is {0}x{1}, but the project is {2}x{3}!")]
+    LayerSizeMismatch(u64, u64, u64, u64),
+    #[error("Palette index {index} is out of range for a palette of {len} colours!")]
+    PaletteIndexOutOfRange { index: u32, len: usize },
+    #[error("Palette colour {0} can't be replaced with itself!")]
+    ReplacedWithItself(u32),
+    #[error("That is not a reordering of a palette of {0} colours!")]
+    NotAReordering(usize),
+    #[error(
+        "The selection is for a {0}x{1} {2:?} canvas, but the project is a {3}x{4} {5:?} canvas!"
+    )]
+    SelectionMismatch(u64, u64, super::GridType, u64, u64, super::GridType),
+    #[error("Tiles from a {0:?} grid can't be pasted onto a {1:?} grid!")]
+    GridTypeMismatch(super::GridType, super::GridType),
+    #[error(
+        "Layer {layer} is a {kind:?} layer, but the pasted tiles are from a {pasted:?} layer!"
+    )]
+    KindMismatch {
+        layer: usize,
+        kind: super::LayerKind,
+        pasted: super::LayerKind,
+    },
+}
+
+/// A single reversible change to a project.
+#[derive(Debug)]
+pub enum Edit {
+    /// Tiles of a base colour layer were changed, as reported by a tool.
+    Tiles { layer: usize, changes: TileChanges },
+    /// Tiles of an alpha layer were changed, as reported by a tool.
+    AlphaTiles {
+        layer: usize,
+        changes: TileChanges<f32>,
+    },
+    /// Tiles of a shading layer were changed, as reported by a tool.
+    ShadingTiles {
+        layer: usize,
+        changes: TileChanges<i32>,
+    },
+    /// The palette of a base colour layer was replaced. Holds the palette from the other side of the edit.
+    Palette { layer: usize, palette: Palette },
+    /// A layer was inserted at `index`.
+    LayerInserted { index: usize },
+    /// `layer` was removed from `index`.
+    LayerRemoved { index: usize, layer: LayerV2 },
+    /// The layer at `from` was moved to `to`.
+    LayerMoved { from: usize, to: usize },
+    /// A layer was shown, hidden, or had its opacity changed. Holds the visibility and opacity from the other side of
+    /// the edit.
+    LayerAppearance {
+        layer: usize,
+        visible: bool,
+        opacity: f32,
+    },
+    /// The project was resized. Holds the size and every layer's canvas from the other side of the edit.
+    Resized {
+        size: CanvasSize,
+        canvases: Vec<LayerV1Canvas>,
+    },
+    /// The active selection changed. Holds the selection from the other side of the edit.
+    Selection { selection: Option<Selection> },
+}
+
+/// Tile and palette edits are only recorded against layers of the kind they change, and layers never change kind, so
+/// reverting one against any other kind of layer means the history no longer matches the project. That's a bug, so it
+/// panics in debug builds; release builds log it and leave the layer alone rather than corrupting it.
+fn wrong_layer_kind(edit: &str, layer: usize) {
+    error!("Can't revert a {edit} edit on layer {layer}, which is the wrong kind of layer!");
+    debug_assert!(false, "{edit} edit recorded against layer {layer} of the wrong kind");
+}
+
+impl Edit {
+    /// Swaps the project to the other side of this edit, and turns the edit into its inverse.
+    fn revert(self, project: &mut ProjectV2) -> Edit {
+        match self {
+            Edit::Tiles { layer, changes } => {
+                if let LayerV1Canvas::BaseColor { canvas, .. } = &project.layers[layer].canvas {
+                    changes.write_old(&mut canvas.write());
+                } else {
+                    wrong_layer_kind("tile", layer);
+                }
+                Edit::Tiles {
+                    layer,
+                    changes: changes.inverted(),
+                }
+            }
+            Edit::AlphaTiles { layer, changes } => {
+                if let LayerV1Canvas::Alpha(alpha) = &mut project.layers[layer].canvas {
+                    changes.write_old(alpha);
+                } else {
+                    wrong_layer_kind("alpha", layer);
+                }
+                Edit::AlphaTiles {
+                    layer,
+                    changes: changes.inverted(),
+                }
+            }
+            Edit::ShadingTiles { layer, changes } => {
+                if let LayerV1Canvas::Shading(shading) = &mut project.layers[layer].canvas {
+                    changes.write_old(shading);
+                } else {
+                    wrong_layer_kind("shading", layer);
+                }
+                Edit::ShadingTiles {
+                    layer,
+                    changes: changes.inverted(),
+                }
+            }
+            Edit::Palette { layer, mut palette } => {
+                if let LayerV1Canvas::BaseColor {
+                    palette: current, ..
+                } = &project.layers[layer].canvas
+                {
+                    std::mem::swap(&mut *current.write(), &mut palette);
+                } else {
+                    wrong_layer_kind("palette", layer);
+                }
+                Edit::Palette { layer, palette }
+            }
+            Edit::LayerInserted { index } => Edit::LayerRemoved {
+                index,
+                layer: project.layers.remove(index),
+            },
+            Edit::LayerRemoved { index, layer } => {
+                project.layers.insert(index, layer);
+                Edit::LayerInserted { index }
+            }
+            Edit::LayerMoved { from, to } => {
+                let layer = project.layers.remove(to);
+                project.layers.insert(from, layer);
+                Edit::LayerMoved { from: to, to: from }
+            }
+            Edit::LayerAppearance {
+                layer,
+                mut visible,
+                mut opacity,
+            } => {
+                let current = &mut project.layers[layer];
+                std::mem::swap(&mut current.visible, &mut visible);
+                std::mem::swap(&mut current.opacity, &mut opacity);
+                Edit::LayerAppearance {
+                    layer,
+                    visible,
+                    opacity,
+                }
+            }
+            Edit::Resized {
+                mut size,
+                mut canvases,
+            } => {
+                std::mem::swap(&mut project.size, &mut size);
+                for (layer, canvas) in project.layers.iter_mut().zip(canvases.iter_mut()) {
+                    layer.size = project.size;
+                    std::mem::swap(&mut layer.canvas, canvas);
+                }
+                Edit::Resized { size, canvases }
+            }
+            Edit::Selection { mut selection } => {
+                std::mem::swap(&mut project.selection, &mut selection);
+                Edit::Selection { selection }
+            }
+        }
+    }
+
+    /// A rough estimate of how much memory this edit keeps alive.
+    fn memory_usage(&self) -> usize {
+        use std::mem::size_of;
+        size_of::<Edit>()
+            + match self {
+                Edit::Tiles { changes, .. } => {
+                    changes.len() * size_of::<crate::tools::TileChange>()
+                }
+                Edit::AlphaTiles { changes, .. } => {
+                    changes.len() * size_of::<crate::tools::TileChange<f32>>()
+                }
+                Edit::ShadingTiles { changes, .. } => {
+                    changes.len() * size_of::<crate::tools::TileChange<i32>>()
+                }
+                Edit::Palette { palette, .. } => palette.len() * size_of::<super::Color>(),
+                Edit::LayerInserted { .. }
+                | Edit::LayerMoved { .. }
+                | Edit::LayerAppearance { .. } => 0,
+                Edit::LayerRemoved { layer, .. } => layer.canvas.memory_usage(),
+                Edit::Resized { canvases, .. } => {
+                    canvases.iter().map(LayerV1Canvas::memory_usage).sum()
+                }
+                Edit::Selection { selection } => {
+                    selection.as_ref().map_or(0, Selection::memory_usage)
+                }
+            }
+    }
+}
+
+/// A group of edits that are undone and redone together, such as every tile touched by one brush stroke.
+#[derive(Debug, Default)]
+struct Entry {
+    edits: Vec<Edit>,
+    memory_usage: usize,
+}
+
+impl Entry {
+    fn push(&mut self, edit: Edit) {
+        self.memory_usage += edit.memory_usage();
+        self.edits.push(edit);
+    }
+
+    /// Reverts every edit in the entry, last first. The result reverts them back, also last first.
+    fn revert(self, project: &mut ProjectV2) -> Entry {
+        let mut inverse = Entry::default();
+        for edit in self.edits.into_iter().rev() {
+            inverse.push(edit.revert(project));
+        }
+        inverse
+    }
+}
+
+/// The undo and redo stacks of a project.
+#[derive(Debug)]
+pub struct EditHistory {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    /// The group currently being recorded, if any.
+    group: Option<Entry>,
+    /// How many nested `begin_group` calls are open.
+    group_depth: usize,
+    memory_cap: usize,
+    memory_usage: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::with_memory_cap(DEFAULT_MEMORY_CAP)
+    }
+}
+
+impl EditHistory {
+    /// Makes an empty history that will forget its oldest entries once it uses more than `memory_cap` bytes.
+    pub fn with_memory_cap(memory_cap: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            group: None,
+            group_depth: 0,
+            memory_cap,
+            memory_usage: 0,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn memory_cap(&self) -> usize {
+        self.memory_cap
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    /// Changes the memory cap, immediately forgetting the oldest entries if the history is now over it.
+    pub fn set_memory_cap(&mut self, memory_cap: usize) {
+        self.memory_cap = memory_cap;
+        self.enforce_cap();
+    }
+
+    /// Forgets everything.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.group = None;
+        self.group_depth = 0;
+        self.memory_usage = 0;
+    }
+
+    /// Records an edit that has already been applied to the project. This branches history, so the redo stack is
+    /// discarded.
+    fn record(&mut self, edit: Edit) {
+        for entry in self.redo.drain(..) {
+            self.memory_usage -= entry.memory_usage;
+        }
+        let memory_usage = edit.memory_usage();
+        self.memory_usage += memory_usage;
+        match &mut self.group {
+            Some(group) => group.push(edit),
+            None => {
+                let mut entry = Entry::default();
+                entry.push(edit);
+                self.undo.push_back(entry);
+            }
+        }
+        self.enforce_cap();
+    }
+
+    fn begin_group(&mut self) {
+        self.group_depth += 1;
+        if self.group.is_none() {
+            self.group = Some(Entry::default());
+        }
+    }
+
+    fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth == 0 {
+            if let Some(group) = self.group.take() {
+                if group.edits.is_empty() {
+                    return;
+                }
+                self.undo.push_back(group);
+                self.enforce_cap();
+            }
+        }
+    }
+
+    /// Forgets the oldest undo entries, and then the furthest redo entries, until the history fits in its cap. The
+    /// open group is never forgotten.
+    fn enforce_cap(&mut self) {
+        while self.memory_usage > self.memory_cap {
+            let forgotten = match self.undo.pop_front() {
+                Some(entry) => entry,
+                None if !self.redo.is_empty() => self.redo.remove(0),
+                None => break,
+            };
+            trace!("Forgetting {} bytes of history", forgotten.memory_usage);
+            self.memory_usage -= forgotten.memory_usage;
+        }
+    }
+}
+
+impl LayerV1Canvas {
+    /// A rough estimate of how much memory this canvas keeps alive.
+    pub(crate) fn memory_usage(&self) -> usize {
+        use std::mem::size_of;
+        match self {
+            LayerV1Canvas::Alpha(alpha) => alpha.len() * size_of::<f32>(),
+            LayerV1Canvas::BaseColor { palette, canvas } => {
+                palette.read().len() * size_of::<super::Color>()
+                    + canvas.read().len() * size_of::<u32>()
+            }
+            LayerV1Canvas::Shading(shading) => shading.len() * size_of::<i32>(),
+        }
+    }
+
+    /// A copy of this canvas with a new size. Tiles keep their row and column, tiles that no longer fit are dropped,
+    /// and new tiles are fully opaque, the background colour, or unshaded.
+    pub(crate) fn resized(&self, old: CanvasSize, new: CanvasSize) -> LayerV1Canvas {
+        fn resize<T: Copy>(old_tiles: &[T], old: CanvasSize, new: CanvasSize, fill: T) -> Vec<T> {
+            let mut tiles = Vec::with_capacity(new.area() as usize);
+            for row in 0..new.height {
+                for col in 0..new.width {
+                    tiles.push(if row < old.height && col < old.width {
+                        old_tiles[(row * old.width + col) as usize]
+                    } else {
+                        fill
+                    });
+                }
+            }
+            tiles
+        }
+        match self {
+            LayerV1Canvas::Alpha(alpha) => LayerV1Canvas::Alpha(resize(alpha, old, new, 1.0)),
+            LayerV1Canvas::BaseColor { palette, canvas } => LayerV1Canvas::BaseColor {
+                palette: parking_lot::RwLock::new(palette.read().clone()),
+                canvas: parking_lot::RwLock::new(resize(
+                    &canvas.read(),
+                    old,
+                    new,
+                    crate::tools::BACKGROUND_INDEX,
+                )),
+            },
+            LayerV1Canvas::Shading(shading) => LayerV1Canvas::Shading(resize(shading, old, new, 0)),
+        }
+    }
+}
+
+impl<T: Copy> TileChanges<T> {
+    /// Puts every changed tile back to its old value.
+    fn write_old(&self, values: &mut [T]) {
+        for change in self.iter() {
+            values[change.index] = change.old;
+        }
+    }
+
+    /// The changes that undo these changes.
+    fn inverted(self) -> TileChanges<T> {
+        let mut inverted = TileChanges::new();
+        for change in self {
+            inverted.push(crate::tools::TileChange {
+                index: change.index,
+                old: change.new,
+                new: change.old,
+            });
+        }
+        inverted
+    }
+}
+
+impl ProjectV2 {
+    pub fn history(&self) -> &EditHistory {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut EditHistory {
+        &mut self.history
+    }
+
+    /// Undoes the most recent entry in the history. Returns `false` if there was nothing to undo.
+    #[instrument(skip(self))]
+    pub fn undo(&mut self) -> bool {
+        self.end_all_groups();
+        let Some(entry) = self.history.undo.pop_back() else {
+            return false;
+        };
+        self.history.memory_usage -= entry.memory_usage;
+        let inverse = entry.revert(self);
+        self.history.memory_usage += inverse.memory_usage;
+        self.history.redo.push(inverse);
+        self.history.enforce_cap();
+        true
+    }
+
+    /// Redoes the most recently undone entry. Returns `false` if there was nothing to redo.
+    #[instrument(skip(self))]
+    pub fn redo(&mut self) -> bool {
+        self.end_all_groups();
+        let Some(entry) = self.history.redo.pop() else {
+            return false;
+        };
+        self.history.memory_usage -= entry.memory_usage;
+        let inverse = entry.revert(self);
+        self.history.memory_usage += inverse.memory_usage;
+        self.history.undo.push_back(inverse);
+        self.history.enforce_cap();
+        true
+    }
+
+    /// Starts grouping edits, so that everything recorded until the matching `end_edit_group` is undone as one entry.
+    /// Groups may be nested, in which case only the outermost group counts.
+    pub fn begin_edit_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    pub fn end_edit_group(&mut self) {
+        self.history.end_group();
+    }
+
+    fn end_all_groups(&mut self) {
+        while self.history.group_depth > 0 {
+            self.history.end_group();
+        }
+    }
+
+    /// Looks up a layer, reporting the index if it doesn't exist.
+    fn layer_checked(&self, index: usize) -> Result<&LayerV2, EditError> {
+        self.layers.get(index).ok_or(EditError::NoSuchLayer(index))
+    }
+
+    /// Records tile changes that a tool has already made to layer `layer`.
+    pub fn record_tile_changes(
+        &mut self,
+        layer: usize,
+        changes: TileChanges,
+    ) -> Result<(), EditError> {
+        if !matches!(
+            self.layer_checked(layer)?.canvas,
+            LayerV1Canvas::BaseColor { .. }
+        ) {
+            return Err(EditError::NotBaseColor(layer));
+        }
+        if !changes.is_empty() {
+            self.history.record(Edit::Tiles { layer, changes });
+        }
+        Ok(())
+    }
+
+    /// Records tile changes that a tool has already made to alpha layer `layer`.
+    pub fn record_alpha_changes(
+        &mut self,
+        layer: usize,
+        changes: TileChanges<f32>,
+    ) -> Result<(), EditError> {
+        if !matches!(self.layer_checked(layer)?.canvas, LayerV1Canvas::Alpha(_)) {
+            return Err(EditError::NotAlpha(layer));
+        }
+        if !changes.is_empty() {
+            self.history.record(Edit::AlphaTiles { layer, changes });
+        }
+        Ok(())
+    }
+
+    /// Records tile changes that a tool has already made to shading layer `layer`.
+    pub fn record_shading_changes(
+        &mut self,
+        layer: usize,
+        changes: TileChanges<i32>,
+    ) -> Result<(), EditError> {
+        if !matches!(self.layer_checked(layer)?.canvas, LayerV1Canvas::Shading(_)) {
+            return Err(EditError::NotShading(layer));
+        }
+        if !changes.is_empty() {
+            self.history.record(Edit::ShadingTiles { layer, changes });
+        }
+        Ok(())
+    }
+
+    /// Replaces the palette of base colour layer `layer`. Tiles keep their indices.
+    pub fn set_palette(&mut self, layer: usize, palette: Palette) -> Result<(), EditError> {
+        let LayerV1Canvas::BaseColor {
+            palette: current, ..
+        } = &self.layer_checked(layer)?.canvas
+        else {
+            return Err(EditError::NotBaseColor(layer));
+        };
+        let old = std::mem::replace(&mut *current.write(), palette);
+        self.history.record(Edit::Palette {
+            layer,
+            palette: old,
+        });
+        Ok(())
+    }
+
+    /// Inserts `layer` at `index`, shifting the layers above it up. An `index` past the top of the stack inserts at the
+    /// top.
+    pub fn insert_layer(&mut self, index: usize, layer: LayerV2) -> Result<(), EditError> {
+        if layer.size.width != self.size.width || layer.size.height != self.size.height {
+            return Err(EditError::LayerSizeMismatch(
+                layer.size.width,
+                layer.size.height,
+                self.size.width,
+                self.size.height,
+            ));
+        }
+        let index = index.min(self.layers.len());
+        self.layers.insert(index, layer);
+        self.history.record(Edit::LayerInserted { index });
+        Ok(())
+    }
+
+    /// Removes and records the layer at `index`.
+    pub fn remove_layer(&mut self, index: usize) -> Result<(), EditError> {
+        self.layer_checked(index)?;
+        let layer = self.layers.remove(index);
+        self.history.record(Edit::LayerRemoved { index, layer });
+        Ok(())
+    }
+
+    /// Moves the layer at `from` so that it ends up at `to`.
+    pub fn move_layer(&mut self, from: usize, to: usize) -> Result<(), EditError> {
+        self.layer_checked(from)?;
+        self.layer_checked(to)?;
+        if from != to {
+            let layer = self.layers.remove(from);
+            self.layers.insert(to, layer);
+            self.history.record(Edit::LayerMoved { from, to });
+        }
+        Ok(())
+    }
+
+    /// Shows or hides layer `layer`.
+    pub fn set_layer_visible(&mut self, layer: usize, visible: bool) -> Result<(), EditError> {
+        let opacity = self.layer_checked(layer)?.opacity;
+        self.set_layer_appearance(layer, visible, opacity);
+        Ok(())
+    }
+
+    /// Sets the opacity of layer `layer`, clamped to `[0, 1]`.
+    pub fn set_layer_opacity(&mut self, layer: usize, opacity: f32) -> Result<(), EditError> {
+        let visible = self.layer_checked(layer)?.visible;
+        self.set_layer_appearance(layer, visible, super::clamp_opacity(opacity));
+        Ok(())
+    }
+
+    /// Changes the appearance of a layer that is known to exist, recording it only if anything actually changed.
+    fn set_layer_appearance(&mut self, layer: usize, visible: bool, opacity: f32) {
+        let current = &mut self.layers[layer];
+        if current.visible == visible && current.opacity == opacity {
+            return;
+        }
+        let old_visible = std::mem::replace(&mut current.visible, visible);
+        let old_opacity = std::mem::replace(&mut current.opacity, opacity);
+        self.history.record(Edit::LayerAppearance {
+            layer,
+            visible: old_visible,
+            opacity: old_opacity,
+        });
+    }
+
+    /// Resizes the project and every layer, keeping tiles anchored to the top left. The selection is cleared, as it no
+    /// longer fits.
+    pub fn resize(&mut self, size: CanvasSize) -> Result<(), EditError> {
+        if size
+            .width
+            .checked_mul(size.height)
+            .and_then(|area| usize::try_from(area).ok())
+            .is_none()
+        {
+            return Err(EditError::CanvasTooLarge(size.width, size.height));
+        }
+        self.begin_edit_group();
+        self.set_selection(None)?;
+        let old = self.size;
+        let mut canvases = Vec::with_capacity(self.layers.len());
+        for layer in self.layers.iter_mut() {
+            let resized = layer.canvas.resized(old, size);
+            canvases.push(std::mem::replace(&mut layer.canvas, resized));
+            layer.size = size;
+        }
+        self.size = size;
+        self.history.record(Edit::Resized {
+            size: old,
+            canvases,
+        });
+        self.end_edit_group();
+        Ok(())
+    }
+
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+
+    /// Makes `selection` the active selection, or removes the active selection if it's `None`. An empty selection stays
+    /// active, so no tile can be edited until it's cleared.
+    pub fn set_selection(&mut self, selection: Option<Selection>) -> Result<(), EditError> {
+        if let Some(selection) = &selection {
+            if selection.size() != self.size || selection.gridtype() != self.gridtype {
+                return Err(EditError::SelectionMismatch(
+                    selection.size().width,
+                    selection.size().height,
+                    selection.gridtype(),
+                    self.size.width,
+                    self.size.height,
+                    self.gridtype,
+                ));
+            }
+        }
+        if selection == self.selection {
+            return Ok(());
+        }
+        let old = std::mem::replace(&mut self.selection, selection);
+        self.history.record(Edit::Selection { selection: old });
+        Ok(())
+    }
+}
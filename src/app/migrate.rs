@@ -0,0 +1,93 @@
+//! Opening project files of any version, and upgrading them to the current `Project`.
+//!
+//! Adding a new project version `n` takes three steps:
+//! 1. Add a `Vn(ProjectVn)` variant to `VersionedProject`, and a matching arm in `VersionedProject::decode`.
+//! 2. Implement `From<ProjectVn-1> for ProjectVn`, and make the `Vn-1` arm of `VersionedProject::upgrade_step` use it.
+//! 3. Save a fixture of the new version to `fixtures/projects/vn.hexil`. Fixtures must never be regenerated.
+use std::io::Read;
+use std::path::Path;
+
+use tracing::{info, instrument};
+
+use super::project_file::{decode_payload, read_file, ProjectFileError};
+use super::{Project, ProjectV1, ProjectV2};
+
+/// A project of any version Hexil has ever saved.
+#[derive(Debug)]
+pub enum VersionedProject {
+    V1(ProjectV1),
+    V2(ProjectV2),
+}
+
+impl VersionedProject {
+    /// The format version of the contained project.
+    pub fn version(&self) -> u32 {
+        match self {
+            VersionedProject::V1(_) => ProjectV1::FORMAT_VERSION,
+            VersionedProject::V2(_) => ProjectV2::FORMAT_VERSION,
+        }
+    }
+
+    /// Loads a project file of any known version from `path`, without upgrading it.
+    #[instrument(err)]
+    pub fn load(path: impl AsRef<Path> + std::fmt::Debug) -> Result<Self, ProjectFileError> {
+        let file = std::fs::File::open(path)?;
+        Self::read_from(std::io::BufReader::new(file))
+    }
+
+    /// Reads a project file of any known version, without upgrading it.
+    pub fn read_from(reader: impl Read) -> Result<Self, ProjectFileError> {
+        let (header, payload) = read_file(reader)?;
+        Self::decode(header.version, &payload)
+    }
+
+    /// Decodes a verified payload according to the version from its header.
+    fn decode(version: u32, payload: &[u8]) -> Result<Self, ProjectFileError> {
+        match version {
+            ProjectV1::FORMAT_VERSION => {
+                let project: ProjectV1 = decode_payload(payload)?;
+                project.check_consistency()?;
+                Ok(VersionedProject::V1(project))
+            }
+            ProjectV2::FORMAT_VERSION => {
+                let project: ProjectV2 = decode_payload(payload)?;
+                project.check_consistency()?;
+                Ok(VersionedProject::V2(project))
+            }
+            _ => Err(ProjectFileError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Upgrades the project by exactly one version. If it is already the current version, it is returned as `Err`.
+    pub fn upgrade_step(self) -> Result<Self, Box<Project>> {
+        match self {
+            VersionedProject::V1(project) => Ok(VersionedProject::V2(project.into())),
+            VersionedProject::V2(project) => Err(Box::new(project)),
+        }
+    }
+
+    /// Upgrades the project through every intermediate version until it is the current version.
+    pub fn upgrade(self) -> Project {
+        let mut project = self;
+        loop {
+            let from = project.version();
+            match project.upgrade_step() {
+                Ok(next) => {
+                    info!(
+                        "Upgraded project from version {} to {}",
+                        from,
+                        next.version()
+                    );
+                    project = next;
+                }
+                Err(current) => return *current,
+            }
+        }
+    }
+}
+
+/// Opens a project file of any known version, upgrading it to the current `Project`.
+#[instrument(err)]
+pub fn open_project(path: impl AsRef<Path> + std::fmt::Debug) -> Result<Project, ProjectFileError> {
+    Ok(VersionedProject::load(path)?.upgrade())
+}
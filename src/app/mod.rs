@@ -5,7 +5,7 @@ use vk::buffer as vbuf;
 use vulkano as vk;
 
 /// The current latest project type.
-pub type Project = ProjectV1;
+pub type Project = ProjectV2;
 
 /// Contains the state of a single instance of Hexil. It probably doesn't make sense to ever have more than one of these.
 pub struct AppInstance {
@@ -26,7 +26,18 @@ pub enum GridType {
     Hexagonal,
 }
 
-#[derive(Debug, Hash, Copy, Clone, bytemuck::Zeroable, bytemuck::Pod, Serialize, Deserialize)]
+#[derive(
+    Debug,
+    Hash,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    bytemuck::Zeroable,
+    bytemuck::Pod,
+    Serialize,
+    Deserialize,
+)]
 #[repr(C)]
 pub struct CanvasSize {
     pub width: u64,
@@ -41,6 +52,7 @@ impl CanvasSize {
 }
 
 /// Contains the state of a single open project. Starting with 1.0, Project types must never be removed, so that legacy projects can be opened and converted by any future Hexil.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ProjectV1 {
     /// The name of the project. Not necessarily the filename.
     name: String,
@@ -51,10 +63,38 @@ pub struct ProjectV1 {
     /// Which grid type the project uses
     gridtype: GridType,
 }
-pub(crate) type Color = palette::Oklab;
-pub(crate) type Palette = Vec<Color>;
 
-pub(crate) type CanvasIndices = Vec<u32>;
+/// Like `ProjectV1`, but every layer can be hidden or partially transparent.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectV2 {
+    /// The name of the project. Not necessarily the filename.
+    name: String,
+    /// The size of the canvas in grid tiles
+    size: CanvasSize,
+    /// The layers of the project, from the bottom of the stack to the top
+    layers: Vec<LayerV2>,
+    /// Which grid type the project uses
+    gridtype: GridType,
+    /// Undo and redo. Never saved.
+    #[serde(skip)]
+    history: history::EditHistory,
+    /// The tiles edits are limited to, or `None` if every tile can be edited. An empty selection stays active and
+    /// keeps every tile from being edited. Never saved.
+    #[serde(skip)]
+    selection: Option<Selection>,
+}
+pub type Color = palette::Oklab;
+pub type Palette = Vec<Color>;
+
+pub type CanvasIndices = Vec<u32>;
+
+/// The kinds of layer, without their canvases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayerKind {
+    Alpha,
+    BaseColor,
+    Shading,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 /// It is fundamentally impossible to deserialize a data structure involving subbuffers with serde. This type exists as a quick and easy go between, so that we can still save projects anyway.
@@ -67,8 +107,30 @@ pub enum LayerV1Canvas {
     Shading(Vec<i32>),
 }
 
+pub mod clipboard;
+pub use clipboard::{FloatingSelection, FloatingValues};
+pub mod color_picker;
+pub use color_picker::ColorPicker;
+pub mod composite;
+pub use composite::{TileColor, SHADING_STEP};
+pub mod history;
+pub use history::{Edit, EditError, EditHistory};
+pub mod migrate;
+pub use migrate::{open_project, VersionedProject};
+pub mod palette_edit;
+pub use palette_edit::PaletteSortKey;
+pub mod palette_io;
+pub use palette_io::{PaletteFile, PaletteFormat, PaletteIoError};
+pub mod project_file;
+pub mod selection;
+pub use selection::{Selection, SelectionError, SelectionMode};
+pub mod validate;
+pub use project_file::ProjectFileError;
+pub use validate::ValidationIssue;
 pub mod transfer_canvas_to_device;
+pub use transfer_canvas_to_device::{CanvasSnapshot, LayerSnapshot};
 /// A layer for a `ProjectV1`
+#[derive(Debug, Serialize, Deserialize)]
 pub struct LayerV1 {
     /// An optional user-defined name for the layer
     name: Option<String>,
@@ -77,3 +139,167 @@ pub struct LayerV1 {
     /// The associated canvas
     canvas: LayerV1Canvas,
 }
+
+/// A layer for a `ProjectV2`. The canvas is unchanged from `LayerV1`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LayerV2 {
+    /// An optional user-defined name for the layer
+    name: Option<String>,
+    /// Size of the canvas. Currently must be identical to project canvas size.
+    size: CanvasSize,
+    /// The associated canvas
+    canvas: LayerV1Canvas,
+    /// Hidden layers are kept, but have no effect on the composited canvas.
+    visible: bool,
+    /// How strongly the layer affects the layers below it, from 0 to 1.
+    opacity: f32,
+}
+
+impl From<LayerV1> for LayerV2 {
+    fn from(layer: LayerV1) -> Self {
+        Self {
+            name: layer.name,
+            size: layer.size,
+            canvas: layer.canvas,
+            visible: true,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl From<ProjectV1> for ProjectV2 {
+    fn from(project: ProjectV1) -> Self {
+        Self {
+            name: project.name,
+            size: project.size,
+            layers: project.layers.into_iter().map(LayerV2::from).collect(),
+            gridtype: project.gridtype,
+            history: Default::default(),
+            selection: None,
+        }
+    }
+}
+
+impl ProjectV2 {
+    /// Makes a new project with no layers.
+    pub fn new(name: String, size: CanvasSize, gridtype: GridType) -> Self {
+        Self {
+            name,
+            size,
+            layers: Vec::new(),
+            gridtype,
+            history: Default::default(),
+            selection: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> CanvasSize {
+        self.size
+    }
+
+    pub fn gridtype(&self) -> GridType {
+        self.gridtype
+    }
+
+    pub fn layers(&self) -> &[LayerV2] {
+        &self.layers
+    }
+
+    /// Mutable access to a layer. Changes made through this aren't recorded in the project's history.
+    pub fn layer_mut(&mut self, index: usize) -> Option<&mut LayerV2> {
+        self.layers.get_mut(index)
+    }
+
+    /// Appends a layer to the top of the layer stack.
+    pub fn push_layer(&mut self, layer: LayerV2) {
+        self.layers.push(layer);
+    }
+}
+
+impl LayerV2 {
+    /// Makes a new layer, visible and fully opaque.
+    pub fn new(name: Option<String>, size: CanvasSize, canvas: LayerV1Canvas) -> Self {
+        Self {
+            name,
+            size,
+            canvas,
+            visible: true,
+            opacity: 1.0,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn size(&self) -> CanvasSize {
+        self.size
+    }
+
+    pub fn canvas(&self) -> &LayerV1Canvas {
+        &self.canvas
+    }
+
+    /// Mutable access to the canvas. Changes made through this aren't recorded in the project's history.
+    pub fn canvas_mut(&mut self) -> &mut LayerV1Canvas {
+        &mut self.canvas
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    /// The layer, shown or hidden. For building layers before they're added to a project; use
+    /// `ProjectV2::set_layer_visible` afterwards, so the change can be undone.
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// The layer with its opacity clamped to `[0, 1]`. For building layers before they're added to a project; use
+    /// `ProjectV2::set_layer_opacity` afterwards, so the change can be undone.
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = clamp_opacity(opacity);
+        self
+    }
+}
+
+/// Clamps an opacity to `[0, 1]`, treating NaN as fully transparent.
+pub(crate) fn clamp_opacity(opacity: f32) -> f32 {
+    if opacity.is_nan() {
+        0.0
+    } else {
+        opacity.clamp(0.0, 1.0)
+    }
+}
+
+impl LayerV1Canvas {
+    /// The number of tiles stored in this canvas.
+    pub fn len(&self) -> usize {
+        match self {
+            LayerV1Canvas::Alpha(alpha) => alpha.len(),
+            LayerV1Canvas::BaseColor { canvas, .. } => canvas.read().len(),
+            LayerV1Canvas::Shading(shading) => shading.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn kind(&self) -> LayerKind {
+        match self {
+            LayerV1Canvas::Alpha(_) => LayerKind::Alpha,
+            LayerV1Canvas::BaseColor { .. } => LayerKind::BaseColor,
+            LayerV1Canvas::Shading(_) => LayerKind::Shading,
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Palette edits that keep the artwork looking the same.
+//!
+//! Palettes are stored per layer, so two base colour layers share a palette when their palettes are identical, as
+//! they are after copying a palette from one layer to another. Every edit here applies to all layers sharing the
+//! edited palette, rewriting their tiles so that each tile keeps its colour, or takes the colour chosen to replace
+//! it. Each edit is recorded as a single entry in the project's history. Tiles whose indices are already out of range
+//! for the palette are left alone.
+use palette::{FromColor, Oklch};
+use tracing::instrument;
+
+use super::{Color, EditError, LayerV1Canvas, Palette, ProjectV2};
+use crate::tools::{TileChange, TileChanges};
+
+/// What `ProjectV2::sort_palette` orders colours by. Every key sorts in ascending order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PaletteSortKey {
+    Lightness,
+    Chroma,
+    /// The Oklch hue angle, starting from pinkish red. Greys have no real hue, and sort as if it were 0.
+    Hue,
+}
+
+impl PaletteSortKey {
+    fn key(self, color: Color) -> f32 {
+        match self {
+            PaletteSortKey::Lightness => color.l,
+            PaletteSortKey::Chroma => Oklch::from_color(color).chroma,
+            PaletteSortKey::Hue => Oklch::from_color(color).hue.into_positive_degrees(),
+        }
+    }
+}
+
+/// The distance between two colours in Oklab, which is its ΔE.
+pub fn delta_e(a: Color, b: Color) -> f32 {
+    ((a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)).sqrt()
+}
+
+/// The index of the entry of `palette` nearest to `color`, or `None` if the palette is empty.
+pub fn nearest_palette_index(palette: &[Color], color: Color) -> Option<u32> {
+    palette
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| delta_e(color, **a).total_cmp(&delta_e(color, **b)))
+        .map(|(index, _)| index as u32)
+}
+
+impl ProjectV2 {
+    /// The base colour layers sharing the palette of base colour layer `layer`, including `layer` itself, from the
+    /// bottom of the stack to the top.
+    pub fn layers_sharing_palette(&self, layer: usize) -> Result<Vec<usize>, EditError> {
+        let palette = self.palette_of(layer)?;
+        Ok(self
+            .layers
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| match &other.canvas {
+                LayerV1Canvas::BaseColor { palette: other, .. } => *other.read() == palette,
+                _ => false,
+            })
+            .map(|(index, _)| index)
+            .collect())
+    }
+
+    /// Inserts `color` into the palette of `layer` at `index`, or at the end if `index` is past it. Tiles using the
+    /// colours after it are shifted along with them.
+    #[instrument(skip(self))]
+    pub fn insert_palette_color(
+        &mut self,
+        layer: usize,
+        index: u32,
+        color: Color,
+    ) -> Result<(), EditError> {
+        let mut palette = self.palette_of(layer)?;
+        let index = index.min(palette.len() as u32);
+        palette.insert(index as usize, color);
+        let remap: Vec<u32> = (0..palette.len() as u32 - 1)
+            .map(|old| if old < index { old } else { old + 1 })
+            .collect();
+        self.remap_palette(layer, palette, &remap)
+    }
+
+    /// Deletes colour `index` from the palette of `layer`. Tiles using it are repainted with colour `replacement`,
+    /// which is an index into the palette before the deletion.
+    #[instrument(skip(self))]
+    pub fn delete_palette_color(
+        &mut self,
+        layer: usize,
+        index: u32,
+        replacement: u32,
+    ) -> Result<(), EditError> {
+        let mut palette = self.palette_of(layer)?;
+        let len = palette.len();
+        for index in [index, replacement] {
+            if index as usize >= len {
+                return Err(EditError::PaletteIndexOutOfRange { index, len });
+            }
+        }
+        if index == replacement {
+            return Err(EditError::ReplacedWithItself(index));
+        }
+        palette.remove(index as usize);
+        let shifted = |old: u32| if old > index { old - 1 } else { old };
+        let remap: Vec<u32> = (0..len as u32)
+            .map(|old| shifted(if old == index { replacement } else { old }))
+            .collect();
+        self.remap_palette(layer, palette, &remap)
+    }
+
+    /// Reorders the palette of `layer` so that its colour `i` is the old colour `order[i]`. `order` must contain every
+    /// index of the palette exactly once.
+    #[instrument(skip(self))]
+    pub fn reorder_palette(&mut self, layer: usize, order: &[u32]) -> Result<(), EditError> {
+        let old = self.palette_of(layer)?;
+        let mut remap = vec![u32::MAX; old.len()];
+        if order.len() != old.len() {
+            return Err(EditError::NotAReordering(old.len()));
+        }
+        for (new, &old_index) in order.iter().enumerate() {
+            match remap.get_mut(old_index as usize) {
+                Some(slot) if *slot == u32::MAX => *slot = new as u32,
+                _ => return Err(EditError::NotAReordering(old.len())),
+            }
+        }
+        if remap
+            .iter()
+            .enumerate()
+            .all(|(old, &new)| old as u32 == new)
+        {
+            return Ok(());
+        }
+        let palette = order.iter().map(|&index| old[index as usize]).collect();
+        self.remap_palette(layer, palette, &remap)
+    }
+
+    /// Merges every colour of the palette of `layer` that is within `threshold` ΔE of an earlier colour into the
+    /// earliest such colour, and removes it. Returns how many colours were removed.
+    #[instrument(skip(self))]
+    pub fn merge_similar_palette_colors(
+        &mut self,
+        layer: usize,
+        threshold: f32,
+    ) -> Result<usize, EditError> {
+        let old = self.palette_of(layer)?;
+        let mut palette = Palette::new();
+        let mut remap = Vec::with_capacity(old.len());
+        for &color in &old {
+            match palette
+                .iter()
+                .position(|&kept| delta_e(color, kept) < threshold)
+            {
+                Some(kept) => remap.push(kept as u32),
+                None => {
+                    remap.push(palette.len() as u32);
+                    palette.push(color);
+                }
+            }
+        }
+        let merged = old.len() - palette.len();
+        if merged > 0 {
+            self.remap_palette(layer, palette, &remap)?;
+        }
+        Ok(merged)
+    }
+
+    /// Sorts the palette of `layer` by `key`. The first colour is the background, so it stays where it is. Colours
+    /// with equal keys keep their order.
+    #[instrument(skip(self))]
+    pub fn sort_palette(&mut self, layer: usize, key: PaletteSortKey) -> Result<(), EditError> {
+        let palette = self.palette_of(layer)?;
+        let mut order: Vec<u32> = (0..palette.len() as u32).collect();
+        if let Some((_background, rest)) = order.split_first_mut() {
+            rest.sort_by(|a, b| {
+                key.key(palette[*a as usize])
+                    .total_cmp(&key.key(palette[*b as usize]))
+            });
+        }
+        self.reorder_palette(layer, &order)
+    }
+
+    /// A copy of the palette of `layer`.
+    fn palette_of(&self, layer: usize) -> Result<Palette, EditError> {
+        match &self
+            .layers
+            .get(layer)
+            .ok_or(EditError::NoSuchLayer(layer))?
+            .canvas
+        {
+            LayerV1Canvas::BaseColor { palette, .. } => Ok(palette.read().clone()),
+            _ => Err(EditError::NotBaseColor(layer)),
+        }
+    }
+
+    /// Replaces the palette of `layer` and every layer sharing it with `palette`, and rewrites every tile using old
+    /// colour `i` to use `remap[i]` instead, all as one entry in the history.
+    fn remap_palette(
+        &mut self,
+        layer: usize,
+        palette: Palette,
+        remap: &[u32],
+    ) -> Result<(), EditError> {
+        let sharing = self.layers_sharing_palette(layer)?;
+        self.begin_edit_group();
+        let result = sharing.into_iter().try_for_each(|layer| {
+            self.set_palette(layer, palette.clone())?;
+            let changes = match &self.layers[layer].canvas {
+                LayerV1Canvas::BaseColor { canvas, .. } => remap_tiles(&mut canvas.write(), remap),
+                _ => TileChanges::new(),
+            };
+            self.record_tile_changes(layer, changes)
+        });
+        self.end_edit_group();
+        result
+    }
+}
+
+/// Rewrites every tile of `canvas` using an index in `remap` to `remap[index]`, recording the tiles that changed.
+fn remap_tiles(canvas: &mut [u32], remap: &[u32]) -> TileChanges {
+    let mut changes = TileChanges::new();
+    for (index, tile) in canvas.iter_mut().enumerate() {
+        let Some(&new) = remap.get(*tile as usize) else {
+            continue;
+        };
+        if new != *tile {
+            changes.push(TileChange {
+                index,
+                old: *tile,
+                new,
+            });
+            *tile = new;
+        }
+    }
+    changes
+}
@@ -0,0 +1,541 @@
+//! Reading and writing palettes in the formats other pixel art tools use.
+//!
+//! | Format    | Extension | Palette name                   | Colour names | Precision           |
+//! |-----------|-----------|--------------------------------|--------------|---------------------|
+//! | GIMP      | `.gpl`    | Yes                            | Yes          | 8 bit sRGB          |
+//! | JASC-PAL  | `.pal`    | No                             | No           | 8 bit sRGB          |
+//! | Paint.NET | `.txt`    | As a `; Palette Name:` comment | No           | 8 bit sRGB          |
+//! | Hex list  | `.hex`    | No                             | No           | 8 bit sRGB          |
+//! | Adobe ASE | `.ase`    | As the name of a group         | Yes          | 32 bit float sRGB   |
+//!
+//! Colours outside of the sRGB gamut are clamped on the way out. Apart from that, a palette read back from a file it
+//! was written to matches the original up to the precision of the format, and keeps every name the format has room
+//! for.
+use std::io::{Read, Write};
+use std::path::Path;
+
+use palette::chromatic_adaptation::AdaptInto;
+use palette::white_point::{D50, D65};
+use palette::{Clamp, FromColor, Lab, Srgb, Xyz};
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{Color, Palette};
+use crate::export::{oklab_to_srgb8, parse_hex_srgb8};
+
+/// The palette file formats Hexil can read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PaletteFormat {
+    /// GIMP's `.gpl`.
+    Gpl,
+    /// Paint Shop Pro's `.pal`.
+    JascPal,
+    /// Paint.NET's `.txt`.
+    PaintNet,
+    /// One `rrggbb` colour per line, as Lospec serves them.
+    Hex,
+    /// Adobe Swatch Exchange.
+    Ase,
+}
+
+impl PaletteFormat {
+    pub const ALL: [PaletteFormat; 5] = [
+        PaletteFormat::Gpl,
+        PaletteFormat::JascPal,
+        PaletteFormat::PaintNet,
+        PaletteFormat::Hex,
+        PaletteFormat::Ase,
+    ];
+
+    /// The extension files in this format are saved with, without the dot.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::JascPal => "pal",
+            PaletteFormat::PaintNet => "txt",
+            PaletteFormat::Hex => "hex",
+            PaletteFormat::Ase => "ase",
+        }
+    }
+
+    /// A human readable name for the format.
+    pub const fn name(self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "GIMP palette",
+            PaletteFormat::JascPal => "JASC-PAL palette",
+            PaletteFormat::PaintNet => "Paint.NET palette",
+            PaletteFormat::Hex => "hex colour list",
+            PaletteFormat::Ase => "Adobe swatch exchange file",
+        }
+    }
+
+    /// The format with the given extension, ignoring case.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| extension.eq_ignore_ascii_case(format.extension()))
+    }
+
+    /// The format of the file at `path`, going by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+}
+
+/// Everything that can go wrong while reading or writing a palette file.
+#[derive(Debug, Error)]
+pub enum PaletteIoError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Can't tell what kind of palette {0} is from its extension!")]
+    UnknownFormat(String),
+    #[error("Not a {}!", .0.name())]
+    BadHeader(PaletteFormat),
+    #[error("Line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("Palette file is truncated!")]
+    Truncated,
+    #[error("Colour model {0:?} is not supported!")]
+    UnsupportedColorModel(String),
+}
+
+/// A single colour of a `PaletteFile`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteEntry {
+    pub color: Color,
+    pub name: Option<String>,
+}
+
+/// A palette as palette files hold it, with room for the names Hexil's own palettes don't have.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PaletteFile {
+    pub name: Option<String>,
+    pub entries: Vec<PaletteEntry>,
+}
+
+impl PaletteFile {
+    /// An unnamed palette of unnamed colours.
+    pub fn from_colors(colors: &[Color]) -> Self {
+        Self {
+            name: None,
+            entries: colors
+                .iter()
+                .map(|color| PaletteEntry {
+                    color: *color,
+                    name: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Just the colours, in order.
+    pub fn colors(&self) -> Palette {
+        self.entries.iter().map(|entry| entry.color).collect()
+    }
+
+    pub fn read_from(format: PaletteFormat, mut reader: impl Read) -> Result<Self, PaletteIoError> {
+        if format == PaletteFormat::Ase {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            return read_ase(&bytes);
+        }
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        // Windows tools like to start text files with a byte order mark.
+        let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+        match format {
+            PaletteFormat::Gpl => read_gpl(text),
+            PaletteFormat::JascPal => read_jasc_pal(text),
+            PaletteFormat::PaintNet => read_paint_net(text),
+            PaletteFormat::Hex => read_hex(text),
+            PaletteFormat::Ase => unreachable!(),
+        }
+    }
+
+    pub fn write_to(
+        &self,
+        format: PaletteFormat,
+        mut writer: impl Write,
+    ) -> Result<(), PaletteIoError> {
+        let bytes = match format {
+            PaletteFormat::Gpl => self.gpl().into_bytes(),
+            PaletteFormat::JascPal => self.jasc_pal().into_bytes(),
+            PaletteFormat::PaintNet => self.paint_net().into_bytes(),
+            PaletteFormat::Hex => self.hex().into_bytes(),
+            PaletteFormat::Ase => self.ase(),
+        };
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Reads the palette at `path`, in the format its extension names.
+    #[instrument(err)]
+    pub fn load(path: impl AsRef<Path> + std::fmt::Debug) -> Result<Self, PaletteIoError> {
+        let path = path.as_ref();
+        let format = PaletteFormat::from_path(path)
+            .ok_or_else(|| PaletteIoError::UnknownFormat(path.display().to_string()))?;
+        let file = std::fs::File::open(path)?;
+        Self::read_from(format, std::io::BufReader::new(file))
+    }
+
+    /// Writes the palette to `path`, in the format its extension names.
+    #[instrument(skip(self), err)]
+    pub fn save(&self, path: impl AsRef<Path> + std::fmt::Debug) -> Result<(), PaletteIoError> {
+        let path = path.as_ref();
+        let format = PaletteFormat::from_path(path)
+            .ok_or_else(|| PaletteIoError::UnknownFormat(path.display().to_string()))?;
+        let file = std::fs::File::create(path)?;
+        self.write_to(format, std::io::BufWriter::new(file))
+    }
+
+    fn gpl(&self) -> String {
+        let mut text = String::from("GIMP Palette\n");
+        if let Some(name) = &self.name {
+            text += &format!("Name: {}\n", single_line(name));
+        }
+        text += "#\n";
+        for entry in &self.entries {
+            let [r, g, b] = oklab_to_srgb8(entry.color);
+            let name = entry.name.as_deref().map(single_line).unwrap_or_default();
+            text += &format!("{r:>3} {g:>3} {b:>3}\t{name}\n");
+        }
+        text
+    }
+
+    fn jasc_pal(&self) -> String {
+        let mut text = format!("JASC-PAL\r\n0100\r\n{}\r\n", self.entries.len());
+        for entry in &self.entries {
+            let [r, g, b] = oklab_to_srgb8(entry.color);
+            text += &format!("{r} {g} {b}\r\n");
+        }
+        text
+    }
+
+    fn paint_net(&self) -> String {
+        let mut text = String::from("; paint.net Palette File\r\n");
+        if let Some(name) = &self.name {
+            text += &format!("; Palette Name: {}\r\n", single_line(name));
+        }
+        text += &format!("; Colors: {}\r\n", self.entries.len());
+        for entry in &self.entries {
+            let [r, g, b] = oklab_to_srgb8(entry.color);
+            text += &format!("FF{r:02X}{g:02X}{b:02X}\r\n");
+        }
+        text
+    }
+
+    fn hex(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let [r, g, b] = oklab_to_srgb8(entry.color);
+                format!("{r:02x}{g:02x}{b:02x}\n")
+            })
+            .collect()
+    }
+
+    fn ase(&self) -> Vec<u8> {
+        let mut blocks: Vec<(u16, Vec<u8>)> = Vec::new();
+        if let Some(name) = &self.name {
+            let mut data = Vec::new();
+            push_ase_string(&mut data, name);
+            blocks.push((ASE_GROUP_START, data));
+        }
+        for entry in &self.entries {
+            let mut data = Vec::new();
+            push_ase_string(&mut data, entry.name.as_deref().unwrap_or_default());
+            data.extend_from_slice(b"RGB ");
+            let srgb: Srgb = Srgb::from_color(entry.color).clamp();
+            for channel in [srgb.red, srgb.green, srgb.blue] {
+                data.extend_from_slice(&channel.to_be_bytes());
+            }
+            data.extend_from_slice(&ASE_NORMAL_COLOR.to_be_bytes());
+            blocks.push((ASE_COLOR, data));
+        }
+        if self.name.is_some() {
+            blocks.push((ASE_GROUP_END, Vec::new()));
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(ASE_MAGIC);
+        bytes.extend_from_slice(&ASE_VERSION.0.to_be_bytes());
+        bytes.extend_from_slice(&ASE_VERSION.1.to_be_bytes());
+        bytes.extend_from_slice(&(blocks.len() as u32).to_be_bytes());
+        for (kind, data) in blocks {
+            bytes.extend_from_slice(&kind.to_be_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&data);
+        }
+        bytes
+    }
+}
+
+const ASE_MAGIC: &[u8; 4] = b"ASEF";
+const ASE_VERSION: (u16, u16) = (1, 0);
+const ASE_COLOR: u16 = 0x0001;
+const ASE_GROUP_START: u16 = 0xC001;
+const ASE_GROUP_END: u16 = 0xC002;
+/// The colour type for ordinary colours, as opposed to global or spot colours.
+const ASE_NORMAL_COLOR: u16 = 2;
+
+fn color_from_srgb8([r, g, b]: [u8; 3]) -> Color {
+    Color::from_color(Srgb::new(r, g, b).into_format::<f32>())
+}
+
+/// Names can't span lines in line based formats.
+fn single_line(name: &str) -> String {
+    name.replace(['\r', '\n'], " ")
+}
+
+fn non_empty(name: &str) -> Option<String> {
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+fn syntax_error(line: usize, message: impl Into<String>) -> PaletteIoError {
+    PaletteIoError::Syntax {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Parses three whitespace separated 8 bit channels from the start of `text`, returning them and the rest of `text`.
+fn parse_channels(text: &str, line: usize) -> Result<([u8; 3], &str), PaletteIoError> {
+    let mut rest = text;
+    let mut rgb = [0u8; 3];
+    for channel in &mut rgb {
+        let trimmed = rest.trim_start();
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        let value = &trimmed[..end];
+        *channel = value.parse().map_err(|_| {
+            syntax_error(
+                line,
+                format!("{value:?} is not a colour channel from 0 to 255"),
+            )
+        })?;
+        rest = &trimmed[end..];
+    }
+    Ok((rgb, rest))
+}
+
+/// Parses a colour written as hex digits, like `export::parse_hex_srgb8` does, blaming `line` if it isn't one.
+fn parse_hex_digits(text: &str, line: usize) -> Result<[u8; 3], PaletteIoError> {
+    parse_hex_srgb8(text)
+        .ok_or_else(|| syntax_error(line, format!("{text:?} is not a colour of the form rrggbb")))
+}
+
+fn read_gpl(text: &str) -> Result<PaletteFile, PaletteIoError> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == "GIMP Palette" => {}
+        _ => return Err(PaletteIoError::BadHeader(PaletteFormat::Gpl)),
+    }
+    let mut file = PaletteFile::default();
+    for (i, line) in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("Name:") {
+            file.name = non_empty(name.trim());
+            continue;
+        }
+        let (rgb, name) = parse_channels(line, i + 1)?;
+        file.entries.push(PaletteEntry {
+            color: color_from_srgb8(rgb),
+            name: non_empty(name.trim()),
+        });
+    }
+    Ok(file)
+}
+
+fn read_jasc_pal(text: &str) -> Result<PaletteFile, PaletteIoError> {
+    let mut lines = text.lines().map(str::trim).enumerate();
+    if lines.next().map(|(_, line)| line) != Some("JASC-PAL") {
+        return Err(PaletteIoError::BadHeader(PaletteFormat::JascPal));
+    }
+    // The version is always 0100, and nothing depends on it.
+    lines.next().ok_or(PaletteIoError::Truncated)?;
+    let (i, count) = lines.next().ok_or(PaletteIoError::Truncated)?;
+    let count: usize = count
+        .parse()
+        .map_err(|_| syntax_error(i + 1, format!("{count:?} is not a number of colours")))?;
+    let mut file = PaletteFile::default();
+    for _ in 0..count {
+        let (i, line) = lines.next().ok_or(PaletteIoError::Truncated)?;
+        // Some tools add a fourth channel for alpha, which has nowhere to go.
+        let (rgb, _) = parse_channels(line, i + 1)?;
+        file.entries.push(PaletteEntry {
+            color: color_from_srgb8(rgb),
+            name: None,
+        });
+    }
+    Ok(file)
+}
+
+fn read_paint_net(text: &str) -> Result<PaletteFile, PaletteIoError> {
+    let mut file = PaletteFile::default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix(';') {
+            if let Some(name) = comment.trim().strip_prefix("Palette Name:") {
+                file.name = non_empty(name.trim());
+            }
+            continue;
+        }
+        // Colours are `aarrggbb`. Palettes have no alpha, so it's dropped.
+        if line.len() != 8 || !line.is_ascii() {
+            return Err(syntax_error(
+                i + 1,
+                format!("{line:?} is not a colour of the form aarrggbb"),
+            ));
+        }
+        file.entries.push(PaletteEntry {
+            color: color_from_srgb8(parse_hex_digits(&line[2..], i + 1)?),
+            name: None,
+        });
+    }
+    Ok(file)
+}
+
+fn read_hex(text: &str) -> Result<PaletteFile, PaletteIoError> {
+    let mut file = PaletteFile::default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        file.entries.push(PaletteEntry {
+            color: color_from_srgb8(parse_hex_digits(line, i + 1)?),
+            name: None,
+        });
+    }
+    Ok(file)
+}
+
+/// Appends `text` as ASE stores strings: a big endian `u16` count of UTF-16 code units including a terminating null,
+/// then the code units themselves, big endian. Text too long for the count is cut short at the last whole character
+/// that fits.
+fn push_ase_string(bytes: &mut Vec<u8>, text: &str) {
+    let mut units: Vec<u16> = Vec::new();
+    for c in text.chars() {
+        // Leave room for the null.
+        if units.len() + c.len_utf16() >= u16::MAX as usize {
+            break;
+        }
+        units.extend_from_slice(c.encode_utf16(&mut [0; 2]));
+    }
+    units.push(0);
+    bytes.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+}
+
+/// Reads big endian values from the front of a byte slice.
+struct AseReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> AseReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PaletteIoError> {
+        if self.bytes.len() < len {
+            return Err(PaletteIoError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, PaletteIoError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, PaletteIoError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, PaletteIoError> {
+        Ok(f32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, PaletteIoError> {
+        let len = self.u16()? as usize;
+        let units: Vec<u16> = self
+            .take(len * 2)?
+            .chunks_exact(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .collect();
+        let text = String::from_utf16_lossy(&units);
+        Ok(text.trim_end_matches('\0').to_owned())
+    }
+
+    fn color(&mut self) -> Result<Color, PaletteIoError> {
+        let model = self.take(4)?;
+        Ok(match model {
+            b"RGB " => Color::from_color(Srgb::new(self.f32()?, self.f32()?, self.f32()?)),
+            // ASE stores lightness from 0 to 1, rather than 0 to 100.
+            b"LAB " => {
+                let lab: Lab<D50, f32> = Lab::new(self.f32()? * 100.0, self.f32()?, self.f32()?);
+                let xyz: Xyz<D65, f32> = Xyz::<D50, f32>::from_color(lab).adapt_into();
+                Color::from_color(xyz)
+            }
+            b"CMYK" => {
+                let [c, m, y, k] = [self.f32()?, self.f32()?, self.f32()?, self.f32()?];
+                Color::from_color(Srgb::new(
+                    (1.0 - c) * (1.0 - k),
+                    (1.0 - m) * (1.0 - k),
+                    (1.0 - y) * (1.0 - k),
+                ))
+            }
+            b"Gray" => {
+                let gray = self.f32()?;
+                Color::from_color(Srgb::new(gray, gray, gray))
+            }
+            model => {
+                return Err(PaletteIoError::UnsupportedColorModel(
+                    String::from_utf8_lossy(model).into_owned(),
+                ))
+            }
+        })
+    }
+}
+
+fn read_ase(bytes: &[u8]) -> Result<PaletteFile, PaletteIoError> {
+    let mut reader = AseReader { bytes };
+    if reader.take(4).ok() != Some(ASE_MAGIC.as_slice()) {
+        return Err(PaletteIoError::BadHeader(PaletteFormat::Ase));
+    }
+    // Only version 1.0 has ever existed.
+    reader.u16()?;
+    reader.u16()?;
+    let block_count = reader.u32()?;
+    let mut file = PaletteFile::default();
+    for _ in 0..block_count {
+        let kind = reader.u16()?;
+        let len = reader.u32()? as usize;
+        let mut block = AseReader {
+            bytes: reader.take(len)?,
+        };
+        match kind {
+            ASE_COLOR => {
+                let name = block.string()?;
+                file.entries.push(PaletteEntry {
+                    color: block.color()?,
+                    name: non_empty(&name),
+                });
+            }
+            // Hexil's palettes are flat, so groups are only kept as the palette's name.
+            ASE_GROUP_START => {
+                let name = block.string()?;
+                if file.name.is_none() {
+                    file.name = non_empty(&name);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(file)
+}
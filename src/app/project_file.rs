@@ -0,0 +1,284 @@
+//! The on-disk `.hexil` project format.
+//!
+//! Every project file starts with a fixed size header, followed by the project itself encoded with `bincode`:
+//!
+//! | Offset | Size | Contents                                                     |
+//! |--------|------|--------------------------------------------------------------|
+//! | 0      | 8    | `MAGIC`                                                      |
+//! | 8      | 4    | Format version, little endian `u32`                          |
+//! | 12     | 8    | Payload length in bytes, little endian `u64`                 |
+//! | 20     | 8    | FNV-1a hash of the payload, little endian `u64`              |
+//! | 28     | ...  | Payload                                                      |
+//!
+//! The payload of a version `n` file is exactly the serialized form of `ProjectVn`, so once a version has shipped,
+//! neither its header layout nor its project type may change.
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use bincode::Options;
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{CanvasSize, ProjectV1, ProjectV2};
+
+/// The file extension used for Hexil projects.
+pub const EXTENSION: &str = "hexil";
+
+/// The first 8 bytes of every Hexil project file. The trailing `\r\n` catches files mangled by newline conversion.
+pub const MAGIC: [u8; 8] = *b"HEXIL\0\r\n";
+
+/// The size in bytes of the header preceding the payload.
+pub const HEADER_LEN: usize = 28;
+
+/// Everything that can go wrong while saving or loading a project file.
+#[derive(Debug, Error)]
+pub enum ProjectFileError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Not a Hexil project file!")]
+    BadMagic,
+    #[error("Project file version {0} is not supported by this version of Hexil!")]
+    UnsupportedVersion(u32),
+    #[error("Project file is truncated!")]
+    Truncated,
+    #[error("Project file has {0} bytes of trailing garbage!")]
+    TrailingBytes(u64),
+    #[error("Project file checksum mismatch, the file is corrupt!")]
+    ChecksumMismatch,
+    #[error("Project file could not be decoded: {0}")]
+    Decode(#[from] bincode::Error),
+    #[error("Project file is inconsistent: {0}")]
+    Inconsistent(String),
+}
+
+/// The fixed size header at the start of every project file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u32,
+    pub payload_len: u64,
+    pub checksum: u64,
+}
+
+impl FileHeader {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[0..8].copy_from_slice(&MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.payload_len.to_le_bytes());
+        bytes[20..28].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Result<Self, ProjectFileError> {
+        if bytes[0..8] != MAGIC {
+            return Err(ProjectFileError::BadMagic);
+        }
+        // The slice lengths are fixed, so the conversions can't fail.
+        Ok(Self {
+            version: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            payload_len: u64::from_le_bytes(bytes[12..20].try_into().unwrap()),
+            checksum: u64::from_le_bytes(bytes[20..28].try_into().unwrap()),
+        })
+    }
+}
+
+/// 64 bit FNV-1a. Not cryptographic, it only has to catch accidental corruption.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The `bincode` configuration used for every payload. This must never change for an already released format version.
+fn payload_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .reject_trailing_bytes()
+}
+
+/// Writes a complete project file (header and payload) with the given version.
+pub(crate) fn write_file<T: serde::Serialize>(
+    mut writer: impl Write,
+    version: u32,
+    project: &T,
+) -> Result<(), ProjectFileError> {
+    let payload = payload_options().serialize(project)?;
+    let header = FileHeader {
+        version,
+        payload_len: payload.len() as u64,
+        checksum: checksum(&payload),
+    };
+    writer.write_all(&header.to_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads and verifies the header and payload of a project file, without decoding the payload.
+pub(crate) fn read_file(mut reader: impl Read) -> Result<(FileHeader, Vec<u8>), ProjectFileError> {
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header).map_err(eof_is_truncation)?;
+    let header = FileHeader::from_bytes(&header)?;
+
+    // Read through `take` rather than allocating `payload_len` up front, so a corrupt length can't exhaust memory.
+    let mut payload = Vec::new();
+    reader
+        .by_ref()
+        .take(header.payload_len)
+        .read_to_end(&mut payload)?;
+    if (payload.len() as u64) < header.payload_len {
+        return Err(ProjectFileError::Truncated);
+    }
+    let trailing = std::io::copy(&mut reader, &mut std::io::sink())?;
+    if trailing != 0 {
+        return Err(ProjectFileError::TrailingBytes(trailing));
+    }
+    if checksum(&payload) != header.checksum {
+        return Err(ProjectFileError::ChecksumMismatch);
+    }
+    Ok((header, payload))
+}
+
+/// Decodes a verified payload.
+pub(crate) fn decode_payload<T: serde::de::DeserializeOwned>(
+    payload: &[u8],
+) -> Result<T, ProjectFileError> {
+    Ok(payload_options()
+        .with_limit(payload.len() as u64)
+        .deserialize(payload)?)
+}
+
+fn eof_is_truncation(err: std::io::Error) -> ProjectFileError {
+    match err.kind() {
+        std::io::ErrorKind::UnexpectedEof => ProjectFileError::Truncated,
+        _ => err.into(),
+    }
+}
+
+impl ProjectV1 {
+    /// The format version written in the header of files containing a `ProjectV1`.
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Checks the invariants that the rest of Hexil relies on, which a well formed but corrupt payload could still violate.
+    pub(crate) fn check_consistency(&self) -> Result<(), ProjectFileError> {
+        check_layers(
+            self.size,
+            self.layers
+                .iter()
+                .map(|layer| (layer.size, layer.canvas.len())),
+        )
+    }
+}
+
+impl ProjectV2 {
+    /// The format version written in the header of files containing a `ProjectV2`.
+    pub const FORMAT_VERSION: u32 = 2;
+
+    /// Saves the project to `path`, replacing anything already there. The file is written next to `path` first and
+    /// then renamed over it, so if saving fails partway through, whatever was at `path` is left as it was.
+    #[instrument(skip(self), err)]
+    pub fn save(&self, path: impl AsRef<Path> + std::fmt::Debug) -> Result<(), ProjectFileError> {
+        let path = path.as_ref();
+        let temporary = temporary_path(path);
+        let result = (|| {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(&temporary)?);
+            self.write_to(&mut writer)?;
+            writer
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?;
+            std::fs::rename(&temporary, path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temporary);
+        }
+        result
+    }
+
+    /// Loads a project from `path`. Only version 2 files are accepted, use `open_project` to open and upgrade a file of any version.
+    #[instrument(err)]
+    pub fn load(path: impl AsRef<Path> + std::fmt::Debug) -> Result<Self, ProjectFileError> {
+        let file = std::fs::File::open(path)?;
+        Self::read_from(std::io::BufReader::new(file))
+    }
+
+    /// Writes the project file to an arbitrary writer.
+    pub fn write_to(&self, writer: impl Write) -> Result<(), ProjectFileError> {
+        write_file(writer, Self::FORMAT_VERSION, self)
+    }
+
+    /// Reads a project file from an arbitrary reader.
+    pub fn read_from(reader: impl Read) -> Result<Self, ProjectFileError> {
+        let (header, payload) = read_file(reader)?;
+        if header.version != Self::FORMAT_VERSION {
+            return Err(ProjectFileError::UnsupportedVersion(header.version));
+        }
+        let project: Self = decode_payload(&payload)?;
+        project.check_consistency()?;
+        Ok(project)
+    }
+
+    /// Checks the invariants that the rest of Hexil relies on, which a well formed but corrupt payload could still violate.
+    pub(crate) fn check_consistency(&self) -> Result<(), ProjectFileError> {
+        check_layers(
+            self.size,
+            self.layers
+                .iter()
+                .map(|layer| (layer.size, layer.canvas.len())),
+        )?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return Err(ProjectFileError::Inconsistent(format!(
+                    "layer {i} has opacity {}, which is outside [0, 1]",
+                    layer.opacity
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that every layer, given as its size and number of tiles, matches a project of the given size.
+fn check_layers(
+    size: CanvasSize,
+    layers: impl Iterator<Item = (CanvasSize, usize)>,
+) -> Result<(), ProjectFileError> {
+    let area = area_as_usize(size)?;
+    for (i, (layer_size, tiles)) in layers.enumerate() {
+        if layer_size.width != size.width || layer_size.height != size.height {
+            return Err(ProjectFileError::Inconsistent(format!(
+                "layer {i} is {}x{}, but the project is {}x{}",
+                layer_size.width, layer_size.height, size.width, size.height
+            )));
+        }
+        if tiles != area {
+            return Err(ProjectFileError::Inconsistent(format!(
+                "layer {i} has {tiles} tiles, but the project has {area}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A hidden file in the same directory as `path`, so renaming it over `path` never has to cross file systems.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+fn area_as_usize(size: CanvasSize) -> Result<usize, ProjectFileError> {
+    size.width
+        .checked_mul(size.height)
+        .and_then(|area| usize::try_from(area).ok())
+        .ok_or_else(|| {
+            ProjectFileError::Inconsistent(format!(
+                "canvas size {}x{} is too large",
+                size.width, size.height
+            ))
+        })
+}
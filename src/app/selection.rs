@@ -0,0 +1,304 @@
+//! Selections: which tiles of a project edits are allowed to touch.
+//!
+//! A `Selection` is a mask over every tile of a canvas. Shapes are built with the constructors here, and combined with
+//! whatever was selected before according to a `SelectionMode`. A project's active selection lives in the project
+//! itself, so that changing it is undone and redone along with everything else, and every tool takes it to limit which
+//! tiles it changes. An empty selection is still active, and keeps every tile from being edited: only clearing the
+//! selection makes every tile editable again.
+use thiserror::Error;
+
+use super::{CanvasSize, EditError, GridType, LayerV1Canvas, LayerV2, ProjectV2};
+use crate::grid::{CubeCoord, OffsetCoord};
+use crate::tools::{fill_region, FillOptions, ToolError};
+
+/// Everything that can go wrong while combining selections.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SelectionError {
+    #[error(
+        "Can't combine a selection on a {0}x{1} {2:?} canvas with one on a {3}x{4} {5:?} canvas!"
+    )]
+    Mismatch(u64, u64, GridType, u64, u64, GridType),
+}
+
+/// How a newly selected shape combines with the existing selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SelectionMode {
+    /// Only the new shape is selected.
+    #[default]
+    Replace,
+    /// Tiles in either are selected.
+    Add,
+    /// Tiles in the existing selection but not the new shape are selected.
+    Subtract,
+    /// Tiles in both are selected.
+    Intersect,
+}
+
+/// A set of tiles on a canvas of a particular size and grid type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    size: CanvasSize,
+    gridtype: GridType,
+    /// One entry per tile, in the same order as a `CanvasIndices`.
+    mask: Vec<bool>,
+}
+
+impl Selection {
+    /// Selects nothing.
+    pub fn none(size: CanvasSize, gridtype: GridType) -> Self {
+        Self {
+            size,
+            gridtype,
+            mask: vec![false; size.area() as usize],
+        }
+    }
+
+    /// Selects every tile.
+    pub fn all(size: CanvasSize, gridtype: GridType) -> Self {
+        Self {
+            size,
+            gridtype,
+            mask: vec![true; size.area() as usize],
+        }
+    }
+
+    /// Selects exactly the tiles at `positions` in a `CanvasIndices`. Positions past the end of the canvas are ignored.
+    pub fn from_positions(
+        size: CanvasSize,
+        gridtype: GridType,
+        positions: impl IntoIterator<Item = usize>,
+    ) -> Self {
+        let mut selection = Self::none(size, gridtype);
+        for position in positions {
+            if let Some(selected) = selection.mask.get_mut(position) {
+                *selected = true;
+            }
+        }
+        selection
+    }
+
+    /// Selects every tile whose column and row lie between those of the corners `a` and `b`, inclusive. Corners may be
+    /// off the canvas, in which case the rectangle is cut to fit.
+    pub fn rectangle(size: CanvasSize, gridtype: GridType, a: OffsetCoord, b: OffsetCoord) -> Self {
+        let (cols, rows) = (
+            a.col.min(b.col)..=a.col.max(b.col),
+            a.row.min(b.row)..=a.row.max(b.row),
+        );
+        Self::matching(size, gridtype, |tile| {
+            cols.contains(&tile.col) && rows.contains(&tile.row)
+        })
+    }
+
+    /// Selects every tile within `radius` steps of `centre`, including `centre` itself. On hexagonal grids this is a
+    /// hexagon. On square grids a step may be diagonal, so it's a square.
+    pub fn range(size: CanvasSize, gridtype: GridType, centre: OffsetCoord, radius: u32) -> Self {
+        match gridtype {
+            GridType::Hexagonal => Self::from_positions(
+                size,
+                gridtype,
+                CubeCoord::from(centre)
+                    .range_within(radius, size)
+                    .filter_map(|tile| OffsetCoord::from(tile).to_index(size)),
+            ),
+            GridType::Square => Self::matching(size, gridtype, |tile| {
+                let distance = (tile.col - centre.col)
+                    .unsigned_abs()
+                    .max((tile.row - centre.row).unsigned_abs());
+                distance <= radius
+            }),
+        }
+    }
+
+    /// Selects every tile whose centre lies inside the polygon with corners `points`, given in tile space (see
+    /// `grid::picking::canvas_to_tile_space`). The polygon closes itself, and may cross itself, in which case
+    /// overlapping parts alternate between inside and outside.
+    pub fn lasso(size: CanvasSize, gridtype: GridType, points: &[[f64; 2]]) -> Self {
+        if points.len() < 3 {
+            return Self::none(size, gridtype);
+        }
+        Self::matching(size, gridtype, |tile| {
+            let shift = match gridtype {
+                GridType::Hexagonal if tile.is_shifted() => 0.5,
+                _ => 0.0,
+            };
+            let (x, y) = (tile.col as f64, tile.row as f64 + shift);
+            let mut inside = false;
+            let mut previous = points[points.len() - 1];
+            for &point in points {
+                let ([x0, y0], [x1, y1]) = (previous, point);
+                if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+                    inside = !inside;
+                }
+                previous = point;
+            }
+            inside
+        })
+    }
+
+    /// Selects the tiles of `layer` that match the tile at `start`, exactly as a fill with `options` would replace
+    /// them: the same palette index on base colour layers, or a value within the tolerance on alpha and shading
+    /// layers.
+    pub fn magic_wand(
+        layer: &LayerV2,
+        gridtype: GridType,
+        start: OffsetCoord,
+        options: FillOptions,
+    ) -> Result<Self, ToolError> {
+        let region = match layer.canvas() {
+            LayerV1Canvas::BaseColor { canvas, .. } => fill_region(
+                &canvas.read(),
+                layer,
+                gridtype,
+                start,
+                options,
+                None,
+                |a, b| a == b,
+            )?,
+            LayerV1Canvas::Alpha(alpha) => {
+                fill_region(alpha, layer, gridtype, start, options, None, |a, b| {
+                    (a - b).abs() <= options.tolerance
+                })?
+            }
+            LayerV1Canvas::Shading(shading) => {
+                fill_region(shading, layer, gridtype, start, options, None, |a, b| {
+                    (a as i64 - b as i64).abs() as f64 <= options.tolerance as f64
+                })?
+            }
+        };
+        Ok(Self::from_positions(layer.size(), gridtype, region))
+    }
+
+    /// Selects every tile for which `selected` is true.
+    fn matching(
+        size: CanvasSize,
+        gridtype: GridType,
+        selected: impl Fn(OffsetCoord) -> bool,
+    ) -> Self {
+        Self {
+            size,
+            gridtype,
+            mask: (0..size.area() as usize)
+                .map(|index| OffsetCoord::from_index(index, size).is_some_and(&selected))
+                .collect(),
+        }
+    }
+
+    pub fn size(&self) -> CanvasSize {
+        self.size
+    }
+
+    pub fn gridtype(&self) -> GridType {
+        self.gridtype
+    }
+
+    /// Whether the tile at `position` in a `CanvasIndices` is selected.
+    pub fn contains(&self, position: usize) -> bool {
+        self.mask.get(position).copied().unwrap_or(false)
+    }
+
+    /// Whether `tile` is selected.
+    pub fn contains_tile(&self, tile: OffsetCoord) -> bool {
+        tile.to_index(self.size)
+            .is_some_and(|position| self.contains(position))
+    }
+
+    /// The number of selected tiles.
+    pub fn len(&self) -> usize {
+        self.mask.iter().filter(|selected| **selected).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.mask.contains(&true)
+    }
+
+    /// The positions in a `CanvasIndices` of every selected tile, in order. This is what
+    /// `RenderCommand::SelectionChanged` takes.
+    pub fn positions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.mask
+            .iter()
+            .enumerate()
+            .filter(|(_, selected)| **selected)
+            .map(|(position, _)| position)
+    }
+
+    /// Selects every tile that wasn't, and deselects every tile that was.
+    pub fn invert(&mut self) {
+        for selected in &mut self.mask {
+            *selected = !*selected;
+        }
+    }
+
+    /// Combines `other` into this selection according to `mode`. Both must be for the same size and grid type.
+    pub fn combine(
+        &mut self,
+        other: &Selection,
+        mode: SelectionMode,
+    ) -> Result<(), SelectionError> {
+        if self.size != other.size || self.gridtype != other.gridtype {
+            return Err(SelectionError::Mismatch(
+                self.size.width,
+                self.size.height,
+                self.gridtype,
+                other.size.width,
+                other.size.height,
+                other.gridtype,
+            ));
+        }
+        for (selected, other) in self.mask.iter_mut().zip(&other.mask) {
+            *selected = match mode {
+                SelectionMode::Replace => *other,
+                SelectionMode::Add => *selected || *other,
+                SelectionMode::Subtract => *selected && !*other,
+                SelectionMode::Intersect => *selected && *other,
+            };
+        }
+        Ok(())
+    }
+
+    /// A rough estimate of how much memory this selection keeps alive.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.mask.len()
+    }
+}
+
+impl ProjectV2 {
+    /// Combines `shape` with the active selection according to `mode`. With no active selection every tile can be
+    /// edited, so adding or replacing selects just `shape`, and subtracting or intersecting starts from every tile.
+    pub fn select(&mut self, shape: &Selection, mode: SelectionMode) -> Result<(), EditError> {
+        let mut selection = self.selection.clone().unwrap_or_else(|| match mode {
+            SelectionMode::Replace | SelectionMode::Add => {
+                Selection::none(self.size, self.gridtype)
+            }
+            SelectionMode::Subtract | SelectionMode::Intersect => {
+                Selection::all(self.size, self.gridtype)
+            }
+        });
+        if selection.combine(shape, mode).is_err() {
+            return Err(EditError::SelectionMismatch(
+                shape.size.width,
+                shape.size.height,
+                shape.gridtype,
+                self.size.width,
+                self.size.height,
+                self.gridtype,
+            ));
+        }
+        self.set_selection(Some(selection))
+    }
+
+    /// Selects every tile that isn't selected, and deselects every tile that is. With no active selection, nothing
+    /// is selected, so this selects everything.
+    pub fn invert_selection(&mut self) -> Result<(), EditError> {
+        let mut selection = self
+            .selection
+            .clone()
+            .unwrap_or_else(|| Selection::none(self.size, self.gridtype));
+        selection.invert();
+        self.set_selection(Some(selection))
+    }
+
+    /// Removes the active selection, so every tile can be edited again.
+    pub fn clear_selection(&mut self) -> Result<(), EditError> {
+        self.set_selection(None)
+    }
+}
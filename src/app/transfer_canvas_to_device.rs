@@ -1,6 +1,117 @@
-use vk::buffer as vbuf;
-use vulkano as vk;
+use tracing::instrument;
 
-use super::LayerV1Canvas;
+use super::{CanvasSize, GridType, LayerKind, LayerV1Canvas, LayerV2, Palette, ProjectV2};
+use crate::render::canvas_manager::{canvas_dimensions, CanvasBuffersManager};
+use crate::render::{Renderer, RendererError};
 
-use std::sync::Arc;
+/// A copy of everything the renderer draws from a project, so it can be sent to the render thread without sharing the
+/// project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanvasSnapshot {
+    pub size: CanvasSize,
+    pub gridtype: GridType,
+    /// Every layer, from the bottom of the stack to the top.
+    pub layers: Vec<LayerSnapshot>,
+}
+
+/// A copy of one layer of a `CanvasSnapshot`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerSnapshot {
+    pub kind: LayerKind,
+    /// The layer's opacity, or 0 if it's hidden.
+    pub opacity: f32,
+    /// Empty unless this is a base colour layer.
+    pub palette: Palette,
+    /// Exactly one value per tile: the palette index for base colour layers, and the bits of the `f32` or `i32` for
+    /// alpha and shading layers.
+    pub tiles: Vec<u32>,
+}
+
+impl CanvasSnapshot {
+    /// Copies every layer of `project`.
+    pub fn of_project(project: &ProjectV2) -> Self {
+        let tiles = project.size().area() as usize;
+        Self {
+            size: project.size(),
+            gridtype: project.gridtype(),
+            layers: project
+                .layers()
+                .iter()
+                .map(|layer| LayerSnapshot::of_layer(layer, tiles))
+                .collect(),
+        }
+    }
+}
+
+impl LayerSnapshot {
+    /// Copies `layer`, padded or cut to exactly `tiles` tiles. Padding has no effect on the composited canvas.
+    pub fn of_layer(layer: &LayerV2, tiles: usize) -> Self {
+        let (palette, mut values) = match layer.canvas() {
+            LayerV1Canvas::BaseColor { palette, canvas } => {
+                (palette.read().clone(), canvas.read().clone())
+            }
+            LayerV1Canvas::Alpha(alpha) => (
+                Palette::new(),
+                alpha.iter().map(|alpha| alpha.to_bits()).collect(),
+            ),
+            LayerV1Canvas::Shading(shading) => (
+                Palette::new(),
+                shading.iter().map(|shading| *shading as u32).collect(),
+            ),
+        };
+        let padding = match layer.canvas().kind() {
+            LayerKind::Alpha => 1.0f32.to_bits(),
+            LayerKind::BaseColor => u32::MAX,
+            LayerKind::Shading => 0,
+        };
+        values.resize(tiles, padding);
+        Self {
+            kind: layer.canvas().kind(),
+            opacity: if layer.visible() {
+                layer.opacity()
+            } else {
+                0.0
+            },
+            palette,
+            tiles: values,
+        }
+    }
+}
+
+impl CanvasBuffersManager {
+    /// Writes `canvas` into the host copies of the canvas buffers, first reallocating them if the canvas size, number
+    /// of layers or total palette length changed. Returns whether they were reallocated, in which case the descriptors
+    /// have been rebuilt and every command buffer drawing the canvas must be recorded again. Either way, the device
+    /// copies are only updated once the command buffer from `upload` runs.
+    #[instrument(skip_all, err)]
+    pub(crate) fn transfer_canvas(
+        &mut self,
+        renderer: &Renderer,
+        canvas: &CanvasSnapshot,
+    ) -> Result<bool, RendererError> {
+        let size = canvas_dimensions(canvas.size)?;
+        let palette: Palette = canvas
+            .layers
+            .iter()
+            .flat_map(|layer| layer.palette.iter().copied())
+            .collect();
+        let tiles: Vec<u32> = canvas
+            .layers
+            .iter()
+            .flat_map(|layer| layer.tiles.iter().copied())
+            .collect();
+        // Storage buffers can't be empty, so an empty palette or layer stack still gets one entry.
+        let palette_size = palette.len().max(1) as u64;
+        let layer_count = canvas.layers.len().max(1) as u64;
+        let reallocate = self.size() != size
+            || self.palette_size() != palette_size
+            || self.layer_count() != layer_count;
+        if reallocate {
+            self.reallocate(renderer, size[0], size[1], palette_size, layer_count)?;
+        }
+        self.write_palette(&palette)?;
+        self.write_indices(&tiles)?;
+        self.write_layers(&canvas.layers)?;
+        Ok(reallocate)
+    }
+}
@@ -0,0 +1,94 @@
+//! Checks for projects that load fine but would draw or export incorrectly.
+//!
+//! Layers whose size or number of tiles doesn't match the project are already rejected while the file is read, so
+//! they're never reported here.
+use super::{LayerV1Canvas, ProjectV2};
+
+/// A single problem with a project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// Some tiles of a base colour layer refer to palette entries that don't exist.
+    IndicesOutOfRange {
+        layer: usize,
+        count: usize,
+        first_tile: usize,
+        largest_index: u32,
+        palette_len: usize,
+    },
+    /// Some tiles of an alpha layer are NaN, infinite, or outside `[0, 1]`.
+    AlphaOutOfRange {
+        layer: usize,
+        count: usize,
+        first_tile: usize,
+    },
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationIssue::IndicesOutOfRange {
+                layer,
+                count,
+                first_tile,
+                largest_index,
+                palette_len,
+            } => write!(
+                f,
+                "layer {layer} has {count} tiles with palette indices out of range (first at tile {first_tile}, largest index {largest_index}, palette has {palette_len} colours)"
+            ),
+            ValidationIssue::AlphaOutOfRange {
+                layer,
+                count,
+                first_tile,
+            } => write!(
+                f,
+                "layer {layer} has {count} tiles with alpha outside [0, 1] (first at tile {first_tile})"
+            ),
+        }
+    }
+}
+
+impl ProjectV2 {
+    /// Finds every problem with the project. An empty result means the project is valid.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for (layer_index, layer) in self.layers.iter().enumerate() {
+            match &layer.canvas {
+                LayerV1Canvas::BaseColor { palette, canvas } => {
+                    let palette_len = palette.read().len();
+                    let canvas = canvas.read();
+                    let mut bad = canvas
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, index)| **index as usize >= palette_len);
+                    if let Some((first_tile, first_index)) = bad.next() {
+                        let (count, largest_index) =
+                            bad.fold((1, *first_index), |(n, max), (_, i)| (n + 1, max.max(*i)));
+                        issues.push(ValidationIssue::IndicesOutOfRange {
+                            layer: layer_index,
+                            count,
+                            first_tile,
+                            largest_index,
+                            palette_len,
+                        });
+                    }
+                }
+                LayerV1Canvas::Alpha(alpha) => {
+                    let mut bad = alpha
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, alpha)| !(0.0..=1.0).contains(*alpha));
+                    if let Some((first_tile, _)) = bad.next() {
+                        issues.push(ValidationIssue::AlphaOutOfRange {
+                            layer: layer_index,
+                            count: bad.count() + 1,
+                            first_tile,
+                        });
+                    }
+                }
+                LayerV1Canvas::Shading(_) => (),
+            }
+        }
+        issues
+    }
+}
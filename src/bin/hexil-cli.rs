@@ -0,0 +1,211 @@
+//! Headless tool for inspecting, converting and exporting Hexil projects. Never touches a window, and only touches the
+//! GPU when asked to with `export --gpu`, so it can run on build machines.
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use hexil::app::{GridType, LayerV1Canvas, Project, VersionedProject};
+use hexil::export::{oklab_to_hex, parse_hex_srgb8, rasterize, RasterOptions};
+use hexil::render::Renderer;
+
+#[derive(Debug, Parser)]
+#[command(name = "hexil-cli", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints the name, size, grid type, layers and palettes of a project.
+    Info { project: PathBuf },
+    /// Exports a project as an image. The format is picked from the extension of the output.
+    Export {
+        project: PathBuf,
+        output: PathBuf,
+        /// Height of one tile in pixels.
+        #[arg(long, default_value_t = 16)]
+        scale: u32,
+        /// Fill the space between tiles with this colour (as `#rrggbb`) instead of leaving it transparent.
+        #[arg(long, value_parser = parse_hex_color)]
+        background: Option<[u8; 3]>,
+        /// Draw with the GPU renderer instead of on the CPU. Needs a Vulkan driver, but not a display.
+        #[arg(long)]
+        gpu: bool,
+    },
+    /// Upgrades a project of any older version to the current version.
+    Convert {
+        project: PathBuf,
+        /// Where to write the upgraded project. Defaults to overwriting the input.
+        output: Option<PathBuf>,
+    },
+    /// Checks that a project is internally consistent. Exits with failure if it isn't.
+    Validate { projects: Vec<PathBuf> },
+}
+
+fn parse_hex_color(s: &str) -> Result<[u8; 3], String> {
+    parse_hex_srgb8(s).ok_or_else(|| format!("{s} is not a colour of the form #rrggbb"))
+}
+
+fn open(path: &Path) -> Result<(u32, Project), String> {
+    let project = VersionedProject::load(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let version = project.version();
+    Ok((version, project.upgrade()))
+}
+
+fn info(path: &Path) -> Result<(), String> {
+    let (version, project) = open(path)?;
+    let size = project.size();
+    println!("Name:      {}", project.name());
+    println!("Version:   {version}");
+    println!("Size:      {}x{}", size.width, size.height);
+    println!(
+        "Grid:      {}",
+        match project.gridtype() {
+            GridType::Square => "square",
+            GridType::Hexagonal => "hexagonal",
+        }
+    );
+    println!("Layers:    {}", project.layers().len());
+    for (i, layer) in project.layers().iter().enumerate() {
+        let name = layer.name().unwrap_or("<unnamed>");
+        let appearance = format!(
+            "{}, opacity {:.0}%",
+            if layer.visible() { "visible" } else { "hidden" },
+            layer.opacity() * 100.0
+        );
+        match layer.canvas() {
+            LayerV1Canvas::Alpha(_) => println!("  {i}: {name} (alpha, {appearance})"),
+            LayerV1Canvas::Shading(_) => println!("  {i}: {name} (shading, {appearance})"),
+            LayerV1Canvas::BaseColor { palette, .. } => {
+                let palette = palette.read();
+                println!(
+                    "  {i}: {name} (base colour, {} colours, {appearance})",
+                    palette.len()
+                );
+                for (j, color) in palette.iter().enumerate() {
+                    println!(
+                        "       {j:>3}: {} (Oklab {:.4} {:.4} {:.4})",
+                        oklab_to_hex(*color),
+                        color.l,
+                        color.a,
+                        color.b
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn export(
+    path: &Path,
+    output: &Path,
+    scale: u32,
+    background: Option<[u8; 3]>,
+    gpu: bool,
+) -> Result<(), String> {
+    let (_, project) = open(path)?;
+    let options = RasterOptions {
+        pixels_per_tile: scale,
+        background: background.map(|[r, g, b]| [r, g, b, 255]),
+    };
+    let raster = if gpu {
+        Renderer::new_headless()
+            .and_then(|renderer| renderer.render_offscreen(&project, options))
+            .map_err(|e| format!("Couldn't render on the GPU: {e}"))?
+    } else {
+        rasterize(&project, options)
+    };
+    let is_png = output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        let file =
+            std::fs::File::create(output).map_err(|e| format!("{}: {e}", output.display()))?;
+        return hexil::export::png::write_raster(&raster, std::io::BufWriter::new(file))
+            .map_err(|e| format!("{}: {e}", output.display()));
+    }
+
+    let format =
+        image::ImageFormat::from_path(output).map_err(|e| format!("{}: {e}", output.display()))?;
+    let image = image::RgbaImage::from_raw(raster.width, raster.height, raster.as_bytes().to_vec())
+        .ok_or_else(|| "The rasterised image has the wrong size!".to_owned())?;
+    let image = image::DynamicImage::ImageRgba8(image);
+    // Not every format can store an alpha channel.
+    let image = match format {
+        image::ImageFormat::Jpeg => image::DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image,
+    };
+    image
+        .save_with_format(output, format)
+        .map_err(|e| format!("{}: {e}", output.display()))
+}
+
+fn convert(path: &Path, output: Option<&Path>) -> Result<(), String> {
+    let (version, project) = open(path)?;
+    let output = output.unwrap_or(path);
+    project
+        .save(output)
+        .map_err(|e| format!("{}: {e}", output.display()))?;
+    if version == Project::FORMAT_VERSION {
+        println!("{}: already version {version}, rewritten", output.display());
+    } else {
+        println!(
+            "{}: upgraded from version {version} to {}",
+            output.display(),
+            Project::FORMAT_VERSION
+        );
+    }
+    Ok(())
+}
+
+fn validate(paths: &[PathBuf]) -> Result<(), String> {
+    let mut failed = 0;
+    for path in paths {
+        match open(path) {
+            Err(e) => {
+                println!("{e}");
+                failed += 1;
+            }
+            Ok((_, project)) => {
+                let issues = project.validate();
+                if issues.is_empty() {
+                    println!("{}: ok", path.display());
+                } else {
+                    failed += 1;
+                    for issue in issues {
+                        println!("{}: {issue}", path.display());
+                    }
+                }
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{n} of {} projects are invalid", paths.len())),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Info { project } => info(project),
+        Command::Export {
+            project,
+            output,
+            scale,
+            background,
+            gpu,
+        } => export(project, output, *scale, *background, *gpu),
+        Command::Convert { project, output } => convert(project, output.as_deref()),
+        Command::Validate { projects } => validate(projects),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::app::{CanvasSize, GridType};
+use crate::grid::picking::window_to_clip;
+use crate::grid::{column_spacing, tile_aspect};
+
+/// The furthest the camera can zoom out.
+pub const MIN_ZOOM: f32 = 0.1;
+/// The furthest the camera can zoom in.
+pub const MAX_ZOOM: f32 = 256.0;
+
+/// The view onto the canvas. `pan` is the point of the canvas (in the clip space `transform_one` in `canvas_vert.glsl`
+/// produces, where the whole canvas spans `[-1, 1]`) that appears at the centre of the window, and `zoom` is how much
+/// that space is magnified. At a zoom of 1 the whole canvas fits in the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub pan: [f32; 2],
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            pan: [0.0, 0.0],
+            zoom: 1.0,
+        }
+    }
+}
+
+/// Maps canvas space to clip space as `clip = canvas * scale + offset`, per axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTransform {
+    pub scale: [f32; 2],
+    pub offset: [f32; 2],
+}
+
+impl ViewTransform {
+    pub fn apply(&self, canvas: [f64; 2]) -> [f64; 2] {
+        [
+            canvas[0] * self.scale[0] as f64 + self.offset[0] as f64,
+            canvas[1] * self.scale[1] as f64 + self.offset[1] as f64,
+        ]
+    }
+
+    pub fn invert(&self, clip: [f64; 2]) -> [f64; 2] {
+        [
+            (clip[0] - self.offset[0] as f64) / self.scale[0] as f64,
+            (clip[1] - self.offset[1] as f64) / self.scale[1] as f64,
+        ]
+    }
+}
+
+impl Camera {
+    /// The transform from canvas space to clip space for this camera, for a canvas of `size` tiles shown in a window
+    /// of `window_size` physical pixels. Tiles keep their shape whatever the shape of the window.
+    pub fn view_transform(
+        &self,
+        window_size: [u32; 2],
+        size: CanvasSize,
+        gridtype: GridType,
+    ) -> ViewTransform {
+        let fit = fit_scale(window_size, size, gridtype);
+        let scale = [fit[0] * self.zoom, fit[1] * self.zoom];
+        ViewTransform {
+            scale,
+            offset: [-self.pan[0] * scale[0], -self.pan[1] * scale[1]],
+        }
+    }
+
+    /// Moves the canvas by `delta` physical pixels, so that whatever was under the cursor stays under it.
+    pub fn pan_by_pixels(
+        &mut self,
+        delta: [f64; 2],
+        window_size: [u32; 2],
+        size: CanvasSize,
+        gridtype: GridType,
+    ) {
+        if window_size[0] == 0 || window_size[1] == 0 {
+            return;
+        }
+        let view = self.view_transform(window_size, size, gridtype);
+        for axis in 0..2 {
+            let clip = delta[axis] / window_size[axis] as f64 * 2.0;
+            self.pan[axis] -= (clip / view.scale[axis] as f64) as f32;
+        }
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the point under `cursor` (in physical pixels) where it is. The zoom is
+    /// kept between `MIN_ZOOM` and `MAX_ZOOM`.
+    pub fn zoom_at(
+        &mut self,
+        factor: f32,
+        cursor: [f64; 2],
+        window_size: [u32; 2],
+        size: CanvasSize,
+        gridtype: GridType,
+    ) {
+        if window_size[0] == 0 || window_size[1] == 0 {
+            return;
+        }
+        let clip = window_to_clip(cursor, window_size);
+        let anchor = self
+            .view_transform(window_size, size, gridtype)
+            .invert(clip);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let view = self.view_transform(window_size, size, gridtype);
+        for axis in 0..2 {
+            self.pan[axis] = (anchor[axis] - clip[axis] / view.scale[axis] as f64) as f32;
+        }
+    }
+}
+
+/// The scale at a zoom of 1, which fits the whole canvas (including the parts of tiles that stick out past the edges
+/// of canvas space) in the window, centred, with tiles at their proper aspect ratio.
+fn fit_scale(window_size: [u32; 2], size: CanvasSize, gridtype: GridType) -> [f32; 2] {
+    if window_size.contains(&0) || size.area() == 0 {
+        return [1.0, 1.0];
+    }
+    let spacing = column_spacing(gridtype);
+    // The width and height of canvas space, in tile heights.
+    let canvas = [size.width as f64 * spacing, size.height as f64];
+    // How much room to leave around canvas space, in tile heights, on both sides of each axis. Hexagons overhang
+    // their column on the left and right, and shifted columns overhang the bottom by half a tile.
+    let margin = match gridtype {
+        GridType::Square => [0.0, 0.0],
+        GridType::Hexagonal if size.width > 1 => [tile_aspect(gridtype) - spacing, 1.0],
+        GridType::Hexagonal => [tile_aspect(gridtype) - spacing, 0.0],
+    };
+    let [window_width, window_height] = window_size.map(|x| x as f64);
+    let pixels_per_tile = f64::min(
+        window_width / (canvas[0] + margin[0]),
+        window_height / (canvas[1] + margin[1]),
+    );
+    [
+        (canvas[0] * pixels_per_tile / window_width) as f32,
+        (canvas[1] * pixels_per_tile / window_height) as f32,
+    ]
+}
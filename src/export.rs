@@ -0,0 +1,207 @@
+//! Getting artwork out of Hexil. Everything here runs entirely on the CPU, so it works without a window or a GPU.
+use palette::{Clamp, FromColor, LinSrgb, LinSrgba, Srgb, Srgba};
+
+use crate::app::{CanvasSize, Color, GridType, ProjectV2, TileColor};
+use crate::camera::ViewTransform;
+use crate::grid::picking::tile_at;
+use crate::grid::{column_spacing, tile_aspect, OffsetCoord, HEX_ASPECT};
+
+pub mod animation;
+pub mod png;
+
+/// How a project is turned into pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RasterOptions {
+    /// The height of one tile in pixels. Hexagons are regular, like the renderer draws them, so they are
+    /// `grid::HEX_ASPECT` times wider than they are tall.
+    pub pixels_per_tile: u32,
+    /// The sRGB colour of every pixel not covered by a tile, or `None` to leave those pixels fully transparent.
+    pub background: Option<[u8; 4]>,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        Self {
+            pixels_per_tile: 16,
+            background: None,
+        }
+    }
+}
+
+/// An sRGB image with 8 bit straight alpha, stored row major from the top left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Raster {
+    /// The pixels as a flat `RGBARGBA...` byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.pixels)
+    }
+}
+
+/// Converts an Oklab colour to 8 bit sRGB, clamping anything outside of the sRGB gamut.
+pub fn oklab_to_srgb8(color: Color) -> [u8; 3] {
+    let srgb: Srgb = Srgb::from_color(color).clamp();
+    let srgb: Srgb<u8> = srgb.into_format();
+    [srgb.red, srgb.green, srgb.blue]
+}
+
+/// Formats an Oklab colour as `#rrggbb`, clamped into the sRGB gamut like `oklab_to_srgb8` clamps it.
+pub fn oklab_to_hex(color: Color) -> String {
+    let [r, g, b] = oklab_to_srgb8(color);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Parses an 8 bit sRGB colour written as `#rrggbb` or `#rgb`, with or without the `#` and in either case.
+pub fn parse_hex_srgb8(text: &str) -> Option<[u8; 3]> {
+    let hex = text.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        6 => Some([
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        ]),
+        3 => {
+            let short = |i: usize| channel(&hex[i..i + 1]).map(|digit| digit * 17);
+            Some([short(0)?, short(1)?, short(2)?])
+        }
+        _ => None,
+    }
+}
+
+/// The size in pixels of the image `rasterize` produces.
+pub fn raster_size(size: CanvasSize, gridtype: GridType, pixels_per_tile: u32) -> (u32, u32) {
+    let s = pixels_per_tile as u64;
+    let (width, height) = match gridtype {
+        GridType::Square => (size.width * s, size.height * s),
+        GridType::Hexagonal if size.width == 0 || size.height == 0 => (0, 0),
+        GridType::Hexagonal => {
+            let s = s as f64;
+            let width = (size.width - 1) as f64 * column_spacing(gridtype) * s + HEX_ASPECT * s;
+            let shift = if size.width > 1 { s / 2.0 } else { 0.0 };
+            (
+                width.ceil() as u64,
+                (size.height as f64 * s + shift).ceil() as u64,
+            )
+        }
+    };
+    (
+        u32::try_from(width).unwrap_or(u32::MAX),
+        u32::try_from(height).unwrap_or(u32::MAX),
+    )
+}
+
+/// The tile covering the centre of the pixel at `(x, y)`, whether or not it is on the canvas.
+pub fn tile_under_pixel(x: u32, y: u32, gridtype: GridType, pixels_per_tile: u32) -> OffsetCoord {
+    let s = pixels_per_tile as f64;
+    let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+    let half_width = tile_aspect(gridtype) * s / 2.0;
+    tile_at(
+        [
+            (px - half_width) / (column_spacing(gridtype) * s),
+            (py - s / 2.0) / s,
+        ],
+        gridtype,
+    )
+}
+
+/// The view transform that makes the renderer draw exactly the image `rasterize` produces, into an image of
+/// `raster_size` pixels.
+pub fn raster_view(size: CanvasSize, gridtype: GridType, pixels_per_tile: u32) -> ViewTransform {
+    let (width, height) = raster_size(size, gridtype, pixels_per_tile);
+    let (width, height) = (width as f64, height as f64);
+    let s = pixels_per_tile as f64;
+    let spacing = column_spacing(gridtype) * s;
+    let half_width = tile_aspect(gridtype) * s / 2.0;
+    // Canvas space spans the centres of the outermost tiles, plus half a column or row, so the first column's
+    // centre lands `half_width` from the left of the image and the first row's centre `s / 2` from the top.
+    let scale = [
+        size.width as f64 * spacing / width,
+        size.height as f64 * s / height,
+    ];
+    ViewTransform {
+        scale: [scale[0] as f32, scale[1] as f32],
+        offset: [
+            (2.0 * (half_width - spacing / 2.0) / width + scale[0] - 1.0) as f32,
+            (scale[1] - 1.0) as f32,
+        ],
+    }
+}
+
+/// The 8 bit sRGB pixel for a tile of colour `tile` drawn over `background`. Blending happens in linear light, like
+/// the renderer's blending does.
+pub fn tile_pixel(tile: TileColor, background: [u8; 4]) -> [u8; 4] {
+    let [r, g, b, a] = background;
+    let background: LinSrgba = Srgba::new(r, g, b, a)
+        .into_format::<f32, f32>()
+        .into_linear();
+    let color: LinSrgb = LinSrgb::from_color(tile.color).clamp();
+    let tile_alpha = tile.alpha.clamp(0.0, 1.0);
+    let alpha = tile_alpha + background.alpha * (1.0 - tile_alpha);
+    if alpha <= 0.0 {
+        return [0; 4];
+    }
+    let below = background.alpha * (1.0 - tile_alpha);
+    let blended = LinSrgb::new(
+        (color.red * tile_alpha + background.red * below) / alpha,
+        (color.green * tile_alpha + background.green * below) / alpha,
+        (color.blue * tile_alpha + background.blue * below) / alpha,
+    );
+    let srgb: Srgb<u8> = Srgb::from_linear(blended);
+    [
+        srgb.red,
+        srgb.green,
+        srgb.blue,
+        (alpha * 255.0).round() as u8,
+    ]
+}
+
+/// Rasterises `tiles` (as from `ProjectV2::composite`) for a canvas of the given size and grid type.
+pub fn rasterize_tiles(
+    tiles: &[TileColor],
+    size: CanvasSize,
+    gridtype: GridType,
+    options: RasterOptions,
+) -> Raster {
+    let (width, height) = raster_size(size, gridtype, options.pixels_per_tile);
+    let background = options.background.unwrap_or([0; 4]);
+    let tile_colors: Vec<Option<[u8; 4]>> = tiles
+        .iter()
+        .map(|tile| tile.is_covered().then(|| tile_pixel(*tile, background)))
+        .collect();
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let tile = tile_under_pixel(x, y, gridtype, options.pixels_per_tile);
+            let color = tile
+                .to_index(size)
+                .and_then(|index| tile_colors.get(index).copied().flatten());
+            pixels.push(color.unwrap_or(background));
+        }
+    }
+    Raster {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Rasterises the whole project.
+pub fn rasterize(project: &ProjectV2, options: RasterOptions) -> Raster {
+    rasterize_tiles(
+        &project.composite(),
+        project.size(),
+        project.gridtype(),
+        options,
+    )
+}
@@ -0,0 +1,309 @@
+//! Animated GIF and APNG export.
+//!
+//! An animation is a sequence of frames, each a project of the same size and grid type shown for its own duration.
+//! Every frame is drawn by `rasterize`, exactly as a still export of it would be, and then scaled up by a whole number
+//! of pixels per pixel.
+//!
+//! GIFs are indexed, and so are base colour layers, so the GIF colour table starts with the palette of the first base
+//! colour layer of the first frame, in the same order. Colours the palette doesn't have, from blending, shading or
+//! other frames, are added after it while there's room, and mapped to the nearest colour in Oklab once there isn't.
+//! GIF pixels are either opaque or transparent, so pixels that are less than half covered become transparent.
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use ahash::AHashMap;
+use palette::{FromColor, Srgb};
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{oklab_to_srgb8, rasterize, Raster, RasterOptions};
+use crate::app::palette_edit::delta_e;
+use crate::app::{Color, LayerV1Canvas, ProjectV2};
+
+/// The most colours a GIF colour table can hold.
+const GIF_COLORS: usize = 256;
+
+/// Everything that can go wrong while exporting an animation.
+#[derive(Debug, Error)]
+pub enum AnimationError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Png(#[from] ::png::EncodingError),
+    #[error(transparent)]
+    Gif(#[from] ::gif::EncodingError),
+    #[error("An animation needs at least one frame!")]
+    NoFrames,
+    #[error("Frame {0} is not the same size and grid type as the first frame!")]
+    FrameMismatch(usize),
+    #[error("The image would be empty!")]
+    Empty,
+    #[error("A {0}x{1} image is too large for this format!")]
+    TooLarge(u32, u32),
+    #[error("The scale factor must be at least 1!")]
+    ZeroScale,
+}
+
+/// One frame of an animation.
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationFrame<'a> {
+    pub project: &'a ProjectV2,
+    /// How long the frame is shown. GIFs store this in hundredths of a second, so it is rounded to the nearest.
+    pub duration: Duration,
+}
+
+/// How an animation is exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationOptions {
+    /// How each frame is drawn before scaling.
+    pub raster: RasterOptions,
+    /// How many pixels wide and tall each drawn pixel becomes.
+    pub scale: u32,
+    /// How many times the animation plays, or 0 to loop forever.
+    pub plays: u16,
+    /// A palette index, of the first base colour layer of the first frame, that is exported as transparent. In GIFs
+    /// this is the transparent index of the colour table, and every pixel that isn't covered uses it too. Without one,
+    /// pixels that aren't covered in a GIF are drawn over the raster background, or black if there isn't one.
+    pub transparent_index: Option<u8>,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            raster: RasterOptions::default(),
+            scale: 1,
+            plays: 0,
+            transparent_index: None,
+        }
+    }
+}
+
+/// Draws every frame, scaled, with the colour at `options.transparent_index` made transparent. All frames are the same
+/// size.
+pub fn render_frames(
+    frames: &[AnimationFrame],
+    options: AnimationOptions,
+) -> Result<Vec<Raster>, AnimationError> {
+    let first = frames.first().ok_or(AnimationError::NoFrames)?.project;
+    if let Some(index) = frames.iter().position(|frame| {
+        frame.project.size() != first.size() || frame.project.gridtype() != first.gridtype()
+    }) {
+        return Err(AnimationError::FrameMismatch(index));
+    }
+    if options.scale == 0 {
+        return Err(AnimationError::ZeroScale);
+    }
+    let transparent = options
+        .transparent_index
+        .and_then(|index| base_palette(first).get(index as usize).copied());
+    frames
+        .iter()
+        .map(|frame| {
+            let mut raster = scale(&rasterize(frame.project, options.raster), options.scale)?;
+            if let Some(transparent) = transparent {
+                for pixel in &mut raster.pixels {
+                    if pixel[..3] == transparent && pixel[3] == 255 {
+                        *pixel = [0; 4];
+                    }
+                }
+            }
+            Ok(raster)
+        })
+        .collect()
+}
+
+/// The palette of the first base colour layer of `project` in 8 bit sRGB, or nothing if it has no base colour layers.
+fn base_palette(project: &ProjectV2) -> Vec<[u8; 3]> {
+    project
+        .layers()
+        .iter()
+        .find_map(|layer| match layer.canvas() {
+            LayerV1Canvas::BaseColor { palette, .. } => Some(
+                palette
+                    .read()
+                    .iter()
+                    .map(|color| oklab_to_srgb8(*color))
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// Scales `raster` up by `factor` in both directions, repeating every pixel.
+fn scale(raster: &Raster, factor: u32) -> Result<Raster, AnimationError> {
+    let too_large = || AnimationError::TooLarge(raster.width, raster.height);
+    let width = raster.width.checked_mul(factor).ok_or_else(too_large)?;
+    let height = raster.height.checked_mul(factor).ok_or_else(too_large)?;
+    if width == 0 || height == 0 {
+        return Err(AnimationError::Empty);
+    }
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for row in raster.pixels.chunks_exact(raster.width as usize) {
+        let scaled: Vec<[u8; 4]> = row
+            .iter()
+            .flat_map(|pixel| std::iter::repeat_n(*pixel, factor as usize))
+            .collect();
+        for _ in 0..factor {
+            pixels.extend_from_slice(&scaled);
+        }
+    }
+    Ok(Raster {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Encodes the frames as an animated PNG, with full alpha.
+pub fn write_apng(
+    frames: &[AnimationFrame],
+    options: AnimationOptions,
+    writer: impl Write,
+) -> Result<(), AnimationError> {
+    let rasters = render_frames(frames, options)?;
+    let (width, height) = (rasters[0].width, rasters[0].height);
+    let mut encoder = ::png::Encoder::new(writer, width, height);
+    encoder.set_color(::png::ColorType::Rgba);
+    encoder.set_depth(::png::BitDepth::Eight);
+    encoder.set_animated(rasters.len() as u32, options.plays as u32)?;
+    let mut writer = encoder.write_header()?;
+    for (frame, raster) in frames.iter().zip(&rasters) {
+        // Milliseconds when they fit, and hundredths of a second when they don't.
+        let millis = frame.duration.as_millis();
+        match u16::try_from(millis) {
+            Ok(millis) => writer.set_frame_delay(millis, 1000)?,
+            Err(_) => {
+                let centis = u16::try_from((millis + 5) / 10).unwrap_or(u16::MAX);
+                writer.set_frame_delay(centis, 100)?
+            }
+        }
+        writer.write_image_data(raster.as_bytes())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Encodes the frames as an animated GIF, with the colour table described in the module documentation.
+pub fn write_gif(
+    frames: &[AnimationFrame],
+    options: AnimationOptions,
+    writer: impl Write,
+) -> Result<(), AnimationError> {
+    let rasters = render_frames(frames, options)?;
+    let (width, height) = (rasters[0].width, rasters[0].height);
+    let (Ok(gif_width), Ok(gif_height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(AnimationError::TooLarge(width, height));
+    };
+
+    let mut table = base_palette(frames[0].project);
+    table.truncate(GIF_COLORS);
+    if let Some(index) = options.transparent_index {
+        if table.len() <= index as usize {
+            table.resize(index as usize + 1, [0; 3]);
+        }
+    }
+    // The first index of each colour, so repeated palette colours all export as the first of them. The transparent
+    // index is left out, so opaque pixels never land on it, even when its entry has their colour.
+    let mut lookup: AHashMap<[u8; 3], u8> = AHashMap::new();
+    for (index, color) in table.iter().enumerate().rev() {
+        if Some(index as u8) != options.transparent_index {
+            lookup.insert(*color, index as u8);
+        }
+    }
+    let flatten = options
+        .raster
+        .background
+        .map(|[r, g, b, _]| [r, g, b])
+        .unwrap_or([0; 3]);
+
+    let mut indexed = Vec::with_capacity(rasters.len());
+    for raster in &rasters {
+        let mut indices = Vec::with_capacity(raster.pixels.len());
+        for pixel in &raster.pixels {
+            let color = match (pixel[3] >= 128, options.transparent_index) {
+                (true, _) => [pixel[0], pixel[1], pixel[2]],
+                (false, Some(index)) => {
+                    indices.push(index);
+                    continue;
+                }
+                (false, None) => flatten,
+            };
+            let index = match lookup.get(&color) {
+                Some(index) => *index,
+                None if table.len() < GIF_COLORS => {
+                    table.push(color);
+                    let index = (table.len() - 1) as u8;
+                    lookup.insert(color, index);
+                    index
+                }
+                None => {
+                    let index = nearest_gif_color(&table, color, options.transparent_index);
+                    lookup.insert(color, index);
+                    index
+                }
+            };
+            indices.push(index);
+        }
+        indexed.push(indices);
+    }
+
+    let flat: Vec<u8> = table.iter().flatten().copied().collect();
+    let mut encoder = ::gif::Encoder::new(writer, gif_width, gif_height, &flat)?;
+    match options.plays {
+        0 => encoder.set_repeat(::gif::Repeat::Infinite)?,
+        1 => {}
+        // The loop count is how many times the animation repeats after the first play.
+        plays => encoder.set_repeat(::gif::Repeat::Finite(plays - 1))?,
+    }
+    for (frame, indices) in frames.iter().zip(indexed) {
+        let centis = (frame.duration.as_millis() + 5) / 10;
+        encoder.write_frame(&::gif::Frame {
+            width: gif_width,
+            height: gif_height,
+            delay: u16::try_from(centis).unwrap_or(u16::MAX),
+            // Without clearing, transparent pixels would show the previous frame through them.
+            dispose: ::gif::DisposalMethod::Background,
+            transparent: options.transparent_index,
+            buffer: indices.into(),
+            ..Default::default()
+        })?;
+    }
+    Ok(())
+}
+
+/// The index of the colour in `table` nearest to `color` in Oklab, other than the transparent one.
+fn nearest_gif_color(table: &[[u8; 3]], color: [u8; 3], transparent: Option<u8>) -> u8 {
+    let oklab = |[r, g, b]: [u8; 3]| Color::from_color(Srgb::new(r, g, b).into_format::<f32>());
+    let color = oklab(color);
+    table
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| Some(*index as u8) != transparent)
+        .map(|(index, entry)| (index, delta_e(color, oklab(*entry))))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(index, _)| index as u8)
+}
+
+/// Exports the frames as an animated PNG at `path`.
+#[instrument(skip(frames), err)]
+pub fn save_apng(
+    frames: &[AnimationFrame],
+    options: AnimationOptions,
+    path: impl AsRef<Path> + std::fmt::Debug,
+) -> Result<(), AnimationError> {
+    let file = std::fs::File::create(path)?;
+    write_apng(frames, options, std::io::BufWriter::new(file))
+}
+
+/// Exports the frames as an animated GIF at `path`.
+#[instrument(skip(frames), err)]
+pub fn save_gif(
+    frames: &[AnimationFrame],
+    options: AnimationOptions,
+    path: impl AsRef<Path> + std::fmt::Debug,
+) -> Result<(), AnimationError> {
+    let file = std::fs::File::create(path)?;
+    write_gif(frames, options, std::io::BufWriter::new(file))
+}
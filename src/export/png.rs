@@ -0,0 +1,53 @@
+//! PNG export.
+use std::io::Write;
+use std::path::Path;
+
+use thiserror::Error;
+use tracing::instrument;
+
+use super::{rasterize, Raster, RasterOptions};
+use crate::app::ProjectV2;
+
+#[derive(Debug, Error)]
+pub enum PngExportError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Encoding(#[from] ::png::EncodingError),
+    #[error("The image would be empty!")]
+    Empty,
+}
+
+/// Encodes a raster as an 8 bit RGBA PNG.
+pub fn write_raster(raster: &Raster, writer: impl Write) -> Result<(), PngExportError> {
+    if raster.width == 0 || raster.height == 0 {
+        return Err(PngExportError::Empty);
+    }
+    let mut encoder = ::png::Encoder::new(writer, raster.width, raster.height);
+    encoder.set_color(::png::ColorType::Rgba);
+    encoder.set_depth(::png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(raster.as_bytes())?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Rasterises the project and encodes it as an 8 bit RGBA PNG.
+pub fn write_png(
+    project: &ProjectV2,
+    options: RasterOptions,
+    writer: impl Write,
+) -> Result<(), PngExportError> {
+    write_raster(&rasterize(project, options), writer)
+}
+
+/// Rasterises the project and saves it as an 8 bit RGBA PNG at `path`.
+#[instrument(skip(project), err)]
+pub fn save_png(
+    project: &ProjectV2,
+    options: RasterOptions,
+    path: impl AsRef<Path> + std::fmt::Debug,
+) -> Result<(), PngExportError> {
+    let file = std::fs::File::create(path)?;
+    write_png(project, options, std::io::BufWriter::new(file))
+}
@@ -0,0 +1,389 @@
+//! Hexagonal grid coordinates.
+//!
+//! Hexil's hexagons are "flat topped", laid out in columns, with every odd column shifted half a tile down (towards
+//! increasing rows). This is the "odd-q" layout, and it matches `transform_one` in `canvas_vert.glsl`. Tiles are stored
+//! row major, so the tile at `OffsetCoord { col, row }` lives at `row * width + col` in a `CanvasIndices`.
+//!
+//! Three coordinate systems are provided, and converting between them is always lossless:
+//! - `OffsetCoord`, the column and row of a tile, which is what storage and rendering use.
+//! - `AxialCoord`, which makes neighbours and lines trivial.
+//! - `CubeCoord`, axial with the redundant third axis spelled out, which makes distances and rotations trivial.
+use smallvec::SmallVec;
+
+use crate::app::{CanvasSize, GridType};
+
+pub mod picking;
+
+/// The width of a hexagonal tile divided by its height. Hexagons are regular, so this is `2 / sqrt(3)`.
+pub const HEX_ASPECT: f64 = 1.154_700_538_379_251_5;
+
+/// The width of a tile on a grid of the given type, as a multiple of its height.
+pub fn tile_aspect(gridtype: GridType) -> f64 {
+    match gridtype {
+        GridType::Square => 1.0,
+        GridType::Hexagonal => HEX_ASPECT,
+    }
+}
+
+/// The distance between the centres of neighbouring columns, as a multiple of the tile height. Hexagonal columns
+/// interlock, so they are closer together than the tiles are wide.
+pub fn column_spacing(gridtype: GridType) -> f64 {
+    match gridtype {
+        GridType::Square => 1.0,
+        GridType::Hexagonal => 0.75 * HEX_ASPECT,
+    }
+}
+
+/// A tile position as a column and row, in the odd-q layout. Can lie outside of any particular canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct OffsetCoord {
+    pub col: i32,
+    pub row: i32,
+}
+
+/// A tile position in axial coordinates. `q` points along the columns, `r` points down and to the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AxialCoord {
+    pub q: i32,
+    pub r: i32,
+}
+
+/// A tile position in cube coordinates. Always satisfies `q + r + s == 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CubeCoord {
+    q: i32,
+    r: i32,
+    s: i32,
+}
+
+/// A point in continuous cube space, used for picking and for drawing lines between tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FractionalCube {
+    pub q: f64,
+    pub r: f64,
+    pub s: f64,
+}
+
+/// The six directions out of a hexagon, starting from straight down and going anticlockwise on screen.
+/// The index of a direction in `CubeCoord::DIRECTIONS` is its index here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HexDirection {
+    Down,
+    DownRight,
+    UpRight,
+    Up,
+    UpLeft,
+    DownLeft,
+}
+
+impl HexDirection {
+    pub const ALL: [HexDirection; 6] = [
+        HexDirection::Down,
+        HexDirection::DownRight,
+        HexDirection::UpRight,
+        HexDirection::Up,
+        HexDirection::UpLeft,
+        HexDirection::DownLeft,
+    ];
+
+    /// The offset one step in this direction.
+    pub const fn offset(self) -> CubeCoord {
+        CubeCoord::DIRECTIONS[self as usize]
+    }
+
+    pub const fn opposite(self) -> HexDirection {
+        Self::ALL[(self as usize + 3) % 6]
+    }
+}
+
+impl OffsetCoord {
+    pub const fn new(col: i32, row: i32) -> Self {
+        Self { col, row }
+    }
+
+    /// Whether this tile is in an odd column, and so is shifted half a tile down.
+    pub const fn is_shifted(self) -> bool {
+        self.col & 1 == 1
+    }
+
+    /// Whether this tile lies on a canvas of the given size.
+    pub fn is_within(self, size: CanvasSize) -> bool {
+        self.col >= 0
+            && self.row >= 0
+            && (self.col as u64) < size.width
+            && (self.row as u64) < size.height
+    }
+
+    /// The position of this tile in a row major `CanvasIndices` for a canvas of the given size, or `None` if the tile
+    /// isn't on the canvas.
+    pub fn to_index(self, size: CanvasSize) -> Option<usize> {
+        if !self.is_within(size) {
+            return None;
+        }
+        usize::try_from(self.row as u64 * size.width + self.col as u64).ok()
+    }
+
+    /// The tile at `index` in a row major `CanvasIndices` for a canvas of the given size, or `None` if `index` is past
+    /// the end of the canvas.
+    pub fn from_index(index: usize, size: CanvasSize) -> Option<Self> {
+        let index = index as u64;
+        if size.width == 0 || index >= size.area() {
+            return None;
+        }
+        Some(Self {
+            col: i32::try_from(index % size.width).ok()?,
+            row: i32::try_from(index / size.width).ok()?,
+        })
+    }
+}
+
+/// Which tiles count as touching on a square grid. Tiles on hexagonal grids always touch exactly six others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SquareConnectivity {
+    /// Only tiles sharing an edge touch.
+    #[default]
+    Four,
+    /// Tiles sharing a corner touch too.
+    Eight,
+}
+
+impl OffsetCoord {
+    /// Every tile touching this one on a grid of the given type, whether or not they are on any particular canvas.
+    pub fn adjacent(
+        self,
+        gridtype: GridType,
+        connectivity: SquareConnectivity,
+    ) -> SmallVec<[OffsetCoord; 8]> {
+        const EDGES: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+        const CORNERS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+        match (gridtype, connectivity) {
+            (GridType::Hexagonal, _) => CubeCoord::from(self)
+                .neighbors()
+                .into_iter()
+                .map(OffsetCoord::from)
+                .collect(),
+            (GridType::Square, SquareConnectivity::Four) => EDGES
+                .iter()
+                .map(|(dc, dr)| OffsetCoord::new(self.col + dc, self.row + dr))
+                .collect(),
+            (GridType::Square, SquareConnectivity::Eight) => EDGES
+                .iter()
+                .chain(CORNERS.iter())
+                .map(|(dc, dr)| OffsetCoord::new(self.col + dc, self.row + dr))
+                .collect(),
+        }
+    }
+}
+
+impl AxialCoord {
+    pub const fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+}
+
+impl CubeCoord {
+    /// The offsets to each neighbour, in the order of `HexDirection::ALL`.
+    pub const DIRECTIONS: [CubeCoord; 6] = [
+        CubeCoord { q: 0, r: 1, s: -1 },
+        CubeCoord { q: 1, r: 0, s: -1 },
+        CubeCoord { q: 1, r: -1, s: 0 },
+        CubeCoord { q: 0, r: -1, s: 1 },
+        CubeCoord { q: -1, r: 0, s: 1 },
+        CubeCoord { q: -1, r: 1, s: 0 },
+    ];
+
+    /// Makes a cube coordinate from its first two axes. The third is implied.
+    pub const fn new(q: i32, r: i32) -> Self {
+        Self { q, r, s: -q - r }
+    }
+
+    pub const fn q(self) -> i32 {
+        self.q
+    }
+
+    pub const fn r(self) -> i32 {
+        self.r
+    }
+
+    pub const fn s(self) -> i32 {
+        self.s
+    }
+
+    pub const fn add(self, other: CubeCoord) -> CubeCoord {
+        CubeCoord::new(self.q + other.q, self.r + other.r)
+    }
+
+    pub const fn sub(self, other: CubeCoord) -> CubeCoord {
+        CubeCoord::new(self.q - other.q, self.r - other.r)
+    }
+
+    pub const fn scale(self, factor: i32) -> CubeCoord {
+        CubeCoord::new(self.q * factor, self.r * factor)
+    }
+
+    /// The tile one step away in `direction`.
+    pub const fn neighbor(self, direction: HexDirection) -> CubeCoord {
+        self.add(direction.offset())
+    }
+
+    /// All six adjacent tiles, in the order of `HexDirection::ALL`.
+    pub fn neighbors(self) -> [CubeCoord; 6] {
+        Self::DIRECTIONS.map(|d| self.add(d))
+    }
+
+    /// The number of steps between two tiles.
+    pub const fn distance(self, other: CubeCoord) -> u32 {
+        let d = self.sub(other);
+        (d.q.unsigned_abs() + d.r.unsigned_abs() + d.s.unsigned_abs()) / 2
+    }
+
+    /// Every tile within `radius` steps of this one (including itself), in order of increasing `q`, then `r`.
+    pub fn range(self, radius: u32) -> impl Iterator<Item = CubeCoord> {
+        let n = radius as i32;
+        (-n..=n).flat_map(move |dq| {
+            (i32::max(-n, -dq - n)..=i32::min(n, -dq + n))
+                .map(move |dr| self.add(CubeCoord::new(dq, dr)))
+        })
+    }
+
+    /// Every tile exactly `radius` steps from this one, walking anticlockwise around the ring. A radius of 0 yields
+    /// just this tile.
+    pub fn ring(self, radius: u32) -> impl Iterator<Item = CubeCoord> {
+        let start = self.add(HexDirection::UpLeft.offset().scale(radius as i32));
+        let steps = if radius == 0 { 1 } else { 6 * radius as usize };
+        (0..steps).scan(start, move |tile, i| {
+            let current = *tile;
+            if radius != 0 {
+                *tile = tile.add(Self::DIRECTIONS[i / radius as usize]);
+            }
+            Some(current)
+        })
+    }
+
+    /// Every tile within `radius` steps of this one that lies on a canvas of the given size.
+    pub fn range_within(self, radius: u32, size: CanvasSize) -> impl Iterator<Item = CubeCoord> {
+        self.range(radius)
+            .filter(move |tile| OffsetCoord::from(*tile).is_within(size))
+    }
+
+    /// The straight line of tiles from `self` to `other`, including both ends. Ties are broken consistently by
+    /// nudging the line slightly off the shared edges before rounding.
+    pub fn line_to(self, other: CubeCoord) -> impl Iterator<Item = CubeCoord> {
+        let n = self.distance(other);
+        const NUDGE: FractionalCube = FractionalCube {
+            q: 1e-6,
+            r: 2e-6,
+            s: -3e-6,
+        };
+        let from = FractionalCube::from(self) + NUDGE;
+        let to = FractionalCube::from(other) + NUDGE;
+        (0..=n).map(move |i| {
+            if n == 0 {
+                self
+            } else {
+                from.lerp(to, i as f64 / n as f64).round()
+            }
+        })
+    }
+}
+
+impl FractionalCube {
+    /// Makes a fractional cube coordinate from its first two axes. The third is implied.
+    pub fn new(q: f64, r: f64) -> Self {
+        Self { q, r, s: -q - r }
+    }
+
+    pub fn lerp(self, other: FractionalCube, t: f64) -> FractionalCube {
+        FractionalCube {
+            q: self.q + (other.q - self.q) * t,
+            r: self.r + (other.r - self.r) * t,
+            s: self.s + (other.s - self.s) * t,
+        }
+    }
+
+    /// The tile containing this point.
+    pub fn round(self) -> CubeCoord {
+        let (q, r, s) = (self.q.round(), self.r.round(), self.s.round());
+        let (dq, dr, ds) = ((q - self.q).abs(), (r - self.r).abs(), (s - self.s).abs());
+        if dq > dr && dq > ds {
+            CubeCoord::new((-r - s) as i32, r as i32)
+        } else if dr > ds {
+            CubeCoord::new(q as i32, (-q - s) as i32)
+        } else {
+            CubeCoord::new(q as i32, r as i32)
+        }
+    }
+
+    /// The point in cube space at `(x, y)` in tile units, where the centre of the tile at `OffsetCoord { col, row }`
+    /// is at `(col, row)` for even columns and `(col, row + 0.5)` for odd columns.
+    pub fn from_tile_space(x: f64, y: f64) -> Self {
+        Self::new(x, y - x / 2.0)
+    }
+}
+
+impl std::ops::Add for FractionalCube {
+    type Output = FractionalCube;
+
+    fn add(self, other: FractionalCube) -> FractionalCube {
+        FractionalCube {
+            q: self.q + other.q,
+            r: self.r + other.r,
+            s: self.s + other.s,
+        }
+    }
+}
+
+impl From<CubeCoord> for FractionalCube {
+    fn from(value: CubeCoord) -> Self {
+        Self {
+            q: value.q as f64,
+            r: value.r as f64,
+            s: value.s as f64,
+        }
+    }
+}
+
+impl From<AxialCoord> for CubeCoord {
+    fn from(value: AxialCoord) -> Self {
+        CubeCoord::new(value.q, value.r)
+    }
+}
+
+impl From<CubeCoord> for AxialCoord {
+    fn from(value: CubeCoord) -> Self {
+        AxialCoord {
+            q: value.q,
+            r: value.r,
+        }
+    }
+}
+
+impl From<OffsetCoord> for AxialCoord {
+    fn from(value: OffsetCoord) -> Self {
+        AxialCoord {
+            q: value.col,
+            r: value.row - (value.col - (value.col & 1)) / 2,
+        }
+    }
+}
+
+impl From<AxialCoord> for OffsetCoord {
+    fn from(value: AxialCoord) -> Self {
+        OffsetCoord {
+            col: value.q,
+            row: value.r + (value.q - (value.q & 1)) / 2,
+        }
+    }
+}
+
+impl From<OffsetCoord> for CubeCoord {
+    fn from(value: OffsetCoord) -> Self {
+        AxialCoord::from(value).into()
+    }
+}
+
+impl From<CubeCoord> for OffsetCoord {
+    fn from(value: CubeCoord) -> Self {
+        AxialCoord::from(value).into()
+    }
+}
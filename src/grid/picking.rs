@@ -0,0 +1,63 @@
+//! Mapping between window positions and tiles. Everything here must stay the exact inverse of `transform_one` in
+//! `canvas_vert.glsl`, followed by the camera's `ViewTransform`.
+use crate::app::{CanvasSize, GridType};
+use crate::camera::ViewTransform;
+
+use super::{FractionalCube, OffsetCoord};
+
+/// Converts a position in physical pixels within a window of `window_size` physical pixels to clip space.
+pub fn window_to_clip(cursor: [f64; 2], window_size: [u32; 2]) -> [f64; 2] {
+    [
+        cursor[0] / window_size[0] as f64 * 2.0 - 1.0,
+        cursor[1] / window_size[1] as f64 * 2.0 - 1.0,
+    ]
+}
+
+/// Converts a position in canvas space to tile space, where the centre of the tile at `OffsetCoord { col, row }` is
+/// at `(col, row)`, plus half a tile down for odd columns on hexagonal grids.
+pub fn canvas_to_tile_space(canvas: [f64; 2], size: CanvasSize) -> [f64; 2] {
+    [
+        (canvas[0] + 1.0) * size.width as f64 / 2.0 - 0.5,
+        (canvas[1] + 1.0) * size.height as f64 / 2.0 - 0.5,
+    ]
+}
+
+/// The centre of `tile` in canvas space.
+pub fn tile_center(tile: OffsetCoord, size: CanvasSize, gridtype: GridType) -> [f64; 2] {
+    let shift = match gridtype {
+        GridType::Hexagonal if tile.is_shifted() => 0.5,
+        _ => 0.0,
+    };
+    [
+        -1.0 + (2.0 * tile.col as f64 + 1.0) / size.width as f64,
+        -1.0 + (2.0 * (tile.row as f64 + shift) + 1.0) / size.height as f64,
+    ]
+}
+
+/// The tile containing the point `tile_space` (see `canvas_to_tile_space`), whether or not it is on the canvas.
+pub fn tile_at(tile_space: [f64; 2], gridtype: GridType) -> OffsetCoord {
+    let [x, y] = tile_space;
+    match gridtype {
+        GridType::Hexagonal => FractionalCube::from_tile_space(x, y).round().into(),
+        GridType::Square => OffsetCoord::new((x + 0.5).floor() as i32, (y + 0.5).floor() as i32),
+    }
+}
+
+/// Finds the tile under the cursor, or `None` if there isn't one.
+///
+/// `cursor` is in physical pixels from the top left of the window, `window_size` is the last size sent with
+/// `RenderCommand::WindowResized`, and `view` is the current camera's transform.
+pub fn pick_tile(
+    cursor: [f64; 2],
+    window_size: [u32; 2],
+    size: CanvasSize,
+    gridtype: GridType,
+    view: &ViewTransform,
+) -> Option<OffsetCoord> {
+    if window_size[0] == 0 || window_size[1] == 0 || size.area() == 0 {
+        return None;
+    }
+    let canvas = view.invert(window_to_clip(cursor, window_size));
+    let tile = tile_at(canvas_to_tile_space(canvas, size), gridtype);
+    tile.is_within(size).then_some(tile)
+}
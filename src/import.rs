@@ -0,0 +1,457 @@
+//! Turning reference images into projects. Like exporting, everything here runs entirely on the CPU.
+//!
+//! An image is stretched over the whole canvas, covering exactly what `export::rasterize` would draw for it. Each tile
+//! takes the average of the pixels whose centres fall inside its footprint, averaged in linear light, so on hexagonal
+//! grids every hexagon averages just the pixels under it. Tiles too small to hold the centre of any pixel take the
+//! pixel under their own centre instead. The tile colours are then quantised to a palette, either one that's given or
+//! one generated from the image, and optionally dithered.
+//!
+//! The imported project has a single base colour layer, plus an alpha layer above it if the image had any transparency.
+use std::path::Path;
+
+use ::image::{ImageFormat, RgbaImage};
+use palette::{FromColor, LinSrgb, Srgb};
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::app::palette_edit::{delta_e, nearest_palette_index};
+use crate::app::{
+    CanvasIndices, CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Palette, Project, TileColor,
+};
+use crate::grid::picking::tile_at;
+use crate::grid::{column_spacing, tile_aspect, CubeCoord, HexDirection, OffsetCoord};
+
+/// The image formats that can be imported.
+pub const SUPPORTED_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Bmp,
+    ImageFormat::Qoi,
+];
+
+/// Everything that can go wrong while importing an image.
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Image(#[from] ::image::ImageError),
+    #[error("Only PNG, JPEG, BMP and QOI images can be imported!")]
+    UnsupportedFormat,
+    #[error("The image is empty!")]
+    EmptyImage,
+    #[error("Can't import onto an empty canvas!")]
+    EmptyCanvas,
+    #[error("Canvas size {0}x{1} is too large!")]
+    CanvasTooLarge(u64, u64),
+    #[error("The palette is empty!")]
+    EmptyPalette,
+}
+
+/// Where the colours of an imported project come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteChoice {
+    /// Exactly this palette, in this order.
+    Existing(Palette),
+    /// At most this many colours, found by median cut.
+    MedianCut(usize),
+    /// At most this many colours, found by median cut and then refined by this many rounds of k-means.
+    KMeans { colors: usize, iterations: u32 },
+}
+
+/// How tiles are dithered when their colour falls between palette colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Dither {
+    /// Every tile takes the nearest palette colour.
+    #[default]
+    None,
+    /// Tiles choose between the two nearest palette colours by a fixed threshold pattern: a 4x4 Bayer matrix on square
+    /// grids, and on hexagonal grids a pattern of 7 thresholds where every tile and its neighbours are all different.
+    Ordered,
+    /// Each tile passes what its palette colour got wrong on to the neighbours that haven't been quantised yet, going
+    /// row by row: Floyd-Steinberg on square grids, and its equivalent over the six neighbours on hexagonal grids.
+    ErrorDiffusion,
+}
+
+/// How an image is turned into a project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportOptions {
+    pub size: CanvasSize,
+    pub gridtype: GridType,
+    pub palette: PaletteChoice,
+    pub dither: Dither,
+}
+
+/// Decodes an image, which must be in one of `SUPPORTED_FORMATS`.
+pub fn read_image(data: &[u8]) -> Result<RgbaImage, ImportError> {
+    let format = ::image::guess_format(data).map_err(|_| ImportError::UnsupportedFormat)?;
+    if !SUPPORTED_FORMATS.contains(&format) {
+        return Err(ImportError::UnsupportedFormat);
+    }
+    Ok(::image::load_from_memory_with_format(data, format)?.into_rgba8())
+}
+
+/// Reads and decodes the image at `path`, which must be in one of `SUPPORTED_FORMATS`.
+#[instrument(err)]
+pub fn load_image(path: impl AsRef<Path> + std::fmt::Debug) -> Result<RgbaImage, ImportError> {
+    read_image(&std::fs::read(path)?)
+}
+
+/// Reads the image at `path` and imports it as a project named after the file.
+#[instrument(skip(options), err)]
+pub fn import_file(
+    path: impl AsRef<Path> + std::fmt::Debug,
+    options: &ImportOptions,
+) -> Result<Project, ImportError> {
+    let name = path
+        .as_ref()
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    import_image(name, &load_image(&path)?, options)
+}
+
+/// Imports `image` as a new project.
+pub fn import_image(
+    name: String,
+    image: &RgbaImage,
+    options: &ImportOptions,
+) -> Result<Project, ImportError> {
+    let size = options.size;
+    if image.width() == 0 || image.height() == 0 {
+        return Err(ImportError::EmptyImage);
+    }
+    let Some(area) = size.width.checked_mul(size.height) else {
+        return Err(ImportError::CanvasTooLarge(size.width, size.height));
+    };
+    if area == 0 {
+        return Err(ImportError::EmptyCanvas);
+    }
+    if usize::try_from(area).is_err() || i32::try_from(size.width.max(size.height)).is_err() {
+        return Err(ImportError::CanvasTooLarge(size.width, size.height));
+    }
+
+    let tiles = sample_image(image, size, options.gridtype)?;
+    let palette = match &options.palette {
+        PaletteChoice::Existing(palette) => palette.clone(),
+        PaletteChoice::MedianCut(colors) => median_cut(&covered_colors(&tiles), *colors),
+        PaletteChoice::KMeans { colors, iterations } => {
+            k_means(&covered_colors(&tiles), *colors, *iterations)
+        }
+    };
+    if palette.is_empty() {
+        return Err(ImportError::EmptyPalette);
+    }
+    let indices = quantize(&tiles, &palette, size, options.gridtype, options.dither);
+
+    let mut project = Project::new(name, size, options.gridtype);
+    project.push_layer(LayerV2::new(
+        None,
+        size,
+        LayerV1Canvas::BaseColor {
+            palette: palette.into(),
+            canvas: indices.into(),
+        },
+    ));
+    if tiles.iter().any(|tile| tile.alpha < 1.0) {
+        project.push_layer(LayerV2::new(
+            None,
+            size,
+            LayerV1Canvas::Alpha(tiles.iter().map(|tile| tile.alpha).collect()),
+        ));
+    }
+    Ok(project)
+}
+
+/// The area an image is stretched over, in tile space (see `grid::picking::canvas_to_tile_space`), as its top left
+/// and bottom right corners. This is everything `export::rasterize` draws.
+fn footprint(size: CanvasSize, gridtype: GridType) -> [[f64; 2]; 2] {
+    let half_width = tile_aspect(gridtype) / column_spacing(gridtype) / 2.0;
+    let shift = match gridtype {
+        GridType::Hexagonal if size.width > 1 => 0.5,
+        _ => 0.0,
+    };
+    [
+        [-half_width, -0.5],
+        [
+            size.width as f64 - 1.0 + half_width,
+            size.height as f64 - 0.5 + shift,
+        ],
+    ]
+}
+
+/// The colour of every tile of a canvas of the given size and grid type with `image` stretched over it, in the order of
+/// a `CanvasIndices`. Fails if there isn't memory for a canvas that large.
+pub fn sample_image(
+    image: &RgbaImage,
+    size: CanvasSize,
+    gridtype: GridType,
+) -> Result<Vec<TileColor>, ImportError> {
+    let [[left, top], [right, bottom]] = footprint(size, gridtype);
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let linear = |pixel: &::image::Rgba<u8>| {
+        let [r, g, b, a] = pixel.0;
+        let color: LinSrgb = Srgb::new(r, g, b).into_format::<f32>().into_linear();
+        (color, a as f32 / 255.0)
+    };
+
+    // Premultiplied linear colour, alpha, and pixel count. This is the most kept for any tile during an import, so
+    // it's where canvases too large to fit in memory are caught.
+    let too_large = || ImportError::CanvasTooLarge(size.width, size.height);
+    let area = size
+        .width
+        .checked_mul(size.height)
+        .and_then(|area| usize::try_from(area).ok())
+        .ok_or_else(too_large)?;
+    let mut sums = Vec::new();
+    sums.try_reserve_exact(area).map_err(|_| too_large())?;
+    sums.resize(area, (LinSrgb::new(0.0, 0.0, 0.0), 0.0, 0u32));
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let tile_space = [
+            left + (x as f64 + 0.5) / width * (right - left),
+            top + (y as f64 + 0.5) / height * (bottom - top),
+        ];
+        let Some(index) = tile_at(tile_space, gridtype).to_index(size) else {
+            continue;
+        };
+        let (color, alpha) = linear(pixel);
+        let sum = &mut sums[index];
+        sum.0 += color * alpha;
+        sum.1 += alpha;
+        sum.2 += 1;
+    }
+
+    Ok(sums
+        .iter()
+        .enumerate()
+        .map(|(index, &(color, alpha, count))| {
+            let (color, alpha) = if count == 0 {
+                let tile =
+                    OffsetCoord::from_index(index, size).expect("Every index is on the canvas.");
+                let shift = match gridtype {
+                    GridType::Hexagonal if tile.is_shifted() => 0.5,
+                    _ => 0.0,
+                };
+                let pixel = |centre: f64, low: f64, high: f64, pixels: u32| {
+                    (((centre - low) / (high - low) * pixels as f64).floor() as i64)
+                        .clamp(0, pixels as i64 - 1) as u32
+                };
+                linear(image.get_pixel(
+                    pixel(tile.col as f64, left, right, image.width()),
+                    pixel(tile.row as f64 + shift, top, bottom, image.height()),
+                ))
+            } else if alpha > 0.0 {
+                (color / alpha, alpha / count as f32)
+            } else {
+                (color, 0.0)
+            };
+            TileColor {
+                color: Color::from_color(color),
+                alpha,
+            }
+        })
+        .collect())
+}
+
+/// The colours of the tiles that are at all covered, or of every tile if none are.
+fn covered_colors(tiles: &[TileColor]) -> Vec<Color> {
+    let covered: Vec<Color> = tiles
+        .iter()
+        .filter(|tile| tile.is_covered())
+        .map(|tile| tile.color)
+        .collect();
+    if covered.is_empty() {
+        tiles.iter().map(|tile| tile.color).collect()
+    } else {
+        covered
+    }
+}
+
+/// One of the three Oklab channels.
+fn channel(color: Color, axis: usize) -> f32 {
+    [color.l, color.a, color.b][axis]
+}
+
+/// The average of `colors`, which must not be empty.
+fn mean(colors: &[Color]) -> Color {
+    let sum = colors
+        .iter()
+        .fold(Color::new(0.0, 0.0, 0.0), |sum, color| sum + *color);
+    sum / colors.len() as f32
+}
+
+/// A palette of at most `count` colours representing `colors`, from darkest to lightest. Repeatedly splits whichever
+/// group of colours spreads furthest along any Oklab axis in half at its median along that axis, and then averages
+/// each group.
+pub fn median_cut(colors: &[Color], count: usize) -> Palette {
+    if colors.is_empty() || count == 0 {
+        return Palette::new();
+    }
+    let mut groups = vec![colors.to_vec()];
+    while groups.len() < count {
+        let widest = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(group, colors)| {
+                (0..3).map(move |axis| {
+                    let values = colors.iter().map(|color| channel(*color, axis));
+                    let spread =
+                        values.clone().fold(f32::MIN, f32::max) - values.fold(f32::MAX, f32::min);
+                    (group, axis, spread)
+                })
+            })
+            .filter(|(_, _, spread)| *spread > 0.0)
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((group, axis, _)) = widest else {
+            break;
+        };
+        let mut lower = groups.swap_remove(group);
+        lower.sort_by(|a, b| channel(*a, axis).total_cmp(&channel(*b, axis)));
+        let upper = lower.split_off(lower.len() / 2);
+        groups.push(lower);
+        groups.push(upper);
+    }
+    let mut palette: Palette = groups.iter().map(|group| mean(group)).collect();
+    palette.sort_by(|a, b| a.l.total_cmp(&b.l));
+    palette
+}
+
+/// A palette of at most `count` colours representing `colors`, from darkest to lightest. Starts from `median_cut`, and
+/// then moves every palette colour to the average of the colours nearest to it, up to `iterations` times or until
+/// nothing moves.
+pub fn k_means(colors: &[Color], count: usize, iterations: u32) -> Palette {
+    let mut palette = median_cut(colors, count);
+    for _ in 0..iterations {
+        let mut groups = vec![Vec::new(); palette.len()];
+        for color in colors {
+            if let Some(index) = nearest_palette_index(&palette, *color) {
+                groups[index as usize].push(*color);
+            }
+        }
+        let next: Palette = groups
+            .iter()
+            .zip(&palette)
+            .map(|(group, old)| if group.is_empty() { *old } else { mean(group) })
+            .collect();
+        if next == palette {
+            break;
+        }
+        palette = next;
+    }
+    palette.sort_by(|a, b| a.l.total_cmp(&b.l));
+    palette
+}
+
+/// The 4x4 Bayer matrix, as thresholds out of 16.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The threshold for `tile` in ordered dithering, from 0 to 1.
+fn ordered_threshold(tile: OffsetCoord, gridtype: GridType) -> f32 {
+    match gridtype {
+        GridType::Square => {
+            let rank = BAYER[tile.row.rem_euclid(4) as usize][tile.col.rem_euclid(4) as usize];
+            (rank as f32 + 0.5) / 16.0
+        }
+        GridType::Hexagonal => {
+            // Steps to each of the six neighbours change `q + 3r` by a different amount modulo 7, so a tile and its
+            // neighbours always have all 7 thresholds between them.
+            let cube = CubeCoord::from(tile);
+            ((cube.q() + 3 * cube.r()).rem_euclid(7) as f32 + 0.5) / 7.0
+        }
+    }
+}
+
+/// The palette index for `color` in ordered dithering with the given threshold: the nearest palette colour, or the
+/// second nearest if `color` is more than `threshold` of the way towards it.
+fn ordered_index(palette: &[Color], color: Color, threshold: f32) -> u32 {
+    let mut nearest: Vec<u32> = (0..palette.len() as u32).collect();
+    nearest.sort_by(|a, b| {
+        delta_e(color, palette[*a as usize]).total_cmp(&delta_e(color, palette[*b as usize]))
+    });
+    let [first, second] = match nearest[..] {
+        [first, second, ..] => [first, second],
+        _ => return nearest[0],
+    };
+    let (a, b) = (palette[first as usize], palette[second as usize]);
+    let (towards, along) = (color - a, b - a);
+    let length = along.l * along.l + along.a * along.a + along.b * along.b;
+    if length <= 0.0 {
+        return first;
+    }
+    let position = (towards.l * along.l + towards.a * along.a + towards.b * along.b) / length;
+    if position > threshold {
+        second
+    } else {
+        first
+    }
+}
+
+/// The neighbours of `tile` that error diffusion passes error on to, with their share of it before normalising, in no
+/// particular order. Only neighbours after `tile` in the order of a `CanvasIndices` can still take any.
+fn diffusion_weights(tile: OffsetCoord, gridtype: GridType) -> Vec<(OffsetCoord, f32)> {
+    let neighbors: Vec<(OffsetCoord, f32)> = match gridtype {
+        GridType::Square => [((1, 0), 7.0), ((-1, 1), 3.0), ((0, 1), 5.0), ((1, 1), 1.0)]
+            .into_iter()
+            .map(|((col, row), weight)| (OffsetCoord::new(tile.col + col, tile.row + row), weight))
+            .collect(),
+        GridType::Hexagonal => [
+            (HexDirection::Down, 5.0),
+            (HexDirection::DownRight, 4.0),
+            (HexDirection::UpRight, 4.0),
+            (HexDirection::DownLeft, 3.0),
+        ]
+        .into_iter()
+        .map(|(direction, weight)| (CubeCoord::from(tile).neighbor(direction).into(), weight))
+        .collect(),
+    };
+    neighbors
+        .into_iter()
+        .filter(|(neighbor, _)| (neighbor.row, neighbor.col) > (tile.row, tile.col))
+        .collect()
+}
+
+/// Quantises the colour of every tile to an index into `palette`, which must not be empty. Error diffusion carries
+/// error from each tile in proportion to its alpha, so that transparent tiles don't affect the rest.
+pub fn quantize(
+    tiles: &[TileColor],
+    palette: &[Color],
+    size: CanvasSize,
+    gridtype: GridType,
+    dither: Dither,
+) -> CanvasIndices {
+    let nearest = |color| nearest_palette_index(palette, color).unwrap_or(0);
+    match dither {
+        Dither::None => tiles.iter().map(|tile| nearest(tile.color)).collect(),
+        Dither::Ordered => tiles
+            .iter()
+            .enumerate()
+            .map(|(index, tile)| match OffsetCoord::from_index(index, size) {
+                Some(position) => {
+                    ordered_index(palette, tile.color, ordered_threshold(position, gridtype))
+                }
+                None => nearest(tile.color),
+            })
+            .collect(),
+        Dither::ErrorDiffusion => {
+            let mut colors: Vec<Color> = tiles.iter().map(|tile| tile.color).collect();
+            let mut indices = CanvasIndices::with_capacity(tiles.len());
+            for index in 0..colors.len() {
+                let chosen = nearest(colors[index]);
+                indices.push(chosen);
+                let Some(tile) = OffsetCoord::from_index(index, size) else {
+                    continue;
+                };
+                let error = (colors[index] - palette[chosen as usize]) * tiles[index].alpha;
+                let weights: Vec<(usize, f32)> = diffusion_weights(tile, gridtype)
+                    .into_iter()
+                    .filter_map(|(neighbor, weight)| Some((neighbor.to_index(size)?, weight)))
+                    .collect();
+                let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+                for (neighbor, weight) in weights {
+                    colors[neighbor] += error * (weight / total);
+                }
+            }
+            indices
+        }
+    }
+}
@@ -1,5 +1,8 @@
 #![windows_subsystem = "windows"]
 
+use hexil::app::{
+    self, CanvasSize, CanvasSnapshot, Color, GridType, LayerV1Canvas, LayerV2, Project,
+};
 use hexil::logging;
 use hexil::render;
 use hexil::window;
@@ -10,14 +13,45 @@ fn main() {
     use window::*;
     let _guard = logging::init_tracing_to_file();
 
+    // The project to open can be given on the command line. Otherwise, start with a blank canvas.
+    let project = match std::env::args_os().nth(1) {
+        Some(path) => match app::open_project(&path) {
+            Ok(project) => project,
+            Err(e) => {
+                error!("Couldn't open {:?}: {}", path, e);
+                return;
+            }
+        },
+        None => blank_project(),
+    };
+    let canvas = CanvasSnapshot::of_project(&project);
+
     let eloop = make_event_loop().unwrap();
     let window = std::sync::Arc::new(make_window("Hexil", &eloop).unwrap());
     let (render_command_sender, render_rec) = std::sync::mpsc::channel::<RenderCommand>();
     let win = window.clone();
-    let render_thread = std::thread::spawn(|| render_thread(win, render_rec));
-    let _eprox = eloop.create_proxy();
-    run_event_loop(eloop, render_command_sender.clone()).unwrap();
+    let eprox = eloop.create_proxy();
+    let render_thread = std::thread::spawn(|| render_thread(win, eprox, canvas, render_rec));
+    run_event_loop(eloop, window, render_command_sender.clone(), project).unwrap();
     if let Err(e) = render_thread.join() {
         error!("Render thread join error: {:#?}", e);
     }
 }
+
+/// A project with a single base colour layer, entirely white.
+fn blank_project() -> Project {
+    let size = CanvasSize {
+        width: 20,
+        height: 15,
+    };
+    let mut project = Project::new("Untitled".to_owned(), size, GridType::Hexagonal);
+    project.push_layer(LayerV2::new(
+        None,
+        size,
+        LayerV1Canvas::BaseColor {
+            palette: vec![Color::new(1.0, 0.0, 0.0)].into(),
+            canvas: vec![0; size.area() as usize].into(),
+        },
+    ));
+    project
+}
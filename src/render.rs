@@ -1,10 +1,13 @@
 mod buffer_make;
 mod command_buffers;
 mod framebuffer;
+mod indices_patch;
 mod init_renderer_state;
 mod instance_create;
 mod lib_select;
 mod make_swapchain;
+mod offscreen;
+mod overlay;
 mod pipeline;
 mod queue_device_creation;
 mod render_pass;
@@ -17,7 +20,9 @@ use tracing::{error, instrument, trace};
 
 use try_log::log_tries;
 use vk::memory::allocator::MemoryAllocator;
-use vk::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
+use vk::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState,
+};
 use vk::pipeline::graphics::input_assembly::InputAssemblyState;
 use vk::pipeline::graphics::multisample::MultisampleState;
 use vk::pipeline::graphics::rasterization::RasterizationState;
@@ -33,24 +38,33 @@ use vk::swapchain::SwapchainPresentInfo;
 use vk::sync::GpuFuture;
 use vk::{Validated, VulkanError};
 use vulkano as vk;
+use winit::event_loop::EventLoopProxy;
 use winit::window::Window;
 mod types;
 
 mod consts;
 
 mod renderer_error;
+pub use indices_patch::*;
+pub use overlay::OverlaySettings;
 pub use renderer_error::*;
 
-use crate::render::canvas_manager::CanvasBuffersManager;
+use crate::app::{CanvasSnapshot, GridType};
+use crate::camera::Camera;
+use crate::render::canvas_manager::{canvas_dimensions, CanvasBuffersManager};
+use crate::window::WindowCommand;
 
+use self::overlay::OverlayState;
 use self::types::Position;
 
 /// Holds the entire state of Hexil's rendering system. It probably doesn't make sense to ever make more than one of this, but technically nothing is stopping you.
 #[allow(dead_code)]
 pub struct Renderer {
     instance: Arc<vk::instance::Instance>,
-    window: Arc<winit::window::Window>,
-    surface: Arc<vk::swapchain::Surface>,
+    /// The window being drawn to, or `None` for a headless renderer.
+    window: Option<Arc<winit::window::Window>>,
+    /// The surface of `window`, or `None` for a headless renderer.
+    surface: Option<Arc<vk::swapchain::Surface>>,
     physical_device: Arc<vk::device::physical::PhysicalDevice>,
     logical_device: Arc<vk::device::Device>,
     command_allocator: vk::command_buffer::allocator::StandardCommandBufferAllocator,
@@ -75,16 +89,40 @@ mod frag {
     }
 }
 
+mod overlay_vert {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/overlay_vert.glsl",
+    }
+}
+
+mod overlay_frag {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/overlay_frag.glsl",
+    }
+}
+
 impl Renderer {
     #[instrument(skip_all, err)]
     #[log_tries(tracing::error)]
+    /// Builds a pipeline drawing one tile per instance in subpass `subpass` of `render_pass`, with the shaders for
+    /// either the canvas or the overlay.
     fn make_pipeline(
         &self,
         vert: Arc<vk::shader::ShaderModule>,
         frag: Arc<vk::shader::ShaderModule>,
         render_pass: Arc<vk::render_pass::RenderPass>,
+        subpass: u32,
         viewport: &Viewport,
+        set_layout: Arc<vk::descriptor_set::layout::DescriptorSetLayout>,
+        gridtype: GridType,
     ) -> Result<Arc<vk::pipeline::GraphicsPipeline>, RendererError> {
+        // The `HEXAGONAL` constant in the vertex shaders picks how tiles are placed.
+        let vert = vert.specialize(ahash::HashMap::from_iter([(
+            0,
+            vk::shader::SpecializationConstant::Bool(gridtype == GridType::Hexagonal),
+        )]))?;
         // A Vulkan shader can in theory contain multiple entry points, so we have to specify
         // which one.
         let vs = vert
@@ -101,13 +139,15 @@ impl Renderer {
             PipelineShaderStageCreateInfo::new(fs),
         ];
 
-        let layout = PipelineLayout::new(
-            self.logical_device.clone(),
-            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                .into_pipeline_layout_create_info(self.logical_device.clone())?,
-        )?;
+        let mut layout_create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(self.logical_device.clone())?;
+        // The canvas descriptor set is allocated by `CanvasBuffersManager`, so the pipeline must use its layout rather
+        // than one reflected from the shaders.
+        layout_create_info.set_layouts = vec![set_layout];
+        let layout = PipelineLayout::new(self.logical_device.clone(), layout_create_info)?;
 
-        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        let subpass = Subpass::from(render_pass.clone(), subpass)
+            .ok_or(RendererError::NoSubpassesSpecifiedForRenderpass)?;
 
         let dynamic_state = ahash::HashSet::from_iter([vk::pipeline::DynamicState::Viewport]);
 
@@ -133,9 +173,21 @@ impl Renderer {
                 // Ignore these for now.
                 rasterization_state: Some(RasterizationState::default()),
                 multisample_state: Some(MultisampleState::default()),
+                // Tiles and outlines are drawn over the framebuffer with straight alpha, leaving it premultiplied. This
+                // must match `export::tile_pixel`.
                 color_blend_state: Some(ColorBlendState::with_attachment_states(
                     subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
+                    ColorBlendAttachmentState {
+                        blend: Some(AttachmentBlend {
+                            src_color_blend_factor: BlendFactor::SrcAlpha,
+                            dst_color_blend_factor: BlendFactor::OneMinusSrcAlpha,
+                            color_blend_op: BlendOp::Add,
+                            src_alpha_blend_factor: BlendFactor::One,
+                            dst_alpha_blend_factor: BlendFactor::OneMinusSrcAlpha,
+                            alpha_blend_op: BlendOp::Add,
+                        }),
+                        ..Default::default()
+                    },
                 )),
                 // This graphics pipeline object concerns the first pass of the render pass.
                 subpass: Some(subpass.into()),
@@ -147,21 +199,38 @@ impl Renderer {
 }
 
 /// A command that can be sent to the main render thread.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum RenderCommand {
     Redraw,
     /// The renderer must handle the window being resized to the new dimensions (in physical pixels).
     WindowResized([u32; 2]),
+    /// The canvas should be drawn through this camera from now on.
+    CameraChanged(Camera),
     /// The renderer should shut down gracefully
     Shutdown,
     CanvasSettingsChanged,
-    CanvasIndicesChanged,
+    /// A different canvas, or one with a different size, palette length or set of layers, should be drawn from now on.
+    CanvasReplaced(CanvasSnapshot),
+    /// Some tiles of one layer of the canvas were given new values. Only those tiles are uploaded.
+    CanvasIndicesChanged {
+        layer: usize,
+        patch: IndicesPatch,
+    },
+    /// The overlay should be drawn with these settings from now on. This is how the grid and highlights are toggled.
+    OverlayChanged(OverlaySettings),
+    /// The cursor moved onto the tile at this position, or off the canvas.
+    HoveredTileChanged(Option<usize>),
+    /// Exactly the tiles at these positions are selected now.
+    SelectionChanged(Vec<usize>),
 }
 
-/// Runs Hexil's rendering system. Should be run in it's own dedicated OS thread.
+/// Runs Hexil's rendering system. Should be run in it's own dedicated OS thread. `window_proxy` is used to tell the
+/// event loop about the canvas being drawn, which starts out as `canvas`.
 #[instrument(skip_all, err)]
 pub fn render_thread(
     window: Arc<Window>,
+    window_proxy: EventLoopProxy<WindowCommand>,
+    canvas: CanvasSnapshot,
     render_command_channel: std::sync::mpsc::Receiver<RenderCommand>,
 ) -> Result<(), renderer_error::RendererError> {
     use try_log::try_or_err;
@@ -173,12 +242,37 @@ pub fn render_thread(
         return Err(e);
     }
     let renderer = try_or_err!(renderer);
-    let manager = CanvasBuffersManager::new(&renderer, 20, 15, 7)?;
+    let mut canvas_size = canvas.size;
+    let mut gridtype = canvas.gridtype;
+    let [width, height] = canvas_dimensions(canvas_size)?;
+    let mut manager = CanvasBuffersManager::new(&renderer, width, height, 1, 1)?;
+    try_or_err!(manager.transfer_canvas(&renderer, &canvas));
+    let mut overlay = OverlayState::default();
+    let mut selection: Vec<usize> = Vec::new();
+    try_or_err!(manager.write_selection(&selection));
+    try_or_err!(manager
+        .upload(&renderer, &renderer.transfer_queue)
+        .and_then(|copy| run_upload(&renderer, copy)));
+    if window_proxy
+        .send_event(WindowCommand::CanvasChanged {
+            size: canvas_size,
+            gridtype,
+        })
+        .is_err()
+    {
+        // The event loop is gone, so there's nobody left to draw for.
+        return Ok(());
+    }
 
+    let mut camera = Camera::default();
+    let mut window_size: [u32; 2] = window.inner_size().into();
     let mut swapchain_wrapper = try_or_err!(SwapchainWrapper::make_canvas_swapchain(
         &renderer,
-        window.inner_size().into(),
-        &manager
+        window_size,
+        &manager,
+        &camera.view_transform(window_size, canvas_size, gridtype),
+        gridtype,
+        overlay,
     ));
 
     loop {
@@ -226,39 +320,106 @@ pub fn render_thread(
                 return Err(e.into());
             }
             Ok(RenderCommand::WindowResized(new_size)) => {
+                window_size = new_size;
+                let view = camera.view_transform(window_size, canvas_size, gridtype);
                 swapchain_wrapper = try_or_err!(if swapchain_wrapper.is_some() {
                     swapchain_wrapper
                         .unwrap()
-                        .rebuild(&renderer, new_size, &manager)
+                        .rebuild(&renderer, new_size, &manager, &view)
                 } else {
-                    SwapchainWrapper::make_canvas_swapchain(&renderer, new_size, &manager)
+                    SwapchainWrapper::make_canvas_swapchain(
+                        &renderer, new_size, &manager, &view, gridtype, overlay,
+                    )
                 });
             }
+            Ok(RenderCommand::CameraChanged(new_camera)) => {
+                camera = new_camera;
+                if let Some(swapchain_wrapper) = &mut swapchain_wrapper {
+                    let view = camera.view_transform(window_size, canvas_size, gridtype);
+                    try_or_err!(swapchain_wrapper.set_view(&renderer, &manager, &view));
+                }
+            }
             Ok(RenderCommand::Shutdown) => return Ok(()),
             Ok(RenderCommand::CanvasSettingsChanged) => {
-                if let Some(swapchain_wrapper) = &swapchain_wrapper {
-                    let execution = vk::sync::now(renderer.logical_device.clone())
-                        .then_execute(
-                            renderer.transfer_queue.clone(),
-                            swapchain_wrapper.pipeline.command_buffers.transfer.clone(),
-                        )?
-                        .then_signal_semaphore_and_flush()?;
-                } else {
-                    tracing::warn!("The canvas settings changed, but the swapchain doesn't exist. That probably shouldn't be possible.")
+                try_or_err!(manager
+                    .upload(&renderer, &renderer.transfer_queue)
+                    .and_then(|copy| run_upload(&renderer, copy)));
+            }
+            Ok(RenderCommand::CanvasReplaced(canvas)) => {
+                let reallocated = try_or_err!(manager.transfer_canvas(&renderer, &canvas));
+                try_or_err!(manager.write_selection(&selection));
+                if let Some(swapchain_wrapper) = &mut swapchain_wrapper {
+                    let view = camera.view_transform(window_size, canvas.size, canvas.gridtype);
+                    if swapchain_wrapper.gridtype() != canvas.gridtype {
+                        try_or_err!(swapchain_wrapper.set_gridtype(
+                            &renderer,
+                            &manager,
+                            &view,
+                            canvas.gridtype
+                        ));
+                    } else if reallocated {
+                        // The old command buffers still bind the old buffers' descriptors.
+                        try_or_err!(swapchain_wrapper.set_view(&renderer, &manager, &view));
+                    }
+                }
+                try_or_err!(manager
+                    .upload(&renderer, &renderer.transfer_queue)
+                    .and_then(|copy| run_upload(&renderer, copy)));
+                if (canvas_size, gridtype) != (canvas.size, canvas.gridtype) {
+                    canvas_size = canvas.size;
+                    gridtype = canvas.gridtype;
+                    if window_proxy
+                        .send_event(WindowCommand::CanvasChanged {
+                            size: canvas_size,
+                            gridtype,
+                        })
+                        .is_err()
+                    {
+                        return Ok(());
+                    }
                 }
             }
-            Ok(RenderCommand::CanvasIndicesChanged) => {
-                if let Some(swapchain_wrapper) = &swapchain_wrapper {
-                    let execution = vk::sync::now(renderer.logical_device.clone())
-                        .then_execute(
-                            renderer.transfer_queue.clone(),
-                            swapchain_wrapper.pipeline.command_buffers.transfer.clone(),
-                        )?
-                        .then_signal_semaphore_and_flush()?;
-                } else {
-                    tracing::warn!("The canvas settings changed, but the swapchain doesn't exist. That probably shouldn't be possible.")
+            Ok(RenderCommand::CanvasIndicesChanged { layer, patch }) => {
+                if let Some(copy) =
+                    try_or_err!(manager.upload_indices_patch(&renderer, layer, &patch))
+                {
+                    try_or_err!(run_upload(&renderer, copy));
                 }
             }
+            Ok(RenderCommand::OverlayChanged(settings)) => {
+                overlay.settings = settings;
+                if let Some(swapchain_wrapper) = &mut swapchain_wrapper {
+                    let view = camera.view_transform(window_size, canvas_size, gridtype);
+                    try_or_err!(swapchain_wrapper.set_overlay(&renderer, &manager, &view, overlay));
+                }
+            }
+            Ok(RenderCommand::HoveredTileChanged(tile)) => {
+                overlay.hovered = tile;
+                if let Some(swapchain_wrapper) = &mut swapchain_wrapper {
+                    let view = camera.view_transform(window_size, canvas_size, gridtype);
+                    try_or_err!(swapchain_wrapper.set_overlay(&renderer, &manager, &view, overlay));
+                }
+            }
+            Ok(RenderCommand::SelectionChanged(tiles)) => {
+                selection = tiles;
+                try_or_err!(manager.write_selection(&selection));
+                try_or_err!(manager
+                    .upload_selection(&renderer)
+                    .and_then(|copy| run_upload(&renderer, copy)));
+            }
         }
     }
 }
+
+/// Runs an upload on the transfer queue and waits for it, since the host buffers it reads from are written again as
+/// soon as the next change comes in.
+fn run_upload(
+    renderer: &Renderer,
+    command_buffer: Arc<vk::command_buffer::PrimaryAutoCommandBuffer>,
+) -> Result<(), RendererError> {
+    vk::sync::now(renderer.logical_device.clone())
+        .then_execute(renderer.transfer_queue.clone(), command_buffer)?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+    Ok(())
+}
@@ -3,17 +3,41 @@ use std::sync::Arc;
 use super::Renderer;
 use super::RendererError;
 
-use super::vert::CanvasSettings;
+use super::vert::{CanvasSettings, Layer};
+use super::IndicesPatch;
+use crate::app::{CanvasSize, Color, LayerKind, LayerSnapshot};
 use tracing::instrument;
 use vk::descriptor_set::allocator::DescriptorSetAllocator;
+use vk::padded::Padded;
 use vulkano as vk;
 
+/// The width and height of a canvas in tiles, as the renderer stores them.
+pub(crate) fn canvas_dimensions(size: CanvasSize) -> Result<[u32; 2], RendererError> {
+    match (u32::try_from(size.width), u32::try_from(size.height)) {
+        (Ok(width), Ok(height)) => Ok([width, height]),
+        _ => Err(RendererError::CanvasTooLarge(size.width, size.height)),
+    }
+}
+
 pub(crate) struct CanvasBuffersManager {
     pub(crate) canvas_settings_host: vk::buffer::Subbuffer<CanvasSettings>,
     pub(crate) canvas_indices_host: vk::buffer::Subbuffer<[u32]>,
     pub(crate) canvas_settings_device: vk::buffer::Subbuffer<CanvasSettings>,
     pub(crate) canvas_indices_device: vk::buffer::Subbuffer<[u32]>,
+    pub(crate) canvas_layers_host: vk::buffer::Subbuffer<[Layer]>,
+    pub(crate) canvas_layers_device: vk::buffer::Subbuffer<[Layer]>,
+    /// One entry per tile, non-zero for selected tiles. Only the overlay reads it.
+    pub(crate) canvas_selection_host: vk::buffer::Subbuffer<[u32]>,
+    pub(crate) canvas_selection_device: vk::buffer::Subbuffer<[u32]>,
+    /// The layout of `descriptors`. Pipelines drawing the canvas must be built with exactly this layout for set 0.
+    pub(crate) layout: Arc<vk::descriptor_set::layout::DescriptorSetLayout>,
     pub(crate) descriptors: Option<Arc<vk::descriptor_set::PersistentDescriptorSet>>,
+    /// The width and height of the canvas, in tiles.
+    size: [u32; 2],
+    /// The number of palette entries `canvas_settings_host` and `canvas_settings_device` have room for.
+    palette_size: u64,
+    /// The number of layers the buffers have room for.
+    layer_count: u64,
 }
 
 impl CanvasBuffersManager {
@@ -23,7 +47,52 @@ impl CanvasBuffersManager {
         width: u32,
         height: u32,
         palette_size: u64,
+        layer_count: u64,
+    ) -> Result<Self, RendererError> {
+        Self::with_layout(
+            renderer,
+            Self::make_layout(renderer)?,
+            width,
+            height,
+            palette_size,
+            layer_count,
+        )
+    }
+
+    /// Replaces every buffer with new ones for a canvas of a different size, palette length or layer count, and rebuilds
+    /// `descriptors` to point at them. The layout is kept, so pipelines built for it stay valid, but every command
+    /// buffer using the old descriptors must be recorded again. The new buffers hold nothing but the canvas size.
+    #[instrument(skip_all, err)]
+    pub fn reallocate(
+        &mut self,
+        renderer: &Renderer,
+        width: u32,
+        height: u32,
+        palette_size: u64,
+        layer_count: u64,
+    ) -> Result<(), RendererError> {
+        *self = Self::with_layout(
+            renderer,
+            self.layout.clone(),
+            width,
+            height,
+            palette_size,
+            layer_count,
+        )?;
+        Ok(())
+    }
+
+    fn with_layout(
+        renderer: &Renderer,
+        layout: Arc<vk::descriptor_set::layout::DescriptorSetLayout>,
+        width: u32,
+        height: u32,
+        palette_size: u64,
+        layer_count: u64,
     ) -> Result<Self, RendererError> {
+        // Storage buffers can't be empty, so an empty canvas still gets room for one tile.
+        let tiles = ((width as u64) * (height as u64) * layer_count).max(1);
+        let selection_tiles = ((width as u64) * (height as u64)).max(1);
         let canvas_settings_host = vk::buffer::Buffer::new_unsized::<CanvasSettings>(
             renderer.allocator.clone(),
             vk::buffer::BufferCreateInfo {
@@ -41,7 +110,7 @@ impl CanvasBuffersManager {
             renderer.allocator.clone(),
             vk::buffer::BufferCreateInfo {
                 usage: vk::buffer::BufferUsage::TRANSFER_DST
-                    | vk::buffer::BufferUsage::UNIFORM_BUFFER,
+                    | vk::buffer::BufferUsage::STORAGE_BUFFER,
                 ..Default::default()
             },
             vk::memory::allocator::AllocationCreateInfo {
@@ -61,20 +130,72 @@ impl CanvasBuffersManager {
                     | vk::memory::allocator::MemoryTypeFilter::PREFER_HOST,
                 ..Default::default()
             },
-            (width as u64) * (height as u64),
+            tiles,
         )?;
         let canvas_indices_device = vk::buffer::Buffer::new_unsized::<[u32]>(
             renderer.allocator.clone(),
             vk::buffer::BufferCreateInfo {
                 usage: vk::buffer::BufferUsage::TRANSFER_DST
-                    | vk::buffer::BufferUsage::UNIFORM_BUFFER,
+                    | vk::buffer::BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            vk::memory::allocator::AllocationCreateInfo {
+                memory_type_filter: vk::memory::allocator::MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            tiles,
+        )?;
+        let canvas_layers_host = vk::buffer::Buffer::new_slice::<Layer>(
+            renderer.allocator.clone(),
+            vk::buffer::BufferCreateInfo {
+                usage: vk::buffer::BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            vk::memory::allocator::AllocationCreateInfo {
+                memory_type_filter: vk::memory::allocator::MemoryTypeFilter::HOST_RANDOM_ACCESS
+                    | vk::memory::allocator::MemoryTypeFilter::PREFER_HOST,
+                ..Default::default()
+            },
+            layer_count,
+        )?;
+        let canvas_layers_device = vk::buffer::Buffer::new_slice::<Layer>(
+            renderer.allocator.clone(),
+            vk::buffer::BufferCreateInfo {
+                usage: vk::buffer::BufferUsage::TRANSFER_DST
+                    | vk::buffer::BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            vk::memory::allocator::AllocationCreateInfo {
+                memory_type_filter: vk::memory::allocator::MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            layer_count,
+        )?;
+        let canvas_selection_host = vk::buffer::Buffer::new_slice::<u32>(
+            renderer.allocator.clone(),
+            vk::buffer::BufferCreateInfo {
+                usage: vk::buffer::BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            vk::memory::allocator::AllocationCreateInfo {
+                memory_type_filter: vk::memory::allocator::MemoryTypeFilter::HOST_RANDOM_ACCESS
+                    | vk::memory::allocator::MemoryTypeFilter::PREFER_HOST,
+                ..Default::default()
+            },
+            selection_tiles,
+        )?;
+        let canvas_selection_device = vk::buffer::Buffer::new_slice::<u32>(
+            renderer.allocator.clone(),
+            vk::buffer::BufferCreateInfo {
+                usage: vk::buffer::BufferUsage::TRANSFER_DST
+                    | vk::buffer::BufferUsage::STORAGE_BUFFER,
                 ..Default::default()
             },
             vk::memory::allocator::AllocationCreateInfo {
                 memory_type_filter: vk::memory::allocator::MemoryTypeFilter::PREFER_DEVICE,
                 ..Default::default()
             },
-            (width as u64) * (height as u64),
+            selection_tiles,
         )?;
 
         {
@@ -87,7 +208,15 @@ impl CanvasBuffersManager {
             canvas_indices_host,
             canvas_settings_device,
             canvas_indices_device,
+            canvas_layers_host,
+            canvas_layers_device,
+            canvas_selection_host,
+            canvas_selection_device,
+            layout,
             descriptors: None,
+            size: [width, height],
+            palette_size,
+            layer_count,
         };
         let descriptors = output.rebuild_descriptors(renderer);
         output.descriptors = Some(descriptors?);
@@ -113,37 +242,232 @@ impl CanvasBuffersManager {
         let indices = vk::descriptor_set::DescriptorBindingResources::Buffer(
             [Some(indices_buffer_info)].into(),
         );
+
+        let write_settings =
+            vk::descriptor_set::WriteDescriptorSet::buffer(0, self.canvas_settings_device.clone());
+        let write_indices =
+            vk::descriptor_set::WriteDescriptorSet::buffer(1, self.canvas_indices_device.clone());
+        let write_layers =
+            vk::descriptor_set::WriteDescriptorSet::buffer(2, self.canvas_layers_device.clone());
+        let write_selection =
+            vk::descriptor_set::WriteDescriptorSet::buffer(3, self.canvas_selection_device.clone());
+
+        let set = vk::descriptor_set::PersistentDescriptorSet::new(
+            &renderer.descriptor_allocator,
+            self.layout.clone(),
+            [write_settings, write_indices, write_layers, write_selection],
+            None,
+        )?;
+
+        Ok(set)
+    }
+
+    /// The layout of the canvas buffers, matching the `CanvasSettings`, `CanvasIndices` and `CanvasLayers` blocks in
+    /// `canvas_vert.glsl`, and the `CanvasSelection` block in `overlay_vert.glsl`.
+    #[instrument(skip_all, err)]
+    fn make_layout(
+        renderer: &Renderer,
+    ) -> Result<Arc<vk::descriptor_set::layout::DescriptorSetLayout>, RendererError> {
         let layout = vk::descriptor_set::layout::DescriptorSetLayoutBinding {
             binding_flags: vk::descriptor_set::layout::DescriptorBindingFlags::UPDATE_AFTER_BIND,
             descriptor_count: 1,
             stages: vk::shader::ShaderStages::VERTEX,
             ..vk::descriptor_set::layout::DescriptorSetLayoutBinding::descriptor_type(
-                vk::descriptor_set::layout::DescriptorType::UniformBuffer,
+                vk::descriptor_set::layout::DescriptorType::StorageBuffer,
             )
         };
         let layout = vk::descriptor_set::layout::DescriptorSetLayoutCreateInfo {
             flags:
                 vk::descriptor_set::layout::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
-            bindings: [(0u32, layout.clone()), (1u32, layout)].into(),
+            bindings: [
+                (0u32, layout.clone()),
+                (1u32, layout.clone()),
+                (2u32, layout.clone()),
+                (3u32, layout),
+            ]
+            .into(),
             ..Default::default()
         };
-        let layout = vk::descriptor_set::layout::DescriptorSetLayout::new(
+        Ok(vk::descriptor_set::layout::DescriptorSetLayout::new(
             renderer.logical_device.clone(),
             layout,
-        )?;
+        )?)
+    }
 
-        let write_settings =
-            vk::descriptor_set::WriteDescriptorSet::buffer(0, self.canvas_settings_device.clone());
-        let write_indices =
-            vk::descriptor_set::WriteDescriptorSet::buffer(1, self.canvas_indices_device.clone());
+    /// The width and height of the canvas, in tiles.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
 
-        let set = vk::descriptor_set::PersistentDescriptorSet::new(
-            &renderer.descriptor_allocator,
-            layout,
-            [write_settings, write_indices],
-            None,
+    /// The number of palette entries the buffers have room for.
+    pub fn palette_size(&self) -> u64 {
+        self.palette_size
+    }
+
+    /// The number of layers the buffers have room for.
+    pub fn layer_count(&self) -> u64 {
+        self.layer_count
+    }
+
+    /// The number of tiles on the canvas, which is also the number of instances to draw.
+    pub fn tile_count(&self) -> u64 {
+        self.size[0] as u64 * self.size[1] as u64
+    }
+
+    /// Writes the palettes of every base colour layer, one after another, into the host copy of the canvas settings.
+    /// Entries past the end of either are left alone. The device copy is only updated once the command buffer from
+    /// `upload` runs.
+    #[instrument(skip_all, err)]
+    pub fn write_palette(&self, palette: &[Color]) -> Result<(), RendererError> {
+        let mut guard = self.canvas_settings_host.write()?;
+        for (slot, color) in guard.palette.iter_mut().zip(palette) {
+            *slot = Padded([color.l, color.a, color.b]);
+        }
+        Ok(())
+    }
+
+    /// Writes the tiles of every layer, one layer after another, into the host copy of the canvas indices. Tiles past the
+    /// end of either are left alone. The device copy is only updated once the command buffer from `upload` runs.
+    #[instrument(skip_all, err)]
+    pub fn write_indices(&self, indices: &[u32]) -> Result<(), RendererError> {
+        let mut guard = self.canvas_indices_host.write()?;
+        let len = guard.len().min(indices.len());
+        guard[..len].copy_from_slice(&indices[..len]);
+        Ok(())
+    }
+
+    /// Writes how every layer is composited into the host copy of the layer table, giving each base colour layer its
+    /// part of the palette written by `write_palette`. Unused entries are left with no effect. The device copy is only
+    /// updated once the command buffer from `upload` runs.
+    #[instrument(skip_all, err)]
+    pub fn write_layers(&self, layers: &[LayerSnapshot]) -> Result<(), RendererError> {
+        let mut guard = self.canvas_layers_host.write()?;
+        let mut palette_offset = 0;
+        let layers = layers.iter().map(|layer| {
+            let palette_len = layer.palette.len() as u32;
+            let entry = Layer {
+                // Must match the kinds in `canvas_vert.glsl`.
+                kind: match layer.kind {
+                    LayerKind::Alpha => 0,
+                    LayerKind::BaseColor => 1,
+                    LayerKind::Shading => 2,
+                },
+                opacity: layer.opacity,
+                palette_offset,
+                palette_len,
+            };
+            palette_offset += palette_len;
+            entry
+        });
+        let unused = std::iter::repeat(Layer {
+            kind: 1,
+            opacity: 0.0,
+            palette_offset: 0,
+            palette_len: 0,
+        });
+        for (slot, layer) in guard.iter_mut().zip(layers.chain(unused)) {
+            *slot = layer;
+        }
+        Ok(())
+    }
+
+    /// Marks exactly the tiles at the positions in `selection` as selected in the host copy of the selection. Positions
+    /// past the end of the canvas are ignored. The device copy is only updated once the command buffer from `upload` or
+    /// `upload_selection` runs.
+    #[instrument(skip_all, err)]
+    pub fn write_selection(&self, selection: &[usize]) -> Result<(), RendererError> {
+        let mut guard = self.canvas_selection_host.write()?;
+        guard.fill(0);
+        for &tile in selection {
+            if let Some(slot) = guard.get_mut(tile) {
+                *slot = 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a command buffer that copies only the host copy of the selection to the device copy.
+    #[instrument(skip_all, err)]
+    pub fn upload_selection(
+        &self,
+        renderer: &Renderer,
+    ) -> Result<Arc<vk::command_buffer::PrimaryAutoCommandBuffer>, RendererError> {
+        let mut builder = vk::command_buffer::AutoCommandBufferBuilder::primary(
+            &renderer.command_allocator,
+            renderer.transfer_queue.queue_family_index(),
+            vk::command_buffer::CommandBufferUsage::OneTimeSubmit,
         )?;
+        builder.copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(
+            self.canvas_selection_host.clone(),
+            self.canvas_selection_device.clone(),
+        ))?;
+        Ok(builder.build()?)
+    }
 
-        Ok(set)
+    /// Writes `patch` into layer `layer` of the host copy of the canvas indices, and records a command buffer that copies
+    /// only the changed tiles to the device copy, merging runs that are close together. Returns `None` if no tile on the
+    /// canvas changed. The command buffer must finish before the host copy is written again.
+    #[instrument(skip_all, err)]
+    pub fn upload_indices_patch(
+        &self,
+        renderer: &Renderer,
+        layer: usize,
+        patch: &IndicesPatch,
+    ) -> Result<Option<Arc<vk::command_buffer::PrimaryAutoCommandBuffer>>, RendererError> {
+        let tiles = self.tile_count() as usize;
+        if layer as u64 >= self.layer_count {
+            return Ok(None);
+        }
+        let first_tile = layer * tiles;
+        patch.apply(&mut self.canvas_indices_host.write()?[first_tile..first_tile + tiles]);
+        let regions = patch.copy_regions(layer, tiles);
+        if regions.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = vk::command_buffer::AutoCommandBufferBuilder::primary(
+            &renderer.command_allocator,
+            renderer.transfer_queue.queue_family_index(),
+            vk::command_buffer::CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder.copy_buffer(vk::command_buffer::CopyBufferInfo {
+            regions,
+            ..vk::command_buffer::CopyBufferInfo::buffers(
+                self.canvas_indices_host.clone(),
+                self.canvas_indices_device.clone(),
+            )
+        })?;
+        Ok(Some(builder.build()?))
+    }
+
+    /// Records a command buffer for `queue` that copies the whole host copy of every canvas buffer to the device copy.
+    #[instrument(skip_all, err)]
+    pub fn upload(
+        &self,
+        renderer: &Renderer,
+        queue: &vk::device::Queue,
+    ) -> Result<Arc<vk::command_buffer::PrimaryAutoCommandBuffer>, RendererError> {
+        let mut builder = vk::command_buffer::AutoCommandBufferBuilder::primary(
+            &renderer.command_allocator,
+            queue.queue_family_index(),
+            vk::command_buffer::CommandBufferUsage::OneTimeSubmit,
+        )?;
+        builder
+            .copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(
+                self.canvas_settings_host.clone(),
+                self.canvas_settings_device.clone(),
+            ))?
+            .copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(
+                self.canvas_indices_host.clone(),
+                self.canvas_indices_device.clone(),
+            ))?
+            .copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(
+                self.canvas_layers_host.clone(),
+                self.canvas_layers_device.clone(),
+            ))?
+            .copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(
+                self.canvas_selection_host.clone(),
+                self.canvas_selection_device.clone(),
+            ))?;
+        Ok(builder.build()?)
     }
 }
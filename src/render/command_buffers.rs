@@ -16,8 +16,10 @@ use vk::pipeline::graphics::viewport::Viewport;
 use vk::pipeline::Pipeline;
 use vulkano as vk;
 
+use super::overlay::OverlayState;
 use super::types::Position;
 use super::RendererError;
+use crate::camera::ViewTransform;
 
 use vk::buffer::Subbuffer;
 
@@ -33,20 +35,30 @@ use vk::command_buffer::allocator::StandardCommandBufferAllocator;
 
 pub struct CommandBufferManager {
     pub(crate) drawing: Vec<Arc<PrimaryAutoCommandBuffer>>,
-    pub(crate) transfer: Arc<PrimaryAutoCommandBuffer>,
 }
 impl CommandBufferManager {
+    /// Records one command buffer per framebuffer, drawing the canvas with `pipeline` and then, unless `overlay` is
+    /// `None`, the overlay with `overlay_pipeline`.
     #[instrument(skip_all, err)]
     pub(crate) fn new(
         command_buffer_allocator: &StandardCommandBufferAllocator,
         gfx_queue: &Arc<Queue>,
-        transfer_queue: &Arc<Queue>,
         pipeline: &Arc<GraphicsPipeline>,
+        overlay_pipeline: &Arc<GraphicsPipeline>,
+        overlay: Option<&OverlayState>,
         viewport: Viewport,
         framebuffers: &Vec<Arc<Framebuffer>>,
         vertex_buffer: &Subbuffer<[Position]>,
         manager: &super::canvas_manager::CanvasBuffersManager,
+        view: &ViewTransform,
+        clear_color: [f32; 4],
     ) -> Result<Self, RendererError> {
+        let overlay =
+            overlay.map(|overlay| overlay.constants(view, viewport.extent, manager.size()));
+        let view = super::vert::View {
+            scale: view.scale,
+            offset: view.offset,
+        };
         let drawing_buffers = framebuffers
             .iter()
             .map(|framebuffer| {
@@ -60,7 +72,7 @@ impl CommandBufferManager {
                 builder
                     .begin_render_pass(
                         RenderPassBeginInfo {
-                            clear_values: vec![Some([0.1, 0.1, 0.1, 1.0].into())],
+                            clear_values: vec![Some(clear_color.into())],
                             ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
                         },
                         SubpassBeginInfo {
@@ -77,31 +89,46 @@ impl CommandBufferManager {
                         0,
                         manager.descriptors.clone().unwrap(),
                     )?
-                    .draw(vertex_buffer.len() as u32, 300, 0, 0)?
-                    .end_render_pass(SubpassEndInfo::default())?;
+                    .push_constants(pipeline.layout().clone(), 0, view)?
+                    .draw(
+                        vertex_buffer.len() as u32,
+                        manager.tile_count() as u32,
+                        0,
+                        0,
+                    )?
+                    .next_subpass(
+                        SubpassEndInfo::default(),
+                        SubpassBeginInfo {
+                            contents: SubpassContents::Inline,
+                            ..Default::default()
+                        },
+                    )?;
+                if let Some(overlay) = overlay {
+                    builder
+                        .bind_pipeline_graphics(overlay_pipeline.clone())?
+                        .set_viewport(0, smallvec![viewport.clone()])?
+                        .bind_descriptor_sets(
+                            vk::pipeline::PipelineBindPoint::Graphics,
+                            overlay_pipeline.layout().clone(),
+                            0,
+                            manager.descriptors.clone().unwrap(),
+                        )?
+                        .push_constants(overlay_pipeline.layout().clone(), 0, overlay)?
+                        .draw(
+                            vertex_buffer.len() as u32,
+                            manager.tile_count() as u32,
+                            0,
+                            0,
+                        )?;
+                }
+                builder.end_render_pass(SubpassEndInfo::default())?;
 
                 Ok(builder.build()?)
             })
             .collect::<Result<Vec<_>, RendererError>>()?;
 
-        let mut builder = AutoCommandBufferBuilder::primary(
-            command_buffer_allocator,
-            transfer_queue.queue_family_index(),
-            // Don't forget to write the correct buffer usage.
-            CommandBufferUsage::MultipleSubmit,
-        )?;
-        builder
-            .copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(
-                manager.canvas_settings_host.clone(),
-                manager.canvas_settings_device.clone(),
-            ))?
-            .copy_buffer(vk::command_buffer::CopyBufferInfo::buffers(
-                manager.canvas_indices_host.clone(),
-                manager.canvas_indices_device.clone(),
-            ))?;
         Ok(Self {
             drawing: drawing_buffers,
-            transfer: builder.build()?,
         })
     }
 }
@@ -79,3 +79,17 @@ pub static ALL_KHR_DEVICE_EXTENSIONS: Lazy<vulkano::device::DeviceExtensions> =
         ext_line_rasterization: true,
         ..Default::default()
     });
+
+/// The device extensions in `ALL_KHR_DEVICE_EXTENSIONS` that need a surface, and so can't be enabled by a headless
+/// renderer.
+pub static SURFACE_DEVICE_EXTENSIONS: Lazy<vulkano::device::DeviceExtensions> =
+    Lazy::new(|| vulkano::device::DeviceExtensions {
+        khr_display_swapchain: true,
+        khr_incremental_present: true,
+        khr_present_id: true,
+        khr_present_wait: true,
+        khr_shared_presentable_image: true,
+        khr_swapchain: true,
+        khr_swapchain_mutable_format: true,
+        ..Default::default()
+    });
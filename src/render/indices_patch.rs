@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use smallvec::SmallVec;
+use vk::command_buffer::BufferCopy;
+use vulkano as vk;
+
+use crate::tools::TileChanges;
+
+/// How many unchanged tiles may sit between two changed runs before they are uploaded as separate copies. Copying a few
+/// tiles that didn't change is cheaper than recording another copy region, and always safe, since the host copy of the
+/// indices mirrors the whole canvas.
+pub const MAX_MERGE_GAP: usize = 16;
+
+/// A run of consecutive tiles and their new values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndicesRun {
+    /// The position of the first tile of the run in the canvas.
+    pub start: usize,
+    pub indices: Vec<u32>,
+}
+
+impl IndicesRun {
+    /// The positions of every tile in the run.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.indices.len()
+    }
+}
+
+/// New values for some of the tiles of one layer, sent to the renderer so that it only has to upload the tiles that
+/// changed. Values are stored the way `CanvasSnapshot` stores them: palette indices for base colour layers, and the
+/// bits of the `f32` or `i32` for alpha and shading layers. Runs are sorted, never overlap, and never touch, so
+/// adjacent changed tiles always share a run.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndicesPatch {
+    runs: Vec<IndicesRun>,
+}
+
+impl IndicesPatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a patch from the positions and new values of changed tiles, in any order. If a tile appears more
+    /// than once, the last value wins.
+    pub fn from_tiles(tiles: impl IntoIterator<Item = (usize, u32)>) -> Self {
+        let tiles: BTreeMap<usize, u32> = tiles.into_iter().collect();
+        let mut runs: Vec<IndicesRun> = Vec::new();
+        for (position, index) in tiles {
+            match runs.last_mut() {
+                Some(run) if run.range().end == position => run.indices.push(index),
+                _ => runs.push(IndicesRun {
+                    start: position,
+                    indices: vec![index],
+                }),
+            }
+        }
+        Self { runs }
+    }
+
+    /// A patch replacing every tile of the layer.
+    pub fn full(indices: &[u32]) -> Self {
+        Self {
+            runs: if indices.is_empty() {
+                Vec::new()
+            } else {
+                vec![IndicesRun {
+                    start: 0,
+                    indices: indices.to_vec(),
+                }]
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn runs(&self) -> &[IndicesRun] {
+        &self.runs
+    }
+
+    /// The number of tiles the patch changes.
+    pub fn tile_count(&self) -> usize {
+        self.runs.iter().map(|run| run.indices.len()).sum()
+    }
+
+    /// Writes the patch into `indices`. Tiles past the end of `indices` are left out.
+    pub fn apply(&self, indices: &mut [u32]) {
+        for run in &self.runs {
+            let range = run.range();
+            let end = range.end.min(indices.len());
+            if range.start < end {
+                indices[range.start..end].copy_from_slice(&run.indices[..end - range.start]);
+            }
+        }
+    }
+
+    /// The ranges of tiles to copy from a buffer the patch has been applied to, so that a second buffer which matched
+    /// the first before the patch matches it again afterwards. Runs separated by at most `max_gap` tiles are merged
+    /// into one range, and every range is clipped to a canvas of `len` tiles.
+    pub fn copy_ranges(&self, max_gap: usize, len: usize) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for run in &self.runs {
+            let range = run.range();
+            let range = range.start.min(len)..range.end.min(len);
+            if range.is_empty() {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(last) if range.start - last.end <= max_gap => last.end = range.end,
+                _ => ranges.push(range),
+            }
+        }
+        ranges
+    }
+
+    /// The regions of the canvas indices buffer to copy from the host copy to the device copy, once the patch has been
+    /// written into layer `layer` of the host copy. Layers are `tiles` tiles long, and runs are merged as by
+    /// `copy_ranges` with `MAX_MERGE_GAP`. Offsets and sizes are in bytes.
+    pub fn copy_regions(&self, layer: usize, tiles: usize) -> SmallVec<[BufferCopy; 1]> {
+        let first_tile = layer * tiles;
+        let tile_size = std::mem::size_of::<u32>() as u64;
+        self.copy_ranges(MAX_MERGE_GAP, tiles)
+            .into_iter()
+            .map(|range| BufferCopy {
+                src_offset: (first_tile + range.start) as u64 * tile_size,
+                dst_offset: (first_tile + range.start) as u64 * tile_size,
+                size: range.len() as u64 * tile_size,
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+impl From<&TileChanges> for IndicesPatch {
+    fn from(changes: &TileChanges) -> Self {
+        Self::from_tiles(changes.iter().map(|change| (change.index, change.new)))
+    }
+}
+
+impl From<&TileChanges<f32>> for IndicesPatch {
+    fn from(changes: &TileChanges<f32>) -> Self {
+        Self::from_tiles(
+            changes
+                .iter()
+                .map(|change| (change.index, change.new.to_bits())),
+        )
+    }
+}
+
+impl From<&TileChanges<i32>> for IndicesPatch {
+    fn from(changes: &TileChanges<i32>) -> Self {
+        Self::from_tiles(
+            changes
+                .iter()
+                .map(|change| (change.index, change.new as u32)),
+        )
+    }
+}
@@ -9,20 +9,46 @@ use vulkano as vk;
 use super::Renderer;
 
 impl Renderer {
-    /// Makes a new `Renderer`.
+    /// Makes a new `Renderer` that draws to `window`.
     #[instrument(skip_all, err)]
     #[log_tries(tracing::error)]
     pub fn new(window: Arc<winit::window::Window>) -> Result<Self, renderer_error::RendererError> {
         let lib = Self::get_vulkan_library()?;
 
-        let instance = Self::get_instance(lib, window.clone())?;
+        let instance = Self::get_instance(lib, Some(window.clone()))?;
 
         let surface = Self::get_surface(instance.clone(), window.clone())?;
 
         let physical_device = Self::get_physical_device(instance.clone(), surface.as_ref())?;
 
+        Self::with_physical_device(instance, Some(window), Some(surface), physical_device)
+    }
+
+    /// Makes a new `Renderer` without a window, which can only draw offscreen (see `Renderer::render_offscreen`). Works
+    /// without a display, including on software drivers like lavapipe.
+    #[instrument(skip_all, err)]
+    #[log_tries(tracing::error)]
+    pub fn new_headless() -> Result<Self, renderer_error::RendererError> {
+        let lib = Self::get_vulkan_library()?;
+
+        let instance = Self::get_instance(lib, None)?;
+
+        let physical_device = Self::get_headless_physical_device(instance.clone())?;
+
+        Self::with_physical_device(instance, None, None, physical_device)
+    }
+
+    /// Everything `new` and `new_headless` have in common, once a physical device has been picked.
+    #[instrument(skip_all, err)]
+    #[log_tries(tracing::error)]
+    fn with_physical_device(
+        instance: Arc<vk::instance::Instance>,
+        window: Option<Arc<winit::window::Window>>,
+        surface: Option<Arc<vk::swapchain::Surface>>,
+        physical_device: Arc<vk::device::physical::PhysicalDevice>,
+    ) -> Result<Self, renderer_error::RendererError> {
         let (logical_device, transfer_queue, graphics_queue) =
-            Self::get_queues_and_device(physical_device.clone())?;
+            Self::get_queues_and_device(physical_device.clone(), surface.is_some())?;
 
         let allocator = Arc::new(vk::memory::allocator::StandardMemoryAllocator::new_default(
             logical_device.clone(),
@@ -14,21 +14,32 @@ use super::Renderer;
 impl Renderer {
     #[instrument(skip_all, err)]
     #[log_tries(tracing::error)]
-    /// Wraps the process of acquiring a Vulkan instance.
+    /// Wraps the process of acquiring a Vulkan instance. Without a window, no surface extensions are enabled.
     pub fn get_instance(
         lib: Arc<VulkanLibrary>,
-        window: Arc<winit::window::Window>,
+        window: Option<Arc<winit::window::Window>>,
     ) -> Result<Arc<vk::instance::Instance>, renderer_error::RendererError> {
-        let wanted_extensions = vk::instance::InstanceExtensions {
-            ext_surface_maintenance1: true,
-            // ext_swapchain_colorspace: todo!(),
-            ..Default::default()
+        let wanted_extensions = match &window {
+            Some(_) => vk::instance::InstanceExtensions {
+                ext_surface_maintenance1: true,
+                // ext_swapchain_colorspace: todo!(),
+                ..Default::default()
+            },
+            None => vk::instance::InstanceExtensions::empty(),
         };
 
-        let needed_extensions = vk::swapchain::Surface::required_extensions(window.as_ref());
+        let needed_extensions = match &window {
+            Some(window) => vk::instance::InstanceExtensions {
+                khr_get_surface_capabilities2: true,
+                ..Default::default()
+            }
+            .union(&vk::swapchain::Surface::required_extensions(
+                window.as_ref(),
+            )),
+            None => vk::instance::InstanceExtensions::empty(),
+        };
 
         let needed_extensions = vk::instance::InstanceExtensions {
-            khr_get_surface_capabilities2: true,
             khr_get_physical_device_properties2: true,
             ..Default::default()
         }
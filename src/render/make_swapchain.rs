@@ -19,6 +19,10 @@ impl Renderer {
         renderer_error::RendererError,
     > {
         let _guard = tracing::info_span!("make_swapchain").entered();
+        let surface = self
+            .surface
+            .as_ref()
+            .ok_or(renderer_error::RendererError::Headless)?;
         if new_size == [0u32, 0u32] {
             Ok(None)
         } else if let Some(swapchain) = old_swapchain {
@@ -28,7 +32,7 @@ impl Renderer {
         } else {
             let present_mode = if self
                 .physical_device
-                .surface_present_modes(self.surface.as_ref(), Default::default())
+                .surface_present_modes(surface.as_ref(), Default::default())
                 .is_ok_and(|mut a| a.any(|b| b == vk::swapchain::PresentMode::Mailbox))
             {
                 vk::swapchain::PresentMode::Mailbox
@@ -46,13 +50,7 @@ impl Renderer {
             };
             let swapchain = vk::swapchain::SwapchainCreateInfo {
                 scaling_behavior,
-                image_format: self
-                    .physical_device
-                    .surface_formats(&self.surface, Default::default())?
-                    .into_iter()
-                    .find(|(_, c)| ColorSpace::SrgbNonLinear == *c)
-                    .unwrap()
-                    .0,
+                image_format: self.pick_surface_format(surface)?,
                 image_view_formats: Default::default(),
                 image_extent: new_size,
                 image_usage: vk::image::ImageUsage::COLOR_ATTACHMENT, // TODO: Might need to be updated to allow for displaying
@@ -64,9 +62,30 @@ impl Renderer {
             };
             Ok(Some(vk::swapchain::Swapchain::new(
                 self.logical_device.clone(),
-                self.surface.clone(),
+                surface.clone(),
                 swapchain,
             )?))
         }
     }
+
+    /// Picks an sRGB format for the swapchain, so that the linear colours the shaders output are encoded for display
+    /// by the hardware. Falls back to any format in the sRGB colour space if there's no sRGB format.
+    fn pick_surface_format(
+        &self,
+        surface: &vk::swapchain::Surface,
+    ) -> Result<vk::format::Format, renderer_error::RendererError> {
+        let formats: Vec<_> = self
+            .physical_device
+            .surface_formats(surface, Default::default())?
+            .into_iter()
+            .filter(|(_, c)| ColorSpace::SrgbNonLinear == *c)
+            .map(|(format, _)| format)
+            .collect();
+        Ok(formats
+            .iter()
+            .find(|format| format.numeric_format_color() == Some(vk::format::NumericFormat::SRGB))
+            .or(formats.first())
+            .copied()
+            .unwrap())
+    }
 }
@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use palette::{Clamp, LinSrgba, Srgb, Srgba};
+use tracing::instrument;
+use try_log::log_tries;
+use vk::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
+use vk::format::Format;
+use vk::pipeline::graphics::viewport::Viewport;
+use vk::sync::GpuFuture;
+use vulkano as vk;
+
+use super::canvas_manager::{canvas_dimensions, CanvasBuffersManager};
+use super::framebuffer::make_framebuffers;
+use super::window_wrappers::pipeline_wrapper::PipelineWrapper;
+use super::{frag, vert, Renderer, RendererError};
+use crate::app::{CanvasSnapshot, ProjectV2};
+use crate::export::{raster_size, raster_view, Raster, RasterOptions};
+
+/// The format offscreen images are drawn in. Like the swapchain, it's sRGB, so the hardware encodes the linear colours
+/// the shaders output.
+const OFFSCREEN_FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+impl Renderer {
+    /// Draws `project` with the same pipeline used for the window, into an image in memory, and reads it back. The
+    /// result is framed exactly like `export::rasterize` frames it, so the two can be compared pixel for pixel. Works
+    /// on headless renderers.
+    #[instrument(skip_all, err)]
+    #[log_tries(tracing::error)]
+    pub fn render_offscreen(
+        &self,
+        project: &ProjectV2,
+        options: RasterOptions,
+    ) -> Result<Raster, RendererError> {
+        let size = project.size();
+        let gridtype = project.gridtype();
+        let (width, height) = raster_size(size, gridtype, options.pixels_per_tile);
+        if width == 0 || height == 0 {
+            return Ok(Raster {
+                width,
+                height,
+                pixels: Vec::new(),
+            });
+        }
+
+        let canvas = CanvasSnapshot::of_project(project);
+        let [tiles_wide, tiles_high] = canvas_dimensions(size)?;
+        let mut manager = CanvasBuffersManager::new(self, tiles_wide, tiles_high, 1, 1)?;
+        manager.transfer_canvas(self, &canvas)?;
+
+        let image = vk::image::Image::new(
+            self.allocator.clone(),
+            vk::image::ImageCreateInfo {
+                image_type: vk::image::ImageType::Dim2d,
+                format: OFFSCREEN_FORMAT,
+                extent: [width, height, 1],
+                usage: vk::image::ImageUsage::COLOR_ATTACHMENT
+                    | vk::image::ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            vk::memory::allocator::AllocationCreateInfo {
+                memory_type_filter: vk::memory::allocator::MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+        )?;
+        let render_pass = self.make_renderpass(OFFSCREEN_FORMAT)?;
+        let framebuffers = make_framebuffers(&vec![image.clone()], render_pass.clone())?;
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: [width as f32, height as f32],
+            depth_range: 0.0..=1.0,
+        };
+        let [r, g, b, a] = options.background.unwrap_or([0; 4]);
+        let background: LinSrgba = Srgba::new(r, g, b, a)
+            .into_format::<f32, f32>()
+            .into_linear();
+        // Tiles are blended over the background premultiplied, so it has to start out that way too.
+        let background = background.premultiply();
+        let pipeline = PipelineWrapper::new(
+            self,
+            vert::load(self.logical_device.clone())?,
+            frag::load(self.logical_device.clone())?,
+            gridtype,
+            &render_pass,
+            viewport,
+            &framebuffers,
+            &manager,
+            &raster_view(size, gridtype, options.pixels_per_tile),
+            [
+                background.color.red,
+                background.color.green,
+                background.color.blue,
+                background.alpha,
+            ],
+            None,
+        )?;
+
+        let readback = vk::buffer::Buffer::new_slice::<[u8; 4]>(
+            self.allocator.clone(),
+            vk::buffer::BufferCreateInfo {
+                usage: vk::buffer::BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            vk::memory::allocator::AllocationCreateInfo {
+                memory_type_filter: vk::memory::allocator::MemoryTypeFilter::HOST_RANDOM_ACCESS
+                    | vk::memory::allocator::MemoryTypeFilter::PREFER_HOST,
+                ..Default::default()
+            },
+            width as u64 * height as u64,
+        )?;
+
+        // Everything runs on the graphics queue, since there's no window to keep responsive.
+        let upload = manager.upload(self, &self.graphics_queue)?;
+        let mut download = AutoCommandBufferBuilder::primary(
+            &self.command_allocator,
+            self.graphics_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        download.copy_image_to_buffer(vk::command_buffer::CopyImageToBufferInfo::image_buffer(
+            image,
+            readback.clone(),
+        ))?;
+
+        vk::sync::now(self.logical_device.clone())
+            .then_execute(self.graphics_queue.clone(), upload)?
+            .then_execute(
+                self.graphics_queue.clone(),
+                pipeline.command_buffers.drawing[0].clone(),
+            )?
+            .then_execute(self.graphics_queue.clone(), download.build()?)?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        let pixels = readback
+            .read()?
+            .iter()
+            .copied()
+            .map(unpremultiply)
+            .collect();
+        Ok(Raster {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Undoes the premultiplication of a pixel read back from an offscreen image, in linear light, so it can be compared to
+/// the straight alpha pixels of `export::tile_pixel`.
+fn unpremultiply(pixel: [u8; 4]) -> [u8; 4] {
+    let [r, g, b, a] = pixel;
+    if a == 0 || a == 255 {
+        return pixel;
+    }
+    let alpha = a as f32 / 255.0;
+    let color = Srgb::new(r, g, b).into_format::<f32>().into_linear() / alpha;
+    let color: Srgb<u8> = Srgb::from_linear(color.clamp());
+    [color.red, color.green, color.blue, a]
+}
@@ -0,0 +1,103 @@
+//! The overlay drawn over the canvas in the editor: tile outlines, the hovered tile, and the edge of the selection. It
+//! is drawn in its own subpass, after the canvas, and never offscreen, so exports only ever contain the canvas.
+use crate::camera::ViewTransform;
+
+use super::overlay_vert::OverlayConstants;
+
+/// How the overlay looks. Colours are linear, with straight alpha, and thicknesses are in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlaySettings {
+    /// Whether tile outlines are drawn at all.
+    pub grid: bool,
+    pub grid_color: [f32; 4],
+    pub grid_thickness: f32,
+    /// Once tiles are shorter than this many physical pixels, the grid starts fading out.
+    pub grid_fade_start: f32,
+    /// Once tiles are this short, the grid is gone entirely.
+    pub grid_fade_end: f32,
+    /// Whether the hovered tile and the selection are outlined.
+    pub highlights: bool,
+    pub hover_color: [f32; 4],
+    pub selection_color: [f32; 4],
+    pub highlight_thickness: f32,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        Self {
+            grid: true,
+            grid_color: [0.0, 0.0, 0.0, 0.35],
+            grid_thickness: 1.0,
+            grid_fade_start: 16.0,
+            grid_fade_end: 6.0,
+            highlights: true,
+            hover_color: [1.0, 1.0, 1.0, 0.9],
+            selection_color: [0.1, 0.45, 1.0, 1.0],
+            highlight_thickness: 2.0,
+        }
+    }
+}
+
+impl OverlaySettings {
+    /// How much of the grid's own opacity is left when tiles are `tile_height` physical pixels tall, from 0 to 1.
+    pub fn grid_fade(&self, tile_height: f32) -> f32 {
+        if !self.grid || tile_height.is_nan() {
+            return 0.0;
+        }
+        if tile_height >= self.grid_fade_start {
+            return 1.0;
+        }
+        if tile_height <= self.grid_fade_end {
+            return 0.0;
+        }
+        (tile_height - self.grid_fade_end) / (self.grid_fade_start - self.grid_fade_end)
+    }
+}
+
+/// Everything the overlay draws from that isn't stored in the canvas buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct OverlayState {
+    pub settings: OverlaySettings,
+    /// The position of the tile under the cursor, if there is one.
+    pub hovered: Option<usize>,
+}
+
+impl OverlayState {
+    /// The push constants for drawing the overlay through `view`, in a window of `window_size` physical pixels.
+    pub(crate) fn constants(
+        &self,
+        view: &ViewTransform,
+        window_size: [f32; 2],
+        size: [u32; 2],
+    ) -> OverlayConstants {
+        let settings = &self.settings;
+        let tile_height = if size[1] == 0 {
+            0.0
+        } else {
+            view.scale[1] * window_size[1] / size[1] as f32
+        };
+        let mut grid_color = settings.grid_color;
+        grid_color[3] *= settings.grid_fade(tile_height);
+        let highlight = |mut color: [f32; 4]| {
+            if !settings.highlights {
+                color[3] = 0.0;
+            }
+            color
+        };
+        OverlayConstants {
+            scale: view.scale,
+            offset: view.offset,
+            grid_color,
+            hover_color: highlight(settings.hover_color),
+            selection_color: highlight(settings.selection_color),
+            grid_thickness: settings.grid_thickness,
+            highlight_thickness: settings.highlight_thickness,
+            hovered_tile: match self.hovered {
+                Some(tile) if settings.highlights && tile < size[0] as usize * size[1] as usize => {
+                    tile as i32
+                }
+                _ => -1,
+            },
+        }
+    }
+}
@@ -11,11 +11,13 @@ use tracing::warn;
 use super::Renderer;
 
 impl Renderer {
-    /// On success, returns a tuple `(device, transfer_queue, graphics_queue)`.
+    /// On success, returns a tuple `(device, transfer_queue, graphics_queue)`. The swapchain extension is only
+    /// enabled if `swapchain` is true. If the device only has a single queue, both queues are the same.
     // #[instrument(skip_all, err)]
     // #[log_tries(tracing::error)]
     pub(crate) fn get_queues_and_device(
         physical_device: Arc<vk::device::physical::PhysicalDevice>,
+        swapchain: bool,
     ) -> Result<
         (
             Arc<vk::device::Device>,
@@ -76,7 +78,10 @@ impl Renderer {
             };
 
         let mut queues = vec![0.5];
-        if graphics_family == transfer_family {
+        // Software drivers like lavapipe only have one queue, which has to do everything.
+        let shared_queue = graphics_family == transfer_family
+            && physical_device.queue_family_properties()[graphics_family].queue_count < 2;
+        if graphics_family == transfer_family && !shared_queue {
             queues.push(0.5);
         }
         let graphics_queue_create_info = vk::device::QueueCreateInfo {
@@ -108,7 +113,7 @@ impl Renderer {
             // khr_multiview: todo!(),
             // khr_present_wait: todo!(),
             // khr_shader_draw_parameters: todo!(),
-            khr_swapchain: true,
+            khr_swapchain: swapchain,
             // ext_conditional_rendering: todo!(),
             // ext_descriptor_buffer: todo!(),
             // ext_descriptor_indexing: true, ///////////////
@@ -122,16 +127,21 @@ impl Renderer {
         };
 
         let needed_features = vk::device::Features {
-            descriptor_binding_uniform_buffer_update_after_bind: true,
+            descriptor_binding_storage_buffer_update_after_bind: true,
             ..Default::default()
         };
 
+        let mut enabled_extensions = physical_device
+            .supported_extensions()
+            .intersection(&super::consts::ALL_KHR_DEVICE_EXTENSIONS.clone());
+        if !swapchain {
+            enabled_extensions =
+                enabled_extensions.difference(&super::consts::SURFACE_DEVICE_EXTENSIONS);
+        }
+
         let logical_device = vk::device::DeviceCreateInfo {
             queue_create_infos,
-            enabled_extensions: physical_device
-                .supported_extensions()
-                .intersection(&super::consts::ALL_KHR_DEVICE_EXTENSIONS.clone())
-                .union(&needed_extensions),
+            enabled_extensions: enabled_extensions.union(&needed_extensions),
             enabled_features: needed_features,
             ..Default::default()
         };
@@ -156,7 +166,7 @@ impl Renderer {
                     == q.queue_family_index()
                         .try_into()
                         .expect("I sure hope u32 fits into usize.")
-                    && q.id_within_family() != graphics_queue.id_within_family()
+                    && (shared_queue || q.id_within_family() != graphics_queue.id_within_family())
             })
             .expect("If it didn't exist, we'd have returned an error a few lines ago.")
             .clone();
@@ -6,17 +6,19 @@ use vk::format::Format;
 use vulkano as vk;
 type Result<T> = std::result::Result<T, super::RendererError>;
 impl super::Renderer {
+    /// The render pass everything is drawn in. The canvas is drawn in the first subpass, and the overlay over it in the
+    /// second.
     #[instrument(skip_all, err)]
     #[log_tries(tracing::error)]
     pub(super) fn make_renderpass(
         &self,
-        swapchain: Arc<vk::swapchain::Swapchain>,
+        format: Format,
     ) -> Result<Arc<vk::render_pass::RenderPass>> {
         Ok(vk::ordered_passes_renderpass!(
             self.logical_device.clone(),
             attachments:{
                 swap:{
-                    format:swapchain.image_format(),
+                    format:format,
                     samples:1,load_op:Clear,store_op:Store,
                 },
             },
@@ -26,6 +28,11 @@ impl super::Renderer {
                     depth_stencil:{},
                     input:[],
                 },
+                {
+                    color:[swap],
+                    depth_stencil:{},
+                    input:[],
+                },
             ]
         )?)
     }
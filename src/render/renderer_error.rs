@@ -37,6 +37,12 @@ pub enum RendererError {
     PipelineCreateInfoErr(#[from] IntoPipelineLayoutCreateInfoError),
     #[error(transparent)]
     RecvErr(#[from] std::sync::mpsc::RecvError),
+    #[error("This renderer is headless, so it can't draw to a window!")]
+    Headless,
+    #[error(transparent)]
+    ImageErr(#[from] vk::image::AllocateImageError),
+    #[error("Canvas size {0}x{1} is too large to render!")]
+    CanvasTooLarge(u64, u64),
 }
 
 impl<T> From<vk::Validated<T>> for RendererError
@@ -71,4 +71,37 @@ impl Renderer {
         );
         Ok(physical_device)
     }
+
+    /// Selects a Vulkan physical device for a headless renderer, preferring real GPUs over software ones.
+    #[instrument(skip_all)]
+    #[log_tries(tracing::error)]
+    pub(crate) fn get_headless_physical_device(
+        instance: Arc<vk::instance::Instance>,
+    ) -> Result<Arc<vk::device::physical::PhysicalDevice>, renderer_error::RendererError> {
+        use vk::device::physical::PhysicalDeviceType;
+        let rank =
+            |dev: &Arc<vk::device::physical::PhysicalDevice>| match dev.properties().device_type {
+                PhysicalDeviceType::DiscreteGpu => 4,
+                PhysicalDeviceType::IntegratedGpu => 3,
+                PhysicalDeviceType::VirtualGpu => 2,
+                PhysicalDeviceType::Cpu => 1,
+                _ => 0,
+            };
+        let physical_device = instance
+            .enumerate_physical_devices()?
+            .inspect(|dev| info!("Physical Device detected: {}", dev.properties().device_name))
+            .filter(|dev| {
+                dev.queue_family_properties()
+                    .iter()
+                    .any(|q| q.queue_flags.intersects(vk::device::QueueFlags::GRAPHICS))
+            })
+            .max_by_key(rank)
+            .ok_or(renderer_error::RendererError::NoPhysicalDevices)?;
+
+        info!(
+            "Selected Physical Device: {}",
+            physical_device.properties().device_name
+        );
+        Ok(physical_device)
+    }
 }
@@ -1,4 +1,4 @@
-mod pipeline_wrapper;
+pub(super) mod pipeline_wrapper;
 use tracing::instrument;
 use try_log::log_tries;
 use vulkano as vk;
@@ -7,7 +7,10 @@ use vk::buffer::Subbuffer;
 
 use super::canvas_manager::CanvasBuffersManager;
 use super::frag;
+use super::overlay::OverlayState;
 use super::RendererError;
+use crate::app::GridType;
+use crate::camera::ViewTransform;
 
 use super::vert;
 
@@ -23,10 +26,14 @@ use std::sync::Arc;
 
 use vk::pipeline::graphics::viewport::Viewport;
 
+/// The linear colour drawn behind the canvas in the editor.
+const EDITOR_BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+
 pub(super) struct SwapchainWrapper {
     pub(super) swapchain: Arc<vk::swapchain::Swapchain>,
     pub(super) swapchain_images: Vec<Arc<vk::image::Image>>,
     pub(super) render_pass: Arc<vk::render_pass::RenderPass>,
+    pub(super) framebuffers: Vec<Arc<Framebuffer>>,
     pub(super) pipeline: pipeline_wrapper::PipelineWrapper,
 }
 
@@ -38,8 +45,10 @@ impl SwapchainWrapper {
         size: [u32; 2],
         vert: Arc<vk::shader::ShaderModule>,
         frag: Arc<vk::shader::ShaderModule>,
-        vertex_buffer: Subbuffer<[Position]>,
+        gridtype: GridType,
         manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+        overlay: OverlayState,
     ) -> Result<Option<SwapchainWrapper>, RendererError> {
         let viewport = Viewport {
             offset: [0.0, 0.0],
@@ -55,7 +64,7 @@ impl SwapchainWrapper {
             Err(err) => return Err(err),
         };
         let render_pass: Arc<vk::render_pass::RenderPass> =
-            renderer.make_renderpass(swapchain.clone())?;
+            renderer.make_renderpass(swapchain.image_format())?;
         let framebuffers: Vec<Arc<Framebuffer>> =
             make_framebuffers(&swapchain_images, render_pass.clone())?;
 
@@ -63,17 +72,21 @@ impl SwapchainWrapper {
             &renderer,
             vert,
             frag,
-            vertex_buffer,
+            gridtype,
             &render_pass,
             viewport,
             &framebuffers,
             manager,
+            view,
+            EDITOR_BACKGROUND,
+            Some(overlay),
         )?;
 
         Ok(Some(Self {
             swapchain,
             swapchain_images,
             render_pass,
+            framebuffers,
             pipeline,
         }))
     }
@@ -85,6 +98,7 @@ impl SwapchainWrapper {
         renderer: &Renderer,
         size: [u32; 2],
         manager: &CanvasBuffersManager,
+        view: &ViewTransform,
     ) -> Result<Option<SwapchainWrapper>, RendererError> {
         let viewport = Viewport {
             offset: [0.0, 0.0],
@@ -102,7 +116,7 @@ impl SwapchainWrapper {
         };
         let render_pass: Arc<vk::render_pass::RenderPass> =
             if swapchain.image_format() != old_format {
-                renderer.make_renderpass(swapchain.clone())?
+                renderer.make_renderpass(swapchain.image_format())?
             } else {
                 self.render_pass
             };
@@ -111,17 +125,91 @@ impl SwapchainWrapper {
 
         let pipeline = self
             .pipeline
-            .rebuild(&renderer, viewport, &framebuffers, manager)?;
+            .rebuild(&renderer, viewport, &framebuffers, manager, view)?;
         Ok(Some(Self {
             swapchain,
             swapchain_images,
             render_pass,
+            framebuffers,
             pipeline,
         }))
     }
+
+    /// Redraws the canvas through a different camera, without touching the swapchain.
+    #[instrument(skip_all, err)]
+    #[log_tries(tracing::error)]
+    pub fn set_view(
+        &mut self,
+        renderer: &Renderer,
+        manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+    ) -> Result<(), RendererError> {
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: self.swapchain.image_extent().map(|f| f as f32),
+            depth_range: 0.0..=1.0,
+        };
+        self.pipeline
+            .set_view(renderer, viewport, &self.framebuffers, manager, view)
+    }
+
+    /// Redraws the overlay with different settings or highlights, without touching the swapchain.
+    #[instrument(skip_all, err)]
+    #[log_tries(tracing::error)]
+    pub fn set_overlay(
+        &mut self,
+        renderer: &Renderer,
+        manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+        overlay: OverlayState,
+    ) -> Result<(), RendererError> {
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: self.swapchain.image_extent().map(|f| f as f32),
+            depth_range: 0.0..=1.0,
+        };
+        self.pipeline.set_overlay(
+            renderer,
+            viewport,
+            &self.framebuffers,
+            manager,
+            view,
+            overlay,
+        )
+    }
+
+    /// The grid the canvas is currently drawn as.
+    pub fn gridtype(&self) -> GridType {
+        self.pipeline.gridtype
+    }
+
+    /// Switches to the pipeline for a different grid type, without touching the swapchain.
+    #[instrument(skip_all, err)]
+    #[log_tries(tracing::error)]
+    pub fn set_gridtype(
+        &mut self,
+        renderer: &Renderer,
+        manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+        gridtype: GridType,
+    ) -> Result<(), RendererError> {
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: self.swapchain.image_extent().map(|f| f as f32),
+            depth_range: 0.0..=1.0,
+        };
+        self.pipeline.set_gridtype(
+            renderer,
+            &self.render_pass,
+            viewport,
+            &self.framebuffers,
+            manager,
+            view,
+            gridtype,
+        )
+    }
 }
 
-#[allow(dead_code)]
 const SQUARE: [Position; 6] = [
     Position {
         position: [0.0, 0.0],
@@ -143,7 +231,6 @@ const SQUARE: [Position; 6] = [
     },
 ];
 
-#[allow(dead_code)]
 const HEXAGON: [Position; 8] = [
     Position {
         position: [0.0, 0.0],
@@ -171,27 +258,41 @@ const HEXAGON: [Position; 8] = [
     },
 ];
 
+/// The vertices of a single tile of a grid of type `gridtype`, as drawn by the canvas pipeline.
+#[log_tries(tracing::error)]
+pub(super) fn canvas_vertex_buffer(
+    renderer: &Renderer,
+    gridtype: GridType,
+) -> Result<Subbuffer<[Position]>, RendererError> {
+    let vertices: &[Position] = match gridtype {
+        GridType::Square => &SQUARE,
+        GridType::Hexagonal => &HEXAGON,
+    };
+    Ok(vk::buffer::Buffer::from_iter(
+        renderer.allocator.clone(),
+        vk::buffer::BufferCreateInfo {
+            usage: vk::buffer::BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        vk::memory::allocator::AllocationCreateInfo {
+            memory_type_filter: vk::memory::allocator::MemoryTypeFilter::PREFER_DEVICE
+                | vk::memory::allocator::MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        vertices.iter().copied(),
+    )?)
+}
+
 impl SwapchainWrapper {
     #[log_tries(tracing::error)]
     pub(super) fn make_canvas_swapchain(
         renderer: &Renderer,
         size: [u32; 2],
         manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+        gridtype: GridType,
+        overlay: OverlayState,
     ) -> Result<Option<SwapchainWrapper>, RendererError> {
-        let vertex_buffer = vk::buffer::Buffer::from_iter(
-            renderer.allocator.clone(),
-            vk::buffer::BufferCreateInfo {
-                usage: vk::buffer::BufferUsage::VERTEX_BUFFER,
-                ..Default::default()
-            },
-            vk::memory::allocator::AllocationCreateInfo {
-                memory_type_filter: vk::memory::allocator::MemoryTypeFilter::PREFER_DEVICE
-                    | vk::memory::allocator::MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            HEXAGON,
-        )?;
-
         let vert: Arc<vk::shader::ShaderModule> = vert::load(renderer.logical_device.clone())?;
         let frag: Arc<vk::shader::ShaderModule> = frag::load(renderer.logical_device.clone())?;
 
@@ -200,8 +301,10 @@ impl SwapchainWrapper {
             size,
             vert.clone(),
             frag.clone(),
-            vertex_buffer,
+            gridtype,
             manager,
+            view,
+            overlay,
         )?)
     }
 }
@@ -1,4 +1,8 @@
+use crate::app::GridType;
+use crate::camera::ViewTransform;
 use crate::render::canvas_manager::CanvasBuffersManager;
+use crate::render::command_buffers::CommandBufferManager;
+use crate::render::overlay::OverlayState;
 
 use super::super::RendererError;
 
@@ -13,15 +17,25 @@ use vk::pipeline::graphics::viewport::Viewport;
 use super::super::Renderer;
 
 use super::super::types::Position;
-
-use vk::buffer::Subbuffer;
+use super::super::{overlay_frag, overlay_vert};
+use super::canvas_vertex_buffer;
 
 use std::sync::Arc;
 
 pub(in crate::render) struct PipelineWrapper {
+    pub(crate) vert: Arc<vk::shader::ShaderModule>,
+    pub(crate) frag: Arc<vk::shader::ShaderModule>,
+    /// The grid the pipeline and `vertex_buffer` draw.
+    pub(crate) gridtype: GridType,
     pub(crate) vertex_buffer: vk::buffer::Subbuffer<[Position]>,
     pub(crate) pipeline: Arc<vk::pipeline::GraphicsPipeline>,
-    pub(crate) command_buffers: crate::render::command_buffers::CommandBufferManager,
+    /// Draws the overlay in the second subpass, over the canvas.
+    pub(crate) overlay_pipeline: Arc<vk::pipeline::GraphicsPipeline>,
+    pub(crate) command_buffers: CommandBufferManager,
+    /// The linear colour drawn behind the canvas.
+    pub(crate) clear_color: [f32; 4],
+    /// What the overlay shows, or `None` to draw only the canvas.
+    pub(crate) overlay: Option<OverlayState>,
 }
 
 impl PipelineWrapper {
@@ -31,55 +45,157 @@ impl PipelineWrapper {
         renderer: &Renderer,
         vert: Arc<vk::shader::ShaderModule>,
         frag: Arc<vk::shader::ShaderModule>,
-        vertex_buffer: Subbuffer<[Position]>,
+        gridtype: GridType,
         render_pass: &Arc<vk::render_pass::RenderPass>,
         viewport: Viewport,
         framebuffers: &Vec<Arc<Framebuffer>>,
         manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+        clear_color: [f32; 4],
+        overlay: Option<OverlayState>,
     ) -> Result<Self, RendererError> {
-        let pipeline =
-            renderer.make_pipeline(vert.clone(), frag.clone(), render_pass.clone(), &viewport)?;
+        let vertex_buffer = canvas_vertex_buffer(renderer, gridtype)?;
+        let pipeline = renderer.make_pipeline(
+            vert.clone(),
+            frag.clone(),
+            render_pass.clone(),
+            0,
+            &viewport,
+            manager.layout.clone(),
+            gridtype,
+        )?;
+        let overlay_pipeline = renderer.make_pipeline(
+            overlay_vert::load(renderer.logical_device.clone())?,
+            overlay_frag::load(renderer.logical_device.clone())?,
+            render_pass.clone(),
+            1,
+            &viewport,
+            manager.layout.clone(),
+            gridtype,
+        )?;
 
-        let command_buffers = crate::render::command_buffers::CommandBufferManager::new(
+        let command_buffers = CommandBufferManager::new(
             &renderer.command_allocator,
             &renderer.graphics_queue,
-            &renderer.transfer_queue,
             &pipeline,
+            &overlay_pipeline,
+            overlay.as_ref(),
             viewport,
             &framebuffers,
             &vertex_buffer,
             manager,
+            view,
+            clear_color,
         )?;
 
         Ok(Self {
+            vert,
+            frag,
+            gridtype,
             vertex_buffer,
             pipeline,
+            overlay_pipeline,
             command_buffers,
+            clear_color,
+            overlay,
         })
     }
 
-    #[instrument(skip_all, err)]
-    pub fn rebuild(
-        self,
+    /// Records the command buffers again, for when anything they draw from other than the pipelines has changed.
+    fn record(
+        &self,
         renderer: &Renderer,
         viewport: Viewport,
         framebuffers: &Vec<Arc<Framebuffer>>,
         manager: &CanvasBuffersManager,
-    ) -> Result<Self, RendererError> {
-        let command_buffers = crate::render::command_buffers::CommandBufferManager::new(
+        view: &ViewTransform,
+    ) -> Result<CommandBufferManager, RendererError> {
+        CommandBufferManager::new(
             &renderer.command_allocator,
             &renderer.graphics_queue,
-            &renderer.transfer_queue,
             &self.pipeline,
+            &self.overlay_pipeline,
+            self.overlay.as_ref(),
             viewport,
-            &framebuffers,
+            framebuffers,
             &self.vertex_buffer,
             manager,
-        )?;
+            view,
+            self.clear_color,
+        )
+    }
+
+    #[instrument(skip_all, err)]
+    pub fn rebuild(
+        self,
+        renderer: &Renderer,
+        viewport: Viewport,
+        framebuffers: &Vec<Arc<Framebuffer>>,
+        manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+    ) -> Result<Self, RendererError> {
+        let command_buffers = self.record(renderer, viewport, framebuffers, manager, view)?;
 
         Ok(Self {
             command_buffers,
             ..self
         })
     }
+
+    /// Rebuilds only the command buffers, to draw the same pipeline with a different camera.
+    #[instrument(skip_all, err)]
+    pub fn set_view(
+        &mut self,
+        renderer: &Renderer,
+        viewport: Viewport,
+        framebuffers: &Vec<Arc<Framebuffer>>,
+        manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+    ) -> Result<(), RendererError> {
+        self.command_buffers = self.record(renderer, viewport, framebuffers, manager, view)?;
+        Ok(())
+    }
+
+    /// Rebuilds only the command buffers, to draw a different overlay.
+    #[instrument(skip_all, err)]
+    pub fn set_overlay(
+        &mut self,
+        renderer: &Renderer,
+        viewport: Viewport,
+        framebuffers: &Vec<Arc<Framebuffer>>,
+        manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+        overlay: OverlayState,
+    ) -> Result<(), RendererError> {
+        self.overlay = Some(overlay);
+        self.set_view(renderer, viewport, framebuffers, manager, view)
+    }
+
+    /// Rebuilds the pipeline, vertex buffer and command buffers to draw a grid of a different type.
+    #[instrument(skip_all, err)]
+    pub fn set_gridtype(
+        &mut self,
+        renderer: &Renderer,
+        render_pass: &Arc<vk::render_pass::RenderPass>,
+        viewport: Viewport,
+        framebuffers: &Vec<Arc<Framebuffer>>,
+        manager: &CanvasBuffersManager,
+        view: &ViewTransform,
+        gridtype: GridType,
+    ) -> Result<(), RendererError> {
+        *self = Self::new(
+            renderer,
+            self.vert.clone(),
+            self.frag.clone(),
+            gridtype,
+            render_pass,
+            viewport,
+            framebuffers,
+            manager,
+            view,
+            self.clear_color,
+            self.overlay,
+        )?;
+        Ok(())
+    }
 }
@@ -1,8 +1,8 @@
 #version 460
 
 layout(location = 0) out vec4 f_color;
-layout(location = 1) in vec3 color;
+layout(location = 1) in vec4 color;
 
 void main() {
-    f_color = vec4(color, 1.0);
+    f_color = color;
 }
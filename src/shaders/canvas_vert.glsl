@@ -1,24 +1,54 @@
 #version 460
 
 layout(location = 0) in vec2 position;
-layout(location = 1) out vec3 color;
+layout(location = 1) out vec4 color;
 
 layout(set = 0, binding = 0) readonly buffer CanvasSettings {
     uint WIDTH;
     uint HEIGHT;
+    // The palettes of every base colour layer, one after another.
     vec3 palette[];
 } Settings;
+// The tiles of every layer, one layer after another. Alpha and shading layers store the bits of their values.
 layout(set = 0, binding = 1) readonly buffer CanvasIndices {
     uint indices[];
 } Indices;
 
+// The values of `Layer.kind`.
+const uint ALPHA = 0;
+const uint BASE_COLOR = 1;
+const uint SHADING = 2;
 
-
+struct Layer {
+    uint kind;
+    // 0 for hidden layers.
+    float opacity;
+    // Where this layer's palette starts in `Settings.palette`, and how long it is.
+    uint palette_offset;
+    uint palette_len;
+};
+// Every layer, from the bottom of the stack to the top.
+layout(set = 0, binding = 2) readonly buffer CanvasLayers {
+    Layer layers[];
+} Layers;
+// The camera, mapping canvas space to clip space. Must match `hexil::camera::ViewTransform`.
+layout(push_constant) uniform View {
+    vec2 scale;
+    vec2 offset;
+} view;
+// Whether the canvas is a hexagonal grid rather than a square one. Set when the pipeline is built, from the project's
+// `GridType`.
+layout(constant_id = 0) const bool HEXAGONAL = true;
 
 vec2 transform_one(vec2 initial) {
-    vec2 scaled_pos = initial / vec2(Settings.WIDTH * 0.75, Settings.HEIGHT);
-    float column_offset = ((gl_InstanceIndex % 2) / 2.f);
-    vec2 grid_offset = 2.f * (vec2(gl_InstanceIndex % Settings.WIDTH, (gl_InstanceIndex / Settings.WIDTH + column_offset)) + 0.5f);
+    // The distance between neighbouring columns, in tile widths. Hexagonal columns interlock, so they're closer.
+    float column_spacing = HEXAGONAL ? 0.75 : 1.0;
+    vec2 scaled_pos = initial / vec2(Settings.WIDTH * column_spacing, Settings.HEIGHT);
+    uint column = gl_InstanceIndex % Settings.WIDTH;
+    uint row = gl_InstanceIndex / Settings.WIDTH;
+    // Odd columns of hexagonal grids are shifted half a tile down. This must match `hexil::grid`.
+    float column_offset = HEXAGONAL ? ((column % 2) / 2.f) : 0.0;
+    vec2 grid_offset = 2.f * (vec2(column, row + column_offset) + 0.5f);
     vec2 top_left = vec2(-1.f);
     vec2 canvas_size = vec2(Settings.WIDTH, Settings.HEIGHT);
     vec2 offset = top_left + (grid_offset / canvas_size);
@@ -26,23 +56,68 @@ vec2 transform_one(vec2 initial) {
     return scaled_pos + offset;
 }
 
+/// Ported from the `palette` crate. Must match `palette::LinSrgb::from_color(palette::Oklab)`.
+vec3 oklab_to_linear_srgb(vec3 c) {
+    // GLSL matrices are column major, so each row here is one column of the matrix.
+    vec3 lms_ = mat3(
+        1.0000000000,  1.0000000000,  1.0000000000,
+        0.3963377774, -0.1055613458, -0.0894841775,
+        0.2158037573, -0.0638541728, -1.2914855480
+    ) * c;
+
+    vec3 lms = lms_ * lms_ * lms_;
+
+    return mat3(
+         4.0767416621, -1.2684380046, -0.0041960863,
+        -3.3077115913,  2.6097574011, -0.7034186147,
+         0.2309699292, -0.3413193965,  1.7076147010
+    ) * lms;
+}
+
+// How far one unit of a shading layer shifts lightness. Must match `hexil::app::SHADING_STEP`.
+const float SHADING_STEP = 0.01;
+
+// Composites every layer of this instance's tile, bottom to top. Must match `hexil::app::composite`. The colour is in
+// Oklab, and the alpha is its coverage.
+vec4 composite_tile() {
+    uint tiles = Settings.WIDTH * Settings.HEIGHT;
+    vec3 lab = vec3(0.0);
+    float alpha = 0.0;
+    for (uint i = 0; i < Layers.layers.length(); i++) {
+        Layer layer = Layers.layers[i];
+        uint value = Indices.indices[i * tiles + gl_InstanceIndex];
+        if (layer.kind == BASE_COLOR) {
+            if (value < layer.palette_len) {
+                float covered = layer.opacity + alpha * (1.0 - layer.opacity);
+                if (covered > 0.0) {
+                    vec3 painted = Settings.palette[layer.palette_offset + value];
+                    lab = (painted * layer.opacity + lab * alpha * (1.0 - layer.opacity)) / covered;
+                    alpha = covered;
+                }
+            }
+        } else if (layer.kind == SHADING) {
+            lab.x += float(int(value)) * SHADING_STEP * layer.opacity;
+        } else if (layer.kind == ALPHA) {
+            float coverage = uintBitsToFloat(value);
+            coverage = isnan(coverage) ? 0.0 : clamp(coverage, 0.0, 1.0);
+            alpha *= 1.0 - layer.opacity * (1.0 - coverage);
+        }
+    }
+    return vec4(lab, alpha);
+}
+
 void main() {
-    vec2 offsets[2] = vec2[2](
-            vec2(0.3, 0.3),
-            -vec2(0.3, 0.3)
-        );
-
-    vec3 colors[6] = vec3[6](
-            vec3(1.0, 0.0, 0.0),
-            vec3(0.0, 1.0, 0.0),
-            vec3(0.0, 0.0, 1.0),
-            vec3(1.0, 0.0, 1.0),
-            vec3(1.0, 1.0, 0.0),
-            vec3(0.0, 1.0, 1.0)
-        );
-    
+    vec4 tile = composite_tile();
+    if (tile.a <= 0.0) {
+        // Uncovered tiles aren't drawn at all. Collapsing every vertex onto one point leaves nothing to rasterise.
+        gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        color = vec4(0.0);
+        return;
+    }
+
     vec2 pos = transform_one(position);
-    
-    gl_Position = vec4(pos, 0.0, 1.0);
-    color = colors[gl_InstanceIndex % 6];
+
+    gl_Position = vec4(pos * view.scale + view.offset, 0.0, 1.0);
+    // The framebuffer is sRGB, so the hardware applies the transfer function when writing.
+    color = vec4(clamp(oklab_to_linear_srgb(tile.rgb), 0.0, 1.0), tile.a);
 }
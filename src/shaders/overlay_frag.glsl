@@ -0,0 +1,70 @@
+#version 460
+
+layout(location = 0) in float edges[6];
+layout(location = 6) flat in uint selection_edges;
+layout(location = 7) flat in uint hovered;
+
+layout(location = 0) out vec4 f_color;
+
+// Must match `overlay_vert.glsl`.
+layout(push_constant) uniform OverlayConstants {
+    vec2 scale;
+    vec2 offset;
+    vec4 grid_color;
+    vec4 hover_color;
+    vec4 selection_color;
+    float grid_thickness;
+    float highlight_thickness;
+    int hovered_tile;
+} overlay;
+
+// Converts a distance from an edge in the tile's own units to pixels. Edges are linear across the tile, so their
+// screen space gradient says how many units there are to a pixel.
+float pixels_from_edge(float edge) {
+    vec2 gradient = vec2(dFdx(edge), dFdy(edge));
+    return edge / max(length(gradient), 1e-6);
+}
+
+// How much of a pixel `distance` pixels inside an edge is covered by a line `width` pixels wide running along the
+// inside of that edge.
+float line_coverage(float distance, float width) {
+    return clamp(width + 0.5 - distance, 0.0, 1.0);
+}
+
+// Straight alpha "over".
+vec4 over(vec4 below, vec4 above) {
+    float alpha = above.a + below.a * (1.0 - above.a);
+    if (alpha <= 0.0) {
+        return vec4(0.0);
+    }
+    return vec4((above.rgb * above.a + below.rgb * below.a * (1.0 - above.a)) / alpha, alpha);
+}
+
+void main() {
+    float nearest_edge = 1e9;
+    float nearest_selection_edge = 1e9;
+    for (int i = 0; i < 6; i++) {
+        float distance = pixels_from_edge(edges[i]);
+        nearest_edge = min(nearest_edge, distance);
+        if ((selection_edges & (1u << i)) != 0u) {
+            nearest_selection_edge = min(nearest_selection_edge, distance);
+        }
+    }
+
+    // The tiles on both sides of a grid line or the edge of the selection draw half of it. Only the hovered tile knows
+    // it's hovered, so it draws the whole outline itself.
+    vec4 color = overlay.grid_color;
+    color.a *= line_coverage(nearest_edge, overlay.grid_thickness / 2.0);
+    vec4 selection = overlay.selection_color;
+    selection.a *= line_coverage(nearest_selection_edge, overlay.highlight_thickness / 2.0);
+    color = over(color, selection);
+    if (hovered != 0u) {
+        vec4 hover = overlay.hover_color;
+        hover.a *= line_coverage(nearest_edge, overlay.highlight_thickness);
+        color = over(color, hover);
+    }
+    if (color.a <= 0.0) {
+        discard;
+    }
+    f_color = color;
+}
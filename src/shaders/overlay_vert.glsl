@@ -0,0 +1,113 @@
+#version 460
+
+layout(location = 0) in vec2 position;
+// How far this point is inside each edge of the tile, in the tile's own units: zero on the edge and positive inside.
+// Edges go clockwise from the top. Square tiles only have four, and leave the rest far away.
+layout(location = 0) out float edges[6];
+// One bit per edge, set if the edge separates a selected tile from an unselected one.
+layout(location = 6) flat out uint selection_edges;
+layout(location = 7) flat out uint hovered;
+
+layout(set = 0, binding = 0) readonly buffer CanvasSettings {
+    uint WIDTH;
+    uint HEIGHT;
+    vec3 palette[];
+} Settings;
+// One entry per tile, non-zero for selected tiles.
+layout(set = 0, binding = 3) readonly buffer CanvasSelection {
+    uint selected[];
+} Selection;
+// Must match `overlay_frag.glsl`. Colours are linear, with straight alpha, and thicknesses are in pixels.
+layout(push_constant) uniform OverlayConstants {
+    vec2 scale;
+    vec2 offset;
+    vec4 grid_color;
+    vec4 hover_color;
+    vec4 selection_color;
+    float grid_thickness;
+    float highlight_thickness;
+    // The position of the hovered tile, or -1 if there isn't one.
+    int hovered_tile;
+} overlay;
+// Whether the canvas is a hexagonal grid rather than a square one. Set when the pipeline is built, from the project's
+// `GridType`.
+layout(constant_id = 0) const bool HEXAGONAL = true;
+
+// Must match `transform_one` in `canvas_vert.glsl`.
+vec2 transform_one(vec2 initial) {
+    float column_spacing = HEXAGONAL ? 0.75 : 1.0;
+    vec2 scaled_pos = initial / vec2(Settings.WIDTH * column_spacing, Settings.HEIGHT);
+    uint column = gl_InstanceIndex % Settings.WIDTH;
+    uint row = gl_InstanceIndex / Settings.WIDTH;
+    float column_offset = HEXAGONAL ? ((column % 2) / 2.f) : 0.0;
+    vec2 grid_offset = 2.f * (vec2(column, row + column_offset) + 0.5f);
+    vec2 top_left = vec2(-1.f);
+    vec2 canvas_size = vec2(Settings.WIDTH, Settings.HEIGHT);
+    vec2 offset = top_left + (grid_offset / canvas_size);
+
+    return scaled_pos + offset;
+}
+
+bool is_selected(int column, int row) {
+    if (column < 0 || row < 0 || column >= int(Settings.WIDTH) || row >= int(Settings.HEIGHT)) {
+        return false;
+    }
+    return Selection.selected[row * int(Settings.WIDTH) + column] != 0;
+}
+
+// The edges of the tile at `column` and `row` whose neighbour across them is selected when the tile isn't, or the
+// other way around. Tiles off the canvas are never selected.
+uint find_selection_edges(int column, int row) {
+    bool selected = is_selected(column, row);
+    // The neighbour across each edge, in the same order as `edges`.
+    ivec2 neighbours[6];
+    int count;
+    if (HEXAGONAL) {
+        // Odd columns are shifted half a tile down, so the tiles beside them are half a row further down. This must
+        // match `hexil::grid`.
+        int shift = column % 2;
+        neighbours = ivec2[6](
+            ivec2(0, -1), ivec2(1, shift - 1), ivec2(1, shift),
+            ivec2(0, 1), ivec2(-1, shift), ivec2(-1, shift - 1)
+        );
+        count = 6;
+    } else {
+        neighbours = ivec2[6](
+            ivec2(0, -1), ivec2(1, 0), ivec2(0, 1),
+            ivec2(-1, 0), ivec2(0, 0), ivec2(0, 0)
+        );
+        count = 4;
+    }
+    uint mask = 0u;
+    for (int i = 0; i < count; i++) {
+        if (is_selected(column + neighbours[i].x, row + neighbours[i].y) != selected) {
+            mask |= 1u << i;
+        }
+    }
+    return mask;
+}
+
+void main() {
+    int column = int(gl_InstanceIndex % Settings.WIDTH);
+    int row = int(gl_InstanceIndex / Settings.WIDTH);
+    hovered = int(gl_InstanceIndex) == overlay.hovered_tile ? 1u : 0u;
+    selection_edges = overlay.selection_color.a > 0.0 ? find_selection_edges(column, row) : 0u;
+    if (overlay.grid_color.a <= 0.0 && hovered == 0u && selection_edges == 0u) {
+        // Nothing to outline, so collapse the tile like `canvas_vert.glsl` does.
+        gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        edges = float[6](0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        return;
+    }
+
+    // `position` spans [-1, 1] across the tile, with y pointing down, like the vertices in `window_wrappers.rs`.
+    float x = position.x;
+    float y = position.y;
+    if (HEXAGONAL) {
+        edges = float[6](y + 1.0, 2.0 - 2.0 * x + y, 2.0 - 2.0 * x - y, 1.0 - y, 2.0 + 2.0 * x - y, 2.0 + 2.0 * x + y);
+    } else {
+        edges = float[6](y + 1.0, 1.0 - x, 1.0 - y, x + 1.0, 1e6, 1e6);
+    }
+
+    vec2 pos = transform_one(position);
+    gl_Position = vec4(pos * overlay.scale + overlay.offset, 0.0, 1.0);
+}
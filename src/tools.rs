@@ -0,0 +1,198 @@
+//! Editing tools. Every tool writes directly into a layer's canvas, and reports exactly which tiles it changed so
+//! that the change can be uploaded to the renderer (and undone) without touching the rest of the canvas. Every tool
+//! also takes the project's active selection, if there is one, and leaves tiles outside of it alone.
+use thiserror::Error;
+
+use crate::app::{CanvasSize, LayerV1Canvas, LayerV2, Selection};
+use crate::grid::OffsetCoord;
+
+mod eraser;
+mod fill;
+mod line;
+mod pencil;
+pub use eraser::*;
+pub use fill::*;
+pub use line::*;
+pub use pencil::*;
+
+/// The palette index that the eraser writes. By convention, the first entry of every palette is the background.
+pub const BACKGROUND_INDEX: u32 = 0;
+
+/// Everything that can stop a tool from being applied. A tool that fails has not changed anything.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ToolError {
+    #[error("This tool only works on base colour layers!")]
+    NotBaseColor,
+    #[error("This tool only works on alpha layers!")]
+    NotAlpha,
+    #[error("This tool only works on shading layers!")]
+    NotShading,
+    #[error("Tile {0:?} is not on the canvas!")]
+    OutOfBounds(OffsetCoord),
+    #[error("Palette index {index} is out of range for a palette of {len} colours!")]
+    PaletteIndexOutOfRange { index: u32, len: usize },
+}
+
+/// A single tile whose value was changed. For base colour layers the value is a palette index, for alpha and shading
+/// layers it is the stored `f32` or `i32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileChange<T = u32> {
+    /// The position of the tile in the layer's canvas.
+    pub index: usize,
+    pub old: T,
+    pub new: T,
+}
+
+/// Every tile changed by one application of a tool, in the order they were changed. Each tile appears at most once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileChanges<T = u32> {
+    changes: Vec<TileChange<T>>,
+}
+
+impl<T> Default for TileChanges<T> {
+    fn default() -> Self {
+        Self {
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl<T> TileChanges<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, TileChange<T>> {
+        self.changes.iter()
+    }
+
+    /// The positions of every changed tile in the layer's canvas, in the order they were changed.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.changes.iter().map(|change| change.index)
+    }
+
+    pub(crate) fn push(&mut self, change: TileChange<T>) {
+        self.changes.push(change);
+    }
+}
+
+impl<T> IntoIterator for TileChanges<T> {
+    type Item = TileChange<T>;
+    type IntoIter = std::vec::IntoIter<TileChange<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a TileChanges<T> {
+    type Item = &'a TileChange<T>;
+    type IntoIter = std::slice::Iter<'a, TileChange<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.iter()
+    }
+}
+
+/// Sets every tile at `positions` in `values` to `value`, recording the tiles that actually changed.
+pub(crate) fn write_values<T: Copy + PartialEq>(
+    values: &mut [T],
+    positions: impl IntoIterator<Item = usize>,
+    value: T,
+) -> TileChanges<T> {
+    let mut changes = TileChanges::new();
+    for position in positions {
+        let old = values[position];
+        if old != value {
+            values[position] = value;
+            changes.push(TileChange {
+                index: position,
+                old,
+                new: value,
+            });
+        }
+    }
+    changes
+}
+
+/// Sets the tile at each position in `values` to the value paired with it, recording the tiles that actually changed.
+/// Positions must not repeat.
+pub(crate) fn write_each<T: Copy + PartialEq>(
+    values: &mut [T],
+    writes: impl IntoIterator<Item = (usize, T)>,
+) -> TileChanges<T> {
+    let mut changes = TileChanges::new();
+    for (position, value) in writes {
+        let old = values[position];
+        if old != value {
+            values[position] = value;
+            changes.push(TileChange {
+                index: position,
+                old,
+                new: value,
+            });
+        }
+    }
+    changes
+}
+
+/// Sets every selected tile in `tiles` to the palette index `index`. All tiles are checked before anything is written,
+/// so on error the layer is untouched.
+pub(crate) fn paint_tiles(
+    layer: &LayerV2,
+    tiles: impl IntoIterator<Item = OffsetCoord>,
+    index: u32,
+    selection: Option<&Selection>,
+) -> Result<TileChanges, ToolError> {
+    let positions = tile_positions(tiles, layer.size())?;
+    paint_tiles_at(layer, within_selection(positions, selection), index)
+}
+
+/// Like `paint_tiles`, but takes positions in the layer's `CanvasIndices`, which must all be in range.
+pub(crate) fn paint_tiles_at(
+    layer: &LayerV2,
+    positions: impl IntoIterator<Item = usize>,
+    index: u32,
+) -> Result<TileChanges, ToolError> {
+    let LayerV1Canvas::BaseColor { palette, canvas } = layer.canvas() else {
+        return Err(ToolError::NotBaseColor);
+    };
+    let palette_len = palette.read().len();
+    if index as usize >= palette_len {
+        return Err(ToolError::PaletteIndexOutOfRange {
+            index,
+            len: palette_len,
+        });
+    }
+    Ok(write_values(&mut canvas.write(), positions, index))
+}
+
+/// Keeps only the positions inside `selection`, or every position if there is no selection.
+pub(crate) fn within_selection(positions: Vec<usize>, selection: Option<&Selection>) -> Vec<usize> {
+    match selection {
+        Some(selection) => positions
+            .into_iter()
+            .filter(|position| selection.contains(*position))
+            .collect(),
+        None => positions,
+    }
+}
+
+/// Converts tiles to positions in a canvas of the given size, failing if any of them are off the canvas.
+pub(crate) fn tile_positions(
+    tiles: impl IntoIterator<Item = OffsetCoord>,
+    size: CanvasSize,
+) -> Result<Vec<usize>, ToolError> {
+    tiles
+        .into_iter()
+        .map(|tile| tile.to_index(size).ok_or(ToolError::OutOfBounds(tile)))
+        .collect()
+}
@@ -0,0 +1,13 @@
+use crate::app::{LayerV2, Selection};
+use crate::grid::OffsetCoord;
+
+use super::{paint_tiles, TileChanges, ToolError, BACKGROUND_INDEX};
+
+/// Resets a single tile of a base colour layer to `BACKGROUND_INDEX`, unless it's outside of `selection`.
+pub fn eraser(
+    layer: &LayerV2,
+    tile: OffsetCoord,
+    selection: Option<&Selection>,
+) -> Result<TileChanges, ToolError> {
+    paint_tiles(layer, [tile], BACKGROUND_INDEX, selection)
+}
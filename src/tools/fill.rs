@@ -0,0 +1,151 @@
+use crate::app::{GridType, LayerV1Canvas, LayerV2, Selection};
+use crate::grid::{OffsetCoord, SquareConnectivity};
+
+use super::{paint_tiles_at, write_values, TileChanges, ToolError};
+
+/// Which tiles a fill replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FillMode {
+    /// Only tiles connected to the starting tile through matching tiles.
+    #[default]
+    Contiguous,
+    /// Every matching tile on the canvas.
+    Global,
+}
+
+/// Settings shared by every kind of fill.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FillOptions {
+    pub mode: FillMode,
+    /// Which tiles touch on square grids. Ignored on hexagonal grids.
+    pub connectivity: SquareConnectivity,
+    /// How far a tile's value may be from the starting tile's value and still match. Ignored for base colour layers,
+    /// where palette indices must match exactly.
+    pub tolerance: f32,
+}
+
+/// Finds every tile that matches the tile at `start`, according to `options`. Uses an explicit stack, so it works on
+/// canvases of any size. With a `selection`, only selected tiles are found, and contiguous regions don't spread through
+/// unselected tiles.
+pub(crate) fn fill_region<T: Copy>(
+    values: &[T],
+    layer: &LayerV2,
+    gridtype: GridType,
+    start: OffsetCoord,
+    options: FillOptions,
+    selection: Option<&Selection>,
+    matches: impl Fn(T, T) -> bool,
+) -> Result<Vec<usize>, ToolError> {
+    let size = layer.size();
+    let start_index = start.to_index(size).ok_or(ToolError::OutOfBounds(start))?;
+    let seed = values[start_index];
+    let selected = |index: usize| selection.is_none_or(|selection| selection.contains(index));
+    if !selected(start_index) {
+        return Ok(Vec::new());
+    }
+
+    match options.mode {
+        FillMode::Global => Ok(values
+            .iter()
+            .enumerate()
+            .filter(|(i, value)| selected(*i) && matches(seed, **value))
+            .map(|(i, _)| i)
+            .collect()),
+        FillMode::Contiguous => {
+            let mut visited = vec![false; values.len()];
+            let mut stack = vec![start_index];
+            let mut region = Vec::new();
+            visited[start_index] = true;
+            while let Some(index) = stack.pop() {
+                region.push(index);
+                let Some(tile) = OffsetCoord::from_index(index, size) else {
+                    continue;
+                };
+                for neighbor in tile.adjacent(gridtype, options.connectivity) {
+                    if let Some(neighbor) = neighbor.to_index(size) {
+                        if !visited[neighbor]
+                            && selected(neighbor)
+                            && matches(seed, values[neighbor])
+                        {
+                            visited[neighbor] = true;
+                            stack.push(neighbor);
+                        }
+                    }
+                }
+            }
+            Ok(region)
+        }
+    }
+}
+
+/// Bucket fill on a base colour layer, replacing tiles with the same palette index as `start` with `index`.
+pub fn fill(
+    layer: &LayerV2,
+    gridtype: GridType,
+    start: OffsetCoord,
+    index: u32,
+    options: FillOptions,
+    selection: Option<&Selection>,
+) -> Result<TileChanges, ToolError> {
+    let LayerV1Canvas::BaseColor { canvas, .. } = layer.canvas() else {
+        return Err(ToolError::NotBaseColor);
+    };
+    let region = fill_region(
+        &canvas.read(),
+        layer,
+        gridtype,
+        start,
+        options,
+        selection,
+        |a, b| a == b,
+    )?;
+    paint_tiles_at(layer, region, index)
+}
+
+/// Bucket fill on an alpha layer, replacing tiles within `options.tolerance` of the value at `start` with `value`.
+pub fn fill_alpha(
+    layer: &mut LayerV2,
+    gridtype: GridType,
+    start: OffsetCoord,
+    value: f32,
+    options: FillOptions,
+    selection: Option<&Selection>,
+) -> Result<TileChanges<f32>, ToolError> {
+    let LayerV1Canvas::Alpha(alpha) = layer.canvas() else {
+        return Err(ToolError::NotAlpha);
+    };
+    let region = fill_region(alpha, layer, gridtype, start, options, selection, |a, b| {
+        (a - b).abs() <= options.tolerance
+    })?;
+    let LayerV1Canvas::Alpha(alpha) = layer.canvas_mut() else {
+        unreachable!("We just checked that this is an alpha layer.");
+    };
+    Ok(write_values(alpha, region, value))
+}
+
+/// Bucket fill on a shading layer, replacing tiles within `options.tolerance` of the value at `start` with `value`.
+pub fn fill_shading(
+    layer: &mut LayerV2,
+    gridtype: GridType,
+    start: OffsetCoord,
+    value: i32,
+    options: FillOptions,
+    selection: Option<&Selection>,
+) -> Result<TileChanges<i32>, ToolError> {
+    let LayerV1Canvas::Shading(shading) = layer.canvas() else {
+        return Err(ToolError::NotShading);
+    };
+    let region = fill_region(
+        shading,
+        layer,
+        gridtype,
+        start,
+        options,
+        selection,
+        |a, b| (a as i64 - b as i64).abs() as f64 <= options.tolerance as f64,
+    )?;
+    let LayerV1Canvas::Shading(shading) = layer.canvas_mut() else {
+        unreachable!("We just checked that this is a shading layer.");
+    };
+    Ok(write_values(shading, region, value))
+}
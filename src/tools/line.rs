@@ -0,0 +1,48 @@
+use crate::app::{GridType, LayerV2, Selection};
+use crate::grid::{CubeCoord, OffsetCoord};
+
+use super::{paint_tiles, TileChanges, ToolError};
+
+/// The tiles on the straight line from `from` to `to`, including both ends, with no gaps and no repeats.
+///
+/// On hexagonal grids, consecutive tiles are always neighbours. On square grids, consecutive tiles may touch only at a
+/// corner, which is what pixel artists expect from a one tile wide line.
+pub fn line_tiles(from: OffsetCoord, to: OffsetCoord, gridtype: GridType) -> Vec<OffsetCoord> {
+    match gridtype {
+        GridType::Hexagonal => CubeCoord::from(from)
+            .line_to(CubeCoord::from(to))
+            .map(OffsetCoord::from)
+            .collect(),
+        GridType::Square => {
+            let (dx, dy) = (to.col - from.col, to.row - from.row);
+            let steps = dx.unsigned_abs().max(dy.unsigned_abs());
+            if steps == 0 {
+                return vec![from];
+            }
+            // Nudged so that ties between two tiles always round the same way, regardless of direction.
+            let (x0, y0) = (from.col as f64 + 1e-6, from.row as f64 + 1e-6);
+            (0..=steps)
+                .map(|i| {
+                    let t = i as f64 / steps as f64;
+                    OffsetCoord::new(
+                        (x0 + dx as f64 * t).round() as i32,
+                        (y0 + dy as f64 * t).round() as i32,
+                    )
+                })
+                .collect()
+        }
+    }
+}
+
+/// Sets every tile on the straight line from `from` to `to` of a base colour layer to the palette index `index`,
+/// except those outside of `selection`.
+pub fn line(
+    layer: &LayerV2,
+    gridtype: GridType,
+    from: OffsetCoord,
+    to: OffsetCoord,
+    index: u32,
+    selection: Option<&Selection>,
+) -> Result<TileChanges, ToolError> {
+    paint_tiles(layer, line_tiles(from, to, gridtype), index, selection)
+}
@@ -0,0 +1,14 @@
+use crate::app::{LayerV2, Selection};
+use crate::grid::OffsetCoord;
+
+use super::{paint_tiles, TileChanges, ToolError};
+
+/// Sets a single tile of a base colour layer to the palette index `index`, unless it's outside of `selection`.
+pub fn pencil(
+    layer: &LayerV2,
+    tile: OffsetCoord,
+    index: u32,
+    selection: Option<&Selection>,
+) -> Result<TileChanges, ToolError> {
+    paint_tiles(layer, [tile], index, selection)
+}
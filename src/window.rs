@@ -7,12 +7,30 @@ use try_log::log_tries;
 use winit::error::EventLoopError;
 use winit::error::OsError;
 use winit::event;
+use winit::event::ElementState;
 use winit::event::Event;
+use winit::event::MouseButton;
+use winit::event::MouseScrollDelta;
 use winit::event_loop::EventLoop;
 use winit::event_loop::EventLoopWindowTarget;
+use winit::keyboard::Key;
+use winit::keyboard::NamedKey;
 use winit::window::Window;
 
-use crate::render::RenderCommand;
+use crate::app::clipboard::system::{ClipboardBackend, MemoryClipboard, SystemClipboard};
+use crate::app::{CanvasSize, CanvasSnapshot, GridType, Project};
+use crate::camera::Camera;
+use crate::grid::picking::pick_tile;
+use crate::render::{OverlaySettings, RenderCommand};
+
+/// How much one notch of the mouse wheel, or one press of `+` or `-`, zooms the camera.
+const ZOOM_STEP: f32 = 1.25;
+/// How many pixels of smooth scrolling (from a touchpad, for example) count as one notch of the mouse wheel.
+const PIXELS_PER_SCROLL_LINE: f64 = 20.0;
+/// How far one press of an arrow key pans the camera, as a fraction of the window's size.
+const KEYBOARD_PAN_FRACTION: f64 = 0.1;
+/// The layer copy, cut and paste work on. The window has no way to pick a layer yet.
+const CLIPBOARD_LAYER: usize = 0;
 
 /// The unified error type for Hexil's windowing system.
 #[derive(Debug, Error)]
@@ -37,10 +55,238 @@ impl From<OsError> for WindowingError {
     }
 }
 
-/// A message that can be sent to the window event loop. Currently, there's nothing here. As long as that remains the case,
-/// this should act like `Infallible` (that is, the compiler should recognize that any code which would need a value of this
-/// type must never run, so, for example, waiting on an `std::sync::mpsc<WindowCommand>` should be optimized out as a noop.)
-pub enum WindowCommand {}
+/// A message that can be sent to the window event loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowCommand {
+    /// The renderer is now drawing a canvas of this size and grid type, which the camera controls need to know.
+    CanvasChanged {
+        size: CanvasSize,
+        gridtype: GridType,
+    },
+}
+
+/// Everything the event loop needs to move the camera in response to input.
+struct CameraControls {
+    camera: Camera,
+    window_size: [u32; 2],
+    canvas: Option<(CanvasSize, GridType)>,
+    /// The last known cursor position, in physical pixels.
+    cursor: [f64; 2],
+    /// Whether the cursor is over the window at all.
+    cursor_in_window: bool,
+    /// Whether the middle mouse button is held, dragging the canvas.
+    dragging: bool,
+    /// The position of the tile the renderer was last told is under the cursor.
+    hovered: Option<usize>,
+}
+
+impl CameraControls {
+    fn new(window_size: [u32; 2]) -> Self {
+        Self {
+            camera: Camera::default(),
+            window_size,
+            canvas: None,
+            cursor: [0.0, 0.0],
+            cursor_in_window: false,
+            dragging: false,
+            hovered: None,
+        }
+    }
+
+    fn pan(&mut self, delta: [f64; 2]) -> Option<Camera> {
+        let (size, gridtype) = self.canvas?;
+        self.camera
+            .pan_by_pixels(delta, self.window_size, size, gridtype);
+        Some(self.camera)
+    }
+
+    fn zoom(&mut self, factor: f32, at: [f64; 2]) -> Option<Camera> {
+        let (size, gridtype) = self.canvas?;
+        self.camera
+            .zoom_at(factor, at, self.window_size, size, gridtype);
+        Some(self.camera)
+    }
+
+    /// The position of the tile under the cursor, if the cursor is over the canvas.
+    fn tile_under_cursor(&self) -> Option<usize> {
+        let (size, gridtype) = self.canvas?;
+        if !self.cursor_in_window {
+            return None;
+        }
+        let view = self.camera.view_transform(self.window_size, size, gridtype);
+        pick_tile(self.cursor, self.window_size, size, gridtype, &view)?.to_index(size)
+    }
+
+    /// Works out which tile is under the cursor, returning it if that changed since the last call.
+    fn update_hovered(&mut self) -> Option<Option<usize>> {
+        let hovered = self.tile_under_cursor();
+        if hovered == self.hovered {
+            return None;
+        }
+        self.hovered = hovered;
+        Some(hovered)
+    }
+
+    fn window_centre(&self) -> [f64; 2] {
+        self.window_size.map(|x| x as f64 / 2.0)
+    }
+
+    /// Moves the camera according to a window event, returning the new camera if it moved.
+    fn handle(&mut self, event: &event::WindowEvent) -> Option<Camera> {
+        match event {
+            event::WindowEvent::Resized(new_size) => {
+                self.window_size = (*new_size).into();
+                None
+            }
+            event::WindowEvent::CursorMoved { position, .. } => {
+                let position = [position.x, position.y];
+                let delta = [position[0] - self.cursor[0], position[1] - self.cursor[1]];
+                self.cursor = position;
+                self.cursor_in_window = true;
+                if self.dragging {
+                    self.pan(delta)
+                } else {
+                    None
+                }
+            }
+            event::WindowEvent::CursorLeft { .. } => {
+                self.cursor_in_window = false;
+                None
+            }
+            event::WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Middle,
+                ..
+            } => {
+                self.dragging = *state == ElementState::Pressed;
+                None
+            }
+            event::WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        (position.y / PIXELS_PER_SCROLL_LINE) as f32
+                    }
+                };
+                self.zoom(ZOOM_STEP.powf(lines), self.cursor)
+            }
+            event::WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed =>
+            {
+                let [width, height] = self.window_size.map(|x| x as f64 * KEYBOARD_PAN_FRACTION);
+                match event.logical_key.as_ref() {
+                    Key::Named(NamedKey::ArrowLeft) => self.pan([width, 0.0]),
+                    Key::Named(NamedKey::ArrowRight) => self.pan([-width, 0.0]),
+                    Key::Named(NamedKey::ArrowUp) => self.pan([0.0, height]),
+                    Key::Named(NamedKey::ArrowDown) => self.pan([0.0, -height]),
+                    Key::Character("+" | "=") => self.zoom(ZOOM_STEP, self.window_centre()),
+                    Key::Character("-") => self.zoom(1.0 / ZOOM_STEP, self.window_centre()),
+                    Key::Character("0") | Key::Named(NamedKey::Home) => {
+                        self.camera = Camera::default();
+                        Some(self.camera)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The project being edited, and what the event loop needs to copy its tiles to and paste them from the clipboard.
+struct ClipboardControls {
+    project: Project,
+    clipboard: Box<dyn ClipboardBackend>,
+    /// Whether the key that copying, cutting and pasting are done with is held: command on macOS, and control
+    /// everywhere else.
+    shortcut_held: bool,
+}
+
+impl ClipboardControls {
+    fn new(project: Project) -> Self {
+        let clipboard: Box<dyn ClipboardBackend> = match SystemClipboard::new() {
+            Ok(clipboard) => Box::new(clipboard),
+            Err(e) => {
+                // Tiles can still be copied and pasted within this window.
+                warn!("Couldn't connect to the system clipboard! {}", e);
+                Box::new(MemoryClipboard::new())
+            }
+        };
+        Self {
+            project,
+            clipboard,
+            shortcut_held: false,
+        }
+    }
+
+    /// Copies, cuts or pastes according to a window event, returning what the renderer has to be told about the
+    /// changes to the project.
+    fn handle(&mut self, event: &event::WindowEvent) -> Vec<RenderCommand> {
+        match event {
+            event::WindowEvent::ModifiersChanged(modifiers) => {
+                let state = modifiers.state();
+                self.shortcut_held = if cfg!(target_os = "macos") {
+                    state.super_key()
+                } else {
+                    state.control_key()
+                };
+                Vec::new()
+            }
+            event::WindowEvent::KeyboardInput { event, .. }
+                if self.shortcut_held && event.state == ElementState::Pressed && !event.repeat =>
+            {
+                let clipboard = self.clipboard.as_mut();
+                let changed = match event.logical_key.as_ref() {
+                    Key::Character("c" | "C") => self
+                        .project
+                        .copy_to_clipboard(CLIPBOARD_LAYER, clipboard)
+                        .map(|_| false),
+                    Key::Character("x" | "X") => self
+                        .project
+                        .cut_to_clipboard(CLIPBOARD_LAYER, clipboard)
+                        .map(|_| true),
+                    Key::Character("v" | "V") => self
+                        .project
+                        .paste_from_clipboard(CLIPBOARD_LAYER, clipboard)
+                        .map(|_| true),
+                    _ => return Vec::new(),
+                };
+                match changed {
+                    Ok(true) => vec![
+                        RenderCommand::CanvasReplaced(CanvasSnapshot::of_project(&self.project)),
+                        RenderCommand::SelectionChanged(
+                            self.project
+                                .selection()
+                                .map(|selection| selection.positions().collect())
+                                .unwrap_or_default(),
+                        ),
+                    ],
+                    Ok(false) => Vec::new(),
+                    Err(e) => {
+                        warn!("Clipboard operation failed! {}", e);
+                        Vec::new()
+                    }
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Toggles parts of the overlay according to a window event, returning whether anything changed.
+fn toggle_overlay(event: &event::WindowEvent, overlay: &mut OverlaySettings) -> bool {
+    match event {
+        event::WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+            match event.logical_key.as_ref() {
+                Key::Character("g") => overlay.grid = !overlay.grid,
+                Key::Character("h") => overlay.highlights = !overlay.highlights,
+                _ => return false,
+            }
+            true
+        }
+        _ => false,
+    }
+}
 
 /// Makes an event loop suitable for Hexil.
 #[instrument(skip_all, err)]
@@ -66,44 +312,115 @@ pub fn make_window(title: &str, eloop: &EventLoop<WindowCommand>) -> Result<Wind
 /// If this function returns, the event loop is dead. Ok(()) means it closed gracefully.
 /// This must be run on the main thread, and will not return until program termination. As such,
 /// any code which runs independently must be initialized to a separate thread before this is called.
-#[instrument(err)]
+///
+/// The camera is driven from here: middle-drag or the arrow keys pan, the mouse wheel or `+` and `-` zoom, and `0` or
+/// `Home` reset it. `g` toggles the grid overlay, and `h` toggles the outlines of the hovered tile and the selection.
+///
+/// `project` is edited from here too: control (command on macOS) with `c`, `x` or `v` copies, cuts or pastes tiles of
+/// its bottom layer through the system clipboard.
+#[instrument(skip(project), err)]
 #[log_tries(tracing::error)]
 pub fn run_event_loop(
     eloop: EventLoop<WindowCommand>,
+    window: std::sync::Arc<Window>,
     render_handle: std::sync::mpsc::Sender<RenderCommand>,
+    project: Project,
 ) -> Result<(), EventLoopError> {
+    let mut controls = CameraControls::new(window.inner_size().into());
+    let mut overlay = OverlaySettings::default();
+    let mut clipboard = ClipboardControls::new(project);
     eloop.run(|event, window_target| match event {
         Event::WindowEvent {
             window_id: _,
             event,
-        } => match event {
-            event::WindowEvent::Resized(new_size) => send_or_exit(
-                &render_handle,
-                window_target,
-                RenderCommand::WindowResized(new_size.into()),
-            ),
-            event::WindowEvent::CloseRequested => {
-                info!("Closing window!");
-                send_or_exit(&render_handle, window_target, RenderCommand::Shutdown);
-                window_target.exit();
+        } => {
+            if let Some(camera) = controls.handle(&event) {
+                send_or_exit(
+                    &render_handle,
+                    window_target,
+                    RenderCommand::CameraChanged(camera),
+                );
+                window.request_redraw();
             }
-            event::WindowEvent::Destroyed => {
-                warn!("Window destroyed!!");
-                send_or_exit(&render_handle, window_target, RenderCommand::Shutdown);
+            if let Some(hovered) = controls.update_hovered() {
+                send_or_exit(
+                    &render_handle,
+                    window_target,
+                    RenderCommand::HoveredTileChanged(hovered),
+                );
+                window.request_redraw();
             }
-            event::WindowEvent::RedrawRequested => {
-                send_or_exit(&render_handle, window_target, RenderCommand::Redraw);
-                window_target.set_control_flow(winit::event_loop::ControlFlow::Poll);
+            if toggle_overlay(&event, &mut overlay) {
+                send_or_exit(
+                    &render_handle,
+                    window_target,
+                    RenderCommand::OverlayChanged(overlay),
+                );
+                window.request_redraw();
             }
-            _ => (),
-        },
-        Event::UserEvent(_) => (),
+            let commands = clipboard.handle(&event);
+            if !commands.is_empty() {
+                for command in commands {
+                    send_or_exit(&render_handle, window_target, command);
+                }
+                window.request_redraw();
+            }
+            handle_window_event(event, &render_handle, window_target)
+        }
+        Event::UserEvent(WindowCommand::CanvasChanged { size, gridtype })
+            if controls.canvas != Some((size, gridtype)) =>
+        {
+            controls.canvas = Some((size, gridtype));
+            controls.camera = Camera::default();
+            send_or_exit(
+                &render_handle,
+                window_target,
+                RenderCommand::CameraChanged(controls.camera),
+            );
+            // Positions mean different tiles on a canvas of a different size.
+            controls.hovered = None;
+            send_or_exit(
+                &render_handle,
+                window_target,
+                RenderCommand::HoveredTileChanged(controls.update_hovered().flatten()),
+            );
+            window.request_redraw();
+        }
         _ => (),
     })?;
 
     Ok(())
 }
 
+/// Forwards the window events the renderer cares about to it.
+fn handle_window_event(
+    event: event::WindowEvent,
+    render_handle: &std::sync::mpsc::Sender<RenderCommand>,
+    window_target: &EventLoopWindowTarget<WindowCommand>,
+) {
+    match event {
+        event::WindowEvent::Resized(new_size) => send_or_exit(
+            render_handle,
+            window_target,
+            RenderCommand::WindowResized(new_size.into()),
+        ),
+        event::WindowEvent::CloseRequested => {
+            info!("Closing window!");
+            send_or_exit(render_handle, window_target, RenderCommand::Shutdown);
+            window_target.exit();
+        }
+        event::WindowEvent::Destroyed => {
+            warn!("Window destroyed!!");
+            send_or_exit(render_handle, window_target, RenderCommand::Shutdown);
+        }
+        event::WindowEvent::RedrawRequested => {
+            send_or_exit(render_handle, window_target, RenderCommand::Redraw);
+            window_target.set_control_flow(winit::event_loop::ControlFlow::Poll);
+        }
+        _ => (),
+    }
+}
+
 /// Sends `command`, and calls `window_target.exit()` if the render thread is dead.
 fn send_or_exit(
     render_handle: &std::sync::mpsc::Sender<RenderCommand>,
@@ -0,0 +1,245 @@
+//! Checks that animated GIFs index straight into the project palette, and that both formats keep frame timing, looping,
+//! scaling and transparency.
+use std::time::Duration;
+
+use hexil::app::{CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Project};
+use hexil::export::animation::{
+    render_frames, write_apng, write_gif, AnimationError, AnimationFrame, AnimationOptions,
+};
+use hexil::export::{oklab_to_srgb8, rasterize, RasterOptions};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 3,
+    height: 2,
+};
+
+fn palette() -> Vec<Color> {
+    vec![
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(0.6, 0.2, 0.1),
+        Color::new(0.5, -0.05, -0.2),
+        Color::new(1.0, 0.0, 0.0),
+    ]
+}
+
+fn frame_project(indices: Vec<u32>) -> Project {
+    project_with_palette(palette(), indices)
+}
+
+fn project_with_palette(palette: Vec<Color>, indices: Vec<u32>) -> Project {
+    let mut project = Project::new("Frame".to_owned(), SIZE, GridType::Square);
+    project.push_layer(LayerV2::new(
+        None,
+        SIZE,
+        LayerV1Canvas::BaseColor {
+            palette: palette.into(),
+            canvas: indices.into(),
+        },
+    ));
+    project
+}
+
+fn options(scale: u32, plays: u16, transparent_index: Option<u8>) -> AnimationOptions {
+    AnimationOptions {
+        raster: RasterOptions {
+            pixels_per_tile: 2,
+            background: None,
+        },
+        scale,
+        plays,
+        transparent_index,
+    }
+}
+
+/// Every frame of a GIF as colour table indices, with its delay, and the GIF's colour table and repeat.
+type DecodedGif = (Vec<(Vec<u8>, u16, Option<u8>)>, Vec<u8>, gif::Repeat);
+
+fn decode_gif(data: &[u8]) -> DecodedGif {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(data).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push((frame.buffer.to_vec(), frame.delay, frame.transparent));
+    }
+    let table = decoder.global_palette().unwrap().to_vec();
+    (frames, table, decoder.repeat())
+}
+
+#[test]
+fn gifs_index_the_project_palette() {
+    let first = frame_project(vec![0, 1, 2, 3, 2, 1]);
+    let second = frame_project(vec![3, 3, 3, 1, 1, 1]);
+    let frames = [
+        AnimationFrame {
+            project: &first,
+            duration: Duration::from_millis(100),
+        },
+        AnimationFrame {
+            project: &second,
+            duration: Duration::from_millis(254),
+        },
+    ];
+    let mut data = Vec::new();
+    write_gif(&frames, options(3, 0, None), &mut data).unwrap();
+    let (decoded, table, repeat) = decode_gif(&data);
+
+    let expected_table: Vec<u8> = palette().into_iter().flat_map(oklab_to_srgb8).collect();
+    assert_eq!(table[..12], expected_table);
+    assert_eq!(repeat, gif::Repeat::Infinite);
+    assert_eq!(decoded.len(), 2);
+    assert_eq!((decoded[0].1, decoded[1].1), (10, 25));
+    // 3 tiles of 2 pixels, scaled 3 times, is 18 pixels across.
+    let width = 18;
+    assert_eq!(decoded[0].0.len(), width * 12);
+    for (row, tiles) in [(0, [0, 1, 2]), (11, [3, 2, 1])] {
+        for (tile, index) in tiles.iter().enumerate() {
+            assert_eq!(decoded[0].0[row * width + tile * 6], *index);
+            assert_eq!(decoded[0].0[row * width + tile * 6 + 5], *index);
+        }
+    }
+    assert!(decoded[1].0[..width * 6].iter().all(|index| *index == 3));
+    assert!(decoded[1].0[width * 6..].iter().all(|index| *index == 1));
+}
+
+#[test]
+fn transparent_index_and_looping() {
+    let project = frame_project(vec![0, 1, 0, 1, 0, 1]);
+    let frames = [AnimationFrame {
+        project: &project,
+        duration: Duration::from_millis(50),
+    }];
+    let mut data = Vec::new();
+    write_gif(&frames, options(1, 3, Some(0)), &mut data).unwrap();
+    let (decoded, _, repeat) = decode_gif(&data);
+    assert_eq!(repeat, gif::Repeat::Finite(2));
+    assert_eq!(decoded[0].2, Some(0));
+    assert_eq!(&decoded[0].0[..6], &[0, 0, 1, 1, 0, 0]);
+
+    let rasters = render_frames(&frames, options(1, 3, Some(0))).unwrap();
+    assert_eq!(rasters[0].pixels[0], [0; 4]);
+    assert_eq!(rasters[0].pixels[2][3], 255);
+
+    // Playing once needs no looping at all.
+    let mut data = Vec::new();
+    write_gif(&frames, options(1, 1, None), &mut data).unwrap();
+    assert_eq!(decode_gif(&data).2, gif::Repeat::Finite(0));
+}
+
+#[test]
+fn opaque_pixels_never_use_the_transparent_index() {
+    let white = Color::new(1.0, 0.0, 0.0);
+    let black = Color::new(0.0, 0.0, 0.0);
+    let orange = palette()[1];
+    // Black is only in the second frame, so it's added to the table after the palette, where the transparent index
+    // is.
+    let first = project_with_palette(vec![white, orange], vec![1; 6]);
+    let second = project_with_palette(vec![white, black], vec![1, 1, 1, 0, 0, 0]);
+    let frames = [&first, &second].map(|project| AnimationFrame {
+        project,
+        duration: Duration::from_millis(50),
+    });
+    let mut data = Vec::new();
+    write_gif(&frames, options(1, 0, Some(2)), &mut data).unwrap();
+    let (decoded, table, _) = decode_gif(&data);
+    let (pixels, _, transparent) = &decoded[1];
+    assert_eq!(*transparent, Some(2));
+    let black_index = pixels[0] as usize;
+    assert_ne!(black_index, 2);
+    assert_eq!(table[black_index * 3..black_index * 3 + 3], [0, 0, 0]);
+    assert!(pixels[..12]
+        .iter()
+        .all(|index| *index as usize == black_index));
+    // Background tiles draw palette entry 0, which is white and opaque.
+    assert!(pixels[12..].iter().all(|index| *index == 0));
+}
+
+#[test]
+fn apngs_match_the_still_export() {
+    let mut hex = Project::new("Hex".to_owned(), SIZE, GridType::Hexagonal);
+    hex.push_layer(LayerV2::new(
+        None,
+        SIZE,
+        LayerV1Canvas::BaseColor {
+            palette: palette().into(),
+            canvas: vec![1, 2, 3, 1, 2, 3].into(),
+        },
+    ));
+    let frames = [
+        AnimationFrame {
+            project: &hex,
+            duration: Duration::from_millis(40),
+        },
+        AnimationFrame {
+            project: &hex,
+            duration: Duration::from_secs(90),
+        },
+    ];
+    let options = AnimationOptions {
+        raster: RasterOptions {
+            pixels_per_tile: 8,
+            background: None,
+        },
+        scale: 2,
+        plays: 4,
+        transparent_index: None,
+    };
+    let mut data = Vec::new();
+    write_apng(&frames, options, &mut data).unwrap();
+
+    let still = rasterize(&hex, options.raster);
+    let mut reader = png::Decoder::new(&data[..]).read_info().unwrap();
+    let control = reader.info().animation_control.unwrap();
+    assert_eq!((control.num_frames, control.num_plays), (2, 4));
+    assert_eq!(
+        (reader.info().width, reader.info().height),
+        (still.width * 2, still.height * 2)
+    );
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let mut delays = Vec::new();
+    for _ in 0..2 {
+        reader.next_frame(&mut buffer).unwrap();
+        let frame = reader.info().frame_control.unwrap();
+        delays.push((frame.delay_num, frame.delay_den));
+        let width = still.width as usize * 2;
+        for (i, pixel) in still.pixels.iter().enumerate() {
+            let (x, y) = (i % still.width as usize * 2, i / still.width as usize * 2);
+            let at = (y * width + x + 1) * 4;
+            assert_eq!(&buffer[at..at + 4], pixel);
+        }
+    }
+    assert_eq!(delays, [(40, 1000), (9000, 100)]);
+}
+
+#[test]
+fn bad_animations_are_rejected() {
+    assert!(matches!(
+        write_gif(&[], AnimationOptions::default(), Vec::new()),
+        Err(AnimationError::NoFrames)
+    ));
+    let project = frame_project(vec![0; 6]);
+    let other = Project::new(
+        "Other".to_owned(),
+        CanvasSize {
+            width: 1,
+            height: 1,
+        },
+        GridType::Square,
+    );
+    let frame = |project| AnimationFrame {
+        project,
+        duration: Duration::from_millis(100),
+    };
+    assert!(matches!(
+        write_apng(
+            &[frame(&project), frame(&other)],
+            AnimationOptions::default(),
+            Vec::new()
+        ),
+        Err(AnimationError::FrameMismatch(1))
+    ));
+    assert!(matches!(
+        write_gif(&[frame(&project)], options(0, 0, None), Vec::new()),
+        Err(AnimationError::ZeroScale)
+    ));
+}
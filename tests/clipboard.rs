@@ -0,0 +1,303 @@
+//! Checks copying, cutting and pasting, and that floating selections keep their shape as they move and turn.
+use hexil::app::{
+    CanvasSize, Color, EditError, FloatingSelection, GridType, LayerV1Canvas, LayerV2, Project,
+    Selection, SelectionMode,
+};
+use hexil::grid::{CubeCoord, HexDirection, OffsetCoord};
+
+mod common;
+use common::{base_color_indices, base_color_layer};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 6,
+    height: 5,
+};
+
+fn base_color(palette: Vec<Color>, indices: Vec<u32>) -> LayerV2 {
+    base_color_layer(SIZE, palette, indices)
+}
+
+fn indices(project: &Project, layer: usize) -> Vec<u32> {
+    base_color_indices(&project.layers()[layer])
+}
+
+fn index(tile: OffsetCoord) -> usize {
+    tile.to_index(SIZE).unwrap()
+}
+
+/// A project with one base colour layer, with colour `i` at the `i`th tile of `tiles` and the background elsewhere,
+/// and those tiles selected.
+fn project_with(gridtype: GridType, tiles: &[OffsetCoord]) -> Project {
+    let mut indices = vec![0; SIZE.area() as usize];
+    for (i, tile) in tiles.iter().enumerate() {
+        indices[index(*tile)] = i as u32 + 1;
+    }
+    let palette = (0..=tiles.len())
+        .map(|i| Color::new(i as f32 / 10.0, 0.0, 0.0))
+        .collect();
+    let mut project = Project::new("Clipboard".to_owned(), SIZE, gridtype);
+    project.push_layer(base_color(palette, indices));
+    let selection =
+        Selection::from_positions(SIZE, gridtype, tiles.iter().map(|tile| index(*tile)));
+    project.select(&selection, SelectionMode::Replace).unwrap();
+    project
+}
+
+/// The floating tiles relative to the anchor, in cube coordinates on hexagonal grids.
+fn shape(floating: &FloatingSelection) -> Vec<(i32, i32)> {
+    let anchor = floating.anchor();
+    let mut shape: Vec<(i32, i32)> = floating
+        .tiles()
+        .map(|tile| match floating.gridtype() {
+            GridType::Hexagonal => {
+                let offset = CubeCoord::from(tile).sub(CubeCoord::from(anchor));
+                (offset.q(), offset.r())
+            }
+            GridType::Square => (tile.col - anchor.col, tile.row - anchor.row),
+        })
+        .collect();
+    shape.sort();
+    shape
+}
+
+fn sorted<const N: usize>(mut tiles: [OffsetCoord; N]) -> Vec<OffsetCoord> {
+    tiles.sort();
+    tiles.to_vec()
+}
+
+/// Where the floating tiles would be stamped, in order.
+fn tiles(floating: &FloatingSelection) -> Vec<OffsetCoord> {
+    let mut tiles: Vec<OffsetCoord> = floating.tiles().collect();
+    tiles.sort();
+    tiles
+}
+
+#[test]
+fn cut_and_paste_moves_tiles_and_undoes_as_one() {
+    let tiles = [OffsetCoord::new(1, 1), OffsetCoord::new(2, 1)];
+    let mut project = project_with(GridType::Square, &tiles);
+    let before = indices(&project, 0);
+
+    let mut floating = project.cut_selection(0).unwrap();
+    assert_eq!(floating.len(), 2);
+    assert!(indices(&project, 0).iter().all(|index| *index == 0));
+    floating.move_by(2, 3);
+    project.paste(0, &floating).unwrap();
+    let after = indices(&project, 0);
+    assert_eq!(after[index(OffsetCoord::new(3, 4))], 1);
+    assert_eq!(after[index(OffsetCoord::new(4, 4))], 2);
+    assert_eq!(project.selection().map(Selection::len), Some(2));
+
+    assert!(project.undo());
+    assert!(indices(&project, 0).iter().all(|index| *index == 0));
+    assert!(project.undo());
+    assert_eq!(indices(&project, 0), before);
+    assert_eq!(project.selection().map(Selection::len), Some(2));
+}
+
+#[test]
+fn pasting_drops_tiles_off_the_canvas() {
+    let tiles = [OffsetCoord::new(4, 0), OffsetCoord::new(5, 0)];
+    let mut project = project_with(GridType::Square, &tiles);
+    let mut floating = project.copy_selection(0).unwrap();
+    floating.move_by(1, 0);
+    project.paste(0, &floating).unwrap();
+    let after = indices(&project, 0);
+    assert_eq!(after[index(OffsetCoord::new(5, 0))], 1);
+    assert_eq!(project.selection().map(Selection::len), Some(1));
+
+    // When every tile lands off the canvas, nothing is pasted, and the selection is left alone.
+    floating.move_by(3, 0);
+    let before = (indices(&project, 0), project.selection().cloned());
+    project.paste(0, &floating).unwrap();
+    assert_eq!((indices(&project, 0), project.selection().cloned()), before);
+}
+
+#[test]
+fn moving_far_off_the_canvas_stops_at_the_limits() {
+    for gridtype in [GridType::Square, GridType::Hexagonal] {
+        let tiles = [OffsetCoord::new(1, 1), OffsetCoord::new(2, 1)];
+        let mut project = project_with(gridtype, &tiles);
+        let mut floating = project.copy_selection(0).unwrap();
+        for (columns, rows) in [(i32::MAX, i32::MIN), (i32::MAX, i32::MAX), (i32::MIN, 0)] {
+            floating.move_by(columns, rows);
+            floating.move_by(columns, rows);
+            assert!(floating.tiles().all(|tile| tile.to_index(SIZE).is_none()));
+            let before = indices(&project, 0);
+            project.paste(0, &floating).unwrap();
+            assert_eq!(indices(&project, 0), before);
+        }
+    }
+}
+
+#[test]
+fn odd_moves_keep_hex_shapes() {
+    // A tile and its neighbour above and to the right. From an even column that neighbour is one row up, and from an
+    // odd column it's on the same row.
+    let start = OffsetCoord::new(2, 2);
+    let up_right = OffsetCoord::from(CubeCoord::from(start).neighbor(HexDirection::UpRight));
+    let mut project = project_with(GridType::Hexagonal, &[start, up_right]);
+    let mut floating = project.copy_selection(0).unwrap();
+    let original = shape(&floating);
+    for (columns, rows) in [(1, 0), (1, 1), (-3, 0), (2, -1)] {
+        floating.move_by(columns, rows);
+        assert_eq!(shape(&floating), original);
+    }
+    floating.move_to(OffsetCoord::new(1, 2));
+    project.paste(0, &floating).unwrap();
+    let after = indices(&project, 0);
+    let pasted: Vec<OffsetCoord> = floating.tiles().collect();
+    let (lower, upper) = if after[index(pasted[0])] == 1 {
+        (pasted[0], pasted[1])
+    } else {
+        (pasted[1], pasted[0])
+    };
+    assert_eq!(after[index(upper)], 2);
+    assert_eq!(
+        OffsetCoord::from(CubeCoord::from(lower).neighbor(HexDirection::UpRight)),
+        upper
+    );
+}
+
+#[test]
+fn rotations_follow_the_grid() {
+    let centre = OffsetCoord::new(2, 2);
+    let down = OffsetCoord::from(CubeCoord::from(centre).neighbor(HexDirection::Down));
+    let up_right = OffsetCoord::from(CubeCoord::from(centre).neighbor(HexDirection::UpRight));
+    let project = project_with(GridType::Hexagonal, &[centre, down, up_right]);
+    let mut floating = project.copy_selection(0).unwrap();
+    assert_eq!(floating.anchor(), centre);
+    let original = floating.clone();
+
+    floating.rotate_clockwise();
+    let neighbor = |direction| OffsetCoord::from(CubeCoord::from(centre).neighbor(direction));
+    assert_eq!(
+        tiles(&floating),
+        sorted([
+            centre,
+            neighbor(HexDirection::DownLeft),
+            neighbor(HexDirection::DownRight)
+        ])
+    );
+    for _ in 0..5 {
+        floating.rotate_clockwise();
+    }
+    assert_eq!(floating, original);
+    floating.rotate_anticlockwise();
+    floating.rotate_clockwise();
+    assert_eq!(floating, original);
+
+    let project = project_with(
+        GridType::Square,
+        &[OffsetCoord::new(2, 2), OffsetCoord::new(2, 3)],
+    );
+    let mut floating = project.copy_selection(0).unwrap();
+    let original = floating.clone();
+    floating.rotate_clockwise();
+    assert_eq!(
+        tiles(&floating),
+        sorted([OffsetCoord::new(2, 2), OffsetCoord::new(1, 2)])
+    );
+    for _ in 0..3 {
+        floating.rotate_clockwise();
+    }
+    assert_eq!(floating, original);
+}
+
+#[test]
+fn flips_mirror_around_the_anchor() {
+    let centre = OffsetCoord::new(3, 2);
+    let neighbor = |direction| OffsetCoord::from(CubeCoord::from(centre).neighbor(direction));
+    let project = project_with(
+        GridType::Hexagonal,
+        &[
+            centre,
+            neighbor(HexDirection::DownRight),
+            neighbor(HexDirection::Up),
+        ],
+    );
+    let mut floating = project.copy_selection(0).unwrap();
+    assert_eq!(floating.anchor(), centre);
+    let original = floating.clone();
+    floating.flip_horizontally();
+    assert_eq!(
+        tiles(&floating),
+        sorted([
+            centre,
+            neighbor(HexDirection::DownLeft),
+            neighbor(HexDirection::Up)
+        ])
+    );
+    floating.flip_horizontally();
+    assert_eq!(floating, original);
+    floating.flip_vertically();
+    assert_eq!(
+        tiles(&floating),
+        sorted([
+            centre,
+            neighbor(HexDirection::UpRight),
+            neighbor(HexDirection::Down)
+        ])
+    );
+    // Flipping both ways is half a turn.
+    floating.flip_horizontally();
+    let mut turned = original.clone();
+    for _ in 0..3 {
+        turned.rotate_clockwise();
+    }
+    assert_eq!(shape(&floating), shape(&turned));
+}
+
+#[test]
+fn pasting_remaps_to_the_nearest_colours() {
+    let white = Color::new(1.0, 0.0, 0.0);
+    let red = Color::new(0.6, 0.2, 0.1);
+    let blue = Color::new(0.5, -0.05, -0.2);
+    let mut source = Project::new("Source".to_owned(), SIZE, GridType::Square);
+    let mut source_indices = vec![0; SIZE.area() as usize];
+    source_indices[..3].copy_from_slice(&[1, 2, 3]);
+    source.push_layer(base_color(vec![white, red, blue], source_indices));
+    let selected = Selection::from_positions(SIZE, GridType::Square, 0..4);
+    source.select(&selected, SelectionMode::Replace).unwrap();
+    let floating = source.copy_selection(0).unwrap();
+
+    let mut target = Project::new("Target".to_owned(), SIZE, GridType::Square);
+    let nearly_blue = Color::new(0.52, -0.04, -0.18);
+    let nearly_red = Color::new(0.58, 0.19, 0.12);
+    target.push_layer(base_color(
+        vec![Color::new(0.0, 0.0, 0.0), nearly_blue, nearly_red, white],
+        vec![0; SIZE.area() as usize],
+    ));
+    target.paste(0, &floating).unwrap();
+    // Index 3 is out of range for the source palette, so that tile is left alone.
+    assert_eq!(indices(&target, 0)[..5], [2, 1, 0, 3, 0]);
+}
+
+#[test]
+fn pasting_checks_the_layer_and_grid() {
+    let project = project_with(GridType::Square, &[OffsetCoord::new(0, 0)]);
+    let floating = project.copy_selection(0).unwrap();
+
+    let mut alpha = Project::new("Alpha".to_owned(), SIZE, GridType::Square);
+    alpha.push_layer(LayerV2::new(
+        None,
+        SIZE,
+        LayerV1Canvas::Alpha(vec![1.0; SIZE.area() as usize]),
+    ));
+    assert!(matches!(
+        alpha.paste(0, &floating),
+        Err(EditError::KindMismatch { layer: 0, .. })
+    ));
+    let mut hex = project_with(GridType::Hexagonal, &[OffsetCoord::new(0, 0)]);
+    assert!(matches!(
+        hex.paste(0, &floating),
+        Err(EditError::GridTypeMismatch(
+            GridType::Square,
+            GridType::Hexagonal
+        ))
+    ));
+    assert!(matches!(
+        alpha.paste(3, &floating),
+        Err(EditError::NoSuchLayer(3))
+    ));
+}
@@ -0,0 +1,100 @@
+//! Checks the colour picker's sliders, hex input and output, gamut mapping and gradient slices.
+use hexil::app::color_picker::{gamut_map, in_srgb_gamut, InvalidHexColor, SliceAxes, MAX_CHROMA};
+use hexil::app::ColorPicker;
+use palette::{FromColor, Oklch};
+
+#[test]
+fn hex_round_trips() {
+    let mut picker = ColorPicker::default();
+    for hex in ["#000000", "#ffffff", "#e06b3c", "#123456", "#00ff00"] {
+        picker.set_hex(hex).unwrap();
+        assert!(picker.in_gamut(), "{hex}");
+        assert_eq!(picker.hex(), hex);
+    }
+    picker.set_hex("F0A").unwrap();
+    assert_eq!(picker.hex(), "#ff00aa");
+    for bad in ["", "#12345", "#gggggg", "#+1+2+3", "#ééé"] {
+        assert_eq!(picker.set_hex(bad), Err(InvalidHexColor(bad.to_owned())));
+    }
+    assert_eq!(picker.hex(), "#ff00aa");
+}
+
+#[test]
+fn sliders_clamp_and_wrap() {
+    let mut picker = ColorPicker::default();
+    picker.set_lightness(1.5);
+    picker.set_chroma(-1.0);
+    picker.set_hue(-90.0);
+    assert_eq!(
+        (picker.lightness(), picker.chroma(), picker.hue()),
+        (1.0, 0.0, 270.0)
+    );
+    picker.set_chroma(f32::NAN);
+    picker.set_hue(720.0);
+    assert_eq!((picker.chroma(), picker.hue()), (0.0, 0.0));
+    picker.set_chroma(1.0);
+    assert_eq!(picker.chroma(), MAX_CHROMA);
+}
+
+#[test]
+fn greys_keep_the_hue() {
+    let mut picker = ColorPicker::default();
+    picker.set_hex("#3366cc").unwrap();
+    let hue = picker.hue();
+    picker.set_hex("#808080").unwrap();
+    assert_eq!(picker.hue(), hue);
+    assert!(picker.chroma() < 1e-3);
+}
+
+#[test]
+fn gamut_mapping_only_reduces_chroma() {
+    let mut picker = ColorPicker::default();
+    picker.set_lightness(0.7);
+    picker.set_hue(145.0);
+    picker.set_chroma(MAX_CHROMA);
+    assert!(!picker.in_gamut());
+    let mapped = Oklch::from_color(picker.gamut_mapped());
+    assert!(in_srgb_gamut(picker.gamut_mapped()));
+    assert!((mapped.l - 0.7).abs() < 1e-4);
+    assert!((mapped.hue.into_positive_degrees() - 145.0).abs() < 0.1);
+    assert!(mapped.chroma < MAX_CHROMA);
+    // Only just in gamut: a little more chroma would leave it.
+    let more = Oklch::new(mapped.l, mapped.chroma + 0.002, mapped.hue);
+    assert!(!in_srgb_gamut(hexil::app::Color::from_color(more)));
+
+    let inside = Oklch::new(0.5, 0.05, 30.0);
+    let unchanged = Oklch::from_color(gamut_map(inside));
+    assert!((unchanged.chroma - 0.05).abs() < 1e-5);
+}
+
+#[test]
+fn slice_positions_round_trip() {
+    let mut picker = ColorPicker::default();
+    picker.set_hex("#e06b3c").unwrap();
+    for axes in [
+        SliceAxes::ChromaLightness,
+        SliceAxes::HueLightness,
+        SliceAxes::HueChroma,
+    ] {
+        let mut moved = picker;
+        moved.set_slice_position(axes, picker.slice_position(axes));
+        assert!((moved.lightness() - picker.lightness()).abs() < 1e-5);
+        assert!((moved.chroma() - picker.chroma()).abs() < 1e-5);
+        assert!((moved.hue() - picker.hue()).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn chroma_lightness_slice_is_light_at_the_top_and_grey_at_the_left() {
+    let mut picker = ColorPicker::default();
+    picker.set_hue(30.0);
+    let slice = picker.gradient_slice(SliceAxes::ChromaLightness, 64, 8);
+    assert_eq!(slice.pixels.len(), 64 * 8);
+    let pixel = |x: usize, y: usize| slice.pixels[y * 64 + x];
+    let [r, g, b, a] = pixel(0, 0);
+    assert!(r > 220 && r.abs_diff(g) <= 3 && g.abs_diff(b) <= 3 && a == 255);
+    let [r, _, _, a] = pixel(0, 7);
+    assert!(r < 25 && a == 255);
+    // The most saturated column is out of gamut near the top, where nothing that bright can be that colourful.
+    assert_eq!(pixel(63, 0)[3], 0);
+}
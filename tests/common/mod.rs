@@ -0,0 +1,60 @@
+//! Fixtures shared by the integration tests. Each test file is its own crate, and uses only some of these.
+#![allow(dead_code)]
+
+use hexil::app::{CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Project};
+
+/// An unnamed base colour layer with `palette`, and `indices` as its canvas.
+pub fn base_color_layer(size: CanvasSize, palette: Vec<Color>, indices: Vec<u32>) -> LayerV2 {
+    LayerV2::new(
+        None,
+        size,
+        LayerV1Canvas::BaseColor {
+            palette: palette.into(),
+            canvas: indices.into(),
+        },
+    )
+}
+
+/// The canvas of a base colour layer.
+pub fn base_color_indices(layer: &LayerV2) -> Vec<u32> {
+    match layer.canvas() {
+        LayerV1Canvas::BaseColor { canvas, .. } => canvas.read().clone(),
+        _ => panic!("not a base colour layer"),
+    }
+}
+
+/// A project made of `layers`, from the bottom of the stack to the top.
+pub fn project_with_layers(
+    name: &str,
+    size: CanvasSize,
+    gridtype: GridType,
+    layers: impl IntoIterator<Item = LayerV2>,
+) -> Project {
+    let mut project = Project::new(name.to_owned(), size, gridtype);
+    for layer in layers {
+        project.push_layer(layer);
+    }
+    project
+}
+
+/// A small deterministic generator, so failures can be reproduced from the seed.
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        self.next() as usize % n
+    }
+
+    /// A number from 0 to 1 inclusive, in steps of a thousandth.
+    pub fn unit(&mut self) -> f32 {
+        self.below(1001) as f32 / 1000.0
+    }
+}
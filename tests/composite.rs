@@ -0,0 +1,175 @@
+//! Checks how the layers of a project combine, and that visibility and opacity survive saving, migration and undo.
+use hexil::app::{
+    open_project, CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Project, TileColor,
+    SHADING_STEP,
+};
+
+mod common;
+use common::{base_color_layer, project_with_layers};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 2,
+    height: 1,
+};
+const RED: Color = Color::new(0.627955, 0.224863, 0.125846);
+const BLUE: Color = Color::new(0.452014, -0.032457, -0.311528);
+
+fn base_color(color: Color, indices: [u32; 2]) -> LayerV2 {
+    base_color_layer(SIZE, vec![color], indices.to_vec())
+}
+
+fn project(layers: impl IntoIterator<Item = LayerV2>) -> Project {
+    project_with_layers("Composite", SIZE, GridType::Square, layers)
+}
+
+fn assert_close(actual: TileColor, expected: TileColor) {
+    let close = (actual.alpha - expected.alpha).abs() < 1e-5
+        && (actual.color.l - expected.color.l).abs() < 1e-5
+        && (actual.color.a - expected.color.a).abs() < 1e-5
+        && (actual.color.b - expected.color.b).abs() < 1e-5;
+    assert!(close, "{actual:?} != {expected:?}");
+}
+
+#[test]
+fn opaque_layers_paint_over_lower_ones() {
+    let tiles = project([base_color(RED, [0, 0]), base_color(BLUE, [0, u32::MAX])]).composite();
+    assert_eq!(
+        tiles,
+        [
+            TileColor {
+                color: BLUE,
+                alpha: 1.0
+            },
+            TileColor {
+                color: RED,
+                alpha: 1.0
+            },
+        ]
+    );
+}
+
+#[test]
+fn hidden_layers_are_skipped() {
+    let blue = base_color(BLUE, [0, 0]).with_visible(false);
+    let tiles = project([base_color(RED, [0, u32::MAX]), blue]).composite();
+    assert_eq!(tiles[0].color, RED);
+    assert!(!tiles[1].is_covered());
+}
+
+#[test]
+fn translucent_layers_mix_with_lower_ones() {
+    let blue = base_color(BLUE, [0, 0]).with_opacity(0.25);
+    let tiles = project([base_color(RED, [0, u32::MAX]), blue]).composite();
+    assert_close(
+        tiles[0],
+        TileColor {
+            color: RED * 0.75 + BLUE * 0.25,
+            alpha: 1.0,
+        },
+    );
+    assert_close(
+        tiles[1],
+        TileColor {
+            color: BLUE,
+            alpha: 0.25,
+        },
+    );
+}
+
+#[test]
+fn shading_and_alpha_scale_with_opacity() {
+    let shading = LayerV2::new(None, SIZE, LayerV1Canvas::Shading(vec![10, -4])).with_opacity(0.5);
+    let alpha =
+        LayerV2::new(None, SIZE, LayerV1Canvas::Alpha(vec![0.0, f32::NAN])).with_opacity(0.5);
+    let tiles = project([base_color(RED, [0, 0]), shading, alpha]).composite();
+    for (tile, steps) in tiles.into_iter().zip([5.0, -2.0]) {
+        assert_close(
+            tile,
+            TileColor {
+                color: Color::new(RED.l + steps * SHADING_STEP, RED.a, RED.b),
+                alpha: 0.5,
+            },
+        );
+    }
+}
+
+#[test]
+fn opacity_is_clamped() {
+    assert_eq!(base_color(RED, [0, 0]).with_opacity(1.5).opacity(), 1.0);
+    assert_eq!(
+        base_color(RED, [0, 0]).with_opacity(f32::NAN).opacity(),
+        0.0
+    );
+    let mut project = project([base_color(RED, [0, 0])]);
+    project.set_layer_opacity(0, -0.5).unwrap();
+    assert_eq!(project.layers()[0].opacity(), 0.0);
+}
+
+#[test]
+fn v1_projects_open_with_every_layer_visible_and_opaque() {
+    let project = open_project(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/projects/v1.hexil"
+    ))
+    .unwrap();
+    assert!(project
+        .layers()
+        .iter()
+        .all(|layer| layer.visible() && layer.opacity() == 1.0));
+}
+
+#[test]
+fn v2_fixture_keeps_its_appearance() {
+    let project = open_project(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/projects/v2.hexil"
+    ))
+    .unwrap();
+    let appearance: Vec<_> = project
+        .layers()
+        .iter()
+        .map(|layer| (layer.visible(), layer.opacity()))
+        .collect();
+    assert_eq!(
+        appearance,
+        [
+            (true, 1.0),
+            (true, 0.5),
+            (true, 0.75),
+            (true, 0.5),
+            (false, 1.0)
+        ]
+    );
+}
+
+#[test]
+fn appearance_survives_saving() {
+    let blue = base_color(BLUE, [0, 0])
+        .with_visible(false)
+        .with_opacity(0.3);
+    let project = project([base_color(RED, [0, 0]), blue]);
+    let mut file = Vec::new();
+    project.write_to(&mut file).unwrap();
+    let loaded = Project::read_from(file.as_slice()).unwrap();
+    assert_eq!(loaded.layers().len(), 2);
+    assert!(!loaded.layers()[1].visible());
+    assert_eq!(loaded.layers()[1].opacity(), 0.3);
+}
+
+#[test]
+fn appearance_changes_can_be_undone() {
+    let mut project = project([base_color(RED, [0, 0])]);
+    project.set_layer_opacity(0, 0.4).unwrap();
+    project.set_layer_visible(0, false).unwrap();
+    // Setting what's already there isn't an edit.
+    project.set_layer_visible(0, false).unwrap();
+
+    assert!(project.undo());
+    assert!(project.layers()[0].visible());
+    assert_eq!(project.layers()[0].opacity(), 0.4);
+    assert!(project.undo());
+    assert_eq!(project.layers()[0].opacity(), 1.0);
+    assert!(!project.undo());
+    assert!(project.redo());
+    assert_eq!(project.layers()[0].opacity(), 0.4);
+}
@@ -0,0 +1,186 @@
+//! Checks which tiles bucket fills reach on each grid, in contiguous and global mode, with tolerances, and on canvases
+//! far too large for a recursive fill.
+use hexil::app::{CanvasSize, Color, GridType, LayerV1Canvas, LayerV2};
+use hexil::grid::{OffsetCoord, SquareConnectivity};
+use hexil::tools::{fill, fill_alpha, fill_shading, FillMode, FillOptions};
+
+mod common;
+use common::{base_color_indices as indices, base_color_layer};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 3,
+    height: 3,
+};
+
+/// A 3x3 canvas whose background tiles only touch at their corners.
+#[rustfmt::skip]
+const CHECKERED: [u32; 9] = [
+    0, 1, 0,
+    1, 0, 1,
+    0, 1, 0,
+];
+
+fn base_color(size: CanvasSize, indices: Vec<u32>) -> LayerV2 {
+    let palette = vec![
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(1.0, 0.0, 0.0),
+        Color::new(0.5, 0.1, 0.1),
+    ];
+    base_color_layer(size, palette, indices)
+}
+
+/// The positions a fill of the checkered canvas from its centre changes.
+fn filled_from_centre(gridtype: GridType, options: FillOptions) -> Vec<usize> {
+    let layer = base_color(SIZE, CHECKERED.to_vec());
+    let changes = fill(&layer, gridtype, OffsetCoord::new(1, 1), 2, options, None).unwrap();
+    let mut filled: Vec<usize> = changes.indices().collect();
+    filled.sort();
+    for (position, index) in indices(&layer).into_iter().enumerate() {
+        let expected = if filled.contains(&position) {
+            2
+        } else {
+            CHECKERED[position]
+        };
+        assert_eq!(index, expected);
+    }
+    filled
+}
+
+#[test]
+fn contiguous_fills_follow_the_grid() {
+    let four = FillOptions {
+        connectivity: SquareConnectivity::Four,
+        ..Default::default()
+    };
+    let eight = FillOptions {
+        connectivity: SquareConnectivity::Eight,
+        ..Default::default()
+    };
+    // Four-connected square tiles don't reach across corners at all.
+    assert_eq!(filled_from_centre(GridType::Square, four), [4]);
+    // Eight-connected ones reach every corner.
+    assert_eq!(filled_from_centre(GridType::Square, eight), [0, 2, 4, 6, 8]);
+    // The centre column is odd, so shifted down: its hexagon touches the bottom corners, but not the top ones. The
+    // connectivity is ignored.
+    for options in [four, eight] {
+        assert_eq!(filled_from_centre(GridType::Hexagonal, options), [4, 6, 8]);
+    }
+}
+
+#[test]
+fn global_fills_ignore_connections() {
+    for gridtype in [GridType::Square, GridType::Hexagonal] {
+        let global = FillOptions {
+            mode: FillMode::Global,
+            ..Default::default()
+        };
+        assert_eq!(filled_from_centre(gridtype, global), [0, 2, 4, 6, 8]);
+
+        let layer = base_color(SIZE, CHECKERED.to_vec());
+        let changes = fill(&layer, gridtype, OffsetCoord::new(1, 0), 2, global, None).unwrap();
+        assert_eq!(changes.indices().collect::<Vec<_>>(), [1, 3, 5, 7]);
+    }
+}
+
+#[test]
+fn filling_with_the_same_value_changes_nothing() {
+    for gridtype in [GridType::Square, GridType::Hexagonal] {
+        for mode in [FillMode::Contiguous, FillMode::Global] {
+            let options = FillOptions {
+                mode,
+                ..Default::default()
+            };
+            let layer = base_color(SIZE, CHECKERED.to_vec());
+            let changes = fill(&layer, gridtype, OffsetCoord::new(0, 0), 0, options, None).unwrap();
+            assert!(changes.is_empty());
+            assert_eq!(indices(&layer), CHECKERED);
+
+            let mut alpha = LayerV2::new(None, SIZE, LayerV1Canvas::Alpha(vec![0.25; 9]));
+            let start = OffsetCoord::new(2, 1);
+            assert!(fill_alpha(&mut alpha, gridtype, start, 0.25, options, None)
+                .unwrap()
+                .is_empty());
+            let mut shading = LayerV2::new(None, SIZE, LayerV1Canvas::Shading(vec![-3; 9]));
+            assert!(
+                fill_shading(&mut shading, gridtype, start, -3, options, None)
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+    }
+}
+
+#[test]
+fn tolerance_is_measured_from_the_starting_tile() {
+    let size = CanvasSize {
+        width: 5,
+        height: 1,
+    };
+    let options = FillOptions {
+        tolerance: 0.15,
+        ..Default::default()
+    };
+    // Each step is within the tolerance of the last, but the fill stops once it's too far from where it started.
+    let mut alpha = LayerV2::new(
+        None,
+        size,
+        LayerV1Canvas::Alpha(vec![0.0, 0.1, 0.2, 0.1, 0.0]),
+    );
+    let changes = fill_alpha(
+        &mut alpha,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        1.0,
+        options,
+        None,
+    )
+    .unwrap();
+    assert_eq!(changes.indices().collect::<Vec<_>>(), [0, 1]);
+
+    let mut shading = LayerV2::new(None, size, LayerV1Canvas::Shading(vec![4, 3, 5, 2, 4]));
+    let options = FillOptions {
+        mode: FillMode::Global,
+        tolerance: 1.0,
+        ..Default::default()
+    };
+    let changes = fill_shading(
+        &mut shading,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        0,
+        options,
+        None,
+    )
+    .unwrap();
+    let mut filled: Vec<_> = changes.indices().collect();
+    filled.sort();
+    assert_eq!(filled, [0, 1, 2, 4]);
+    assert!(matches!(
+        shading.canvas(),
+        LayerV1Canvas::Shading(shading) if shading == &[0, 0, 0, 2, 0]
+    ));
+}
+
+#[test]
+fn huge_contiguous_fills_dont_overflow_the_stack() {
+    // A recursive fill would go about a million calls deep here, far past the stack of a test thread.
+    let size = CanvasSize {
+        width: 1024,
+        height: 1024,
+    };
+    let area = size.area() as usize;
+    for gridtype in [GridType::Square, GridType::Hexagonal] {
+        let layer = base_color(size, vec![0; area]);
+        let changes = fill(
+            &layer,
+            gridtype,
+            OffsetCoord::new(511, 700),
+            1,
+            FillOptions::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(changes.len(), area);
+        assert!(indices(&layer).iter().all(|index| *index == 1));
+    }
+}
@@ -0,0 +1,98 @@
+//! Checks the CPU exporter and the GPU renderer against the golden images in `fixtures/golden`. The GPU half needs a
+//! Vulkan driver, so it's ignored by default; CI runs it under lavapipe with `cargo test -- --include-ignored`.
+use hexil::app::{open_project, ProjectV2};
+use hexil::export::{rasterize, Raster, RasterOptions};
+use hexil::render::Renderer;
+
+const BACKGROUND: [u8; 4] = [25, 50, 75, 255];
+
+/// Every fixture, drawn with and without a background, and the golden image each should match.
+fn cases() -> Vec<(ProjectV2, RasterOptions, String)> {
+    let fixtures = [
+        "v1", // Translucent, shaded, masked and hidden layers.
+        "v2",
+    ];
+    fixtures
+        .into_iter()
+        .flat_map(|fixture| {
+            [(None, ""), (Some(BACKGROUND), "-background")].map(|(background, suffix)| {
+                let project = open_project(format!(
+                    "{}/fixtures/projects/{fixture}.hexil",
+                    env!("CARGO_MANIFEST_DIR")
+                ))
+                .unwrap();
+                let options = RasterOptions {
+                    pixels_per_tile: 32,
+                    background,
+                };
+                let golden = format!(
+                    "{}/fixtures/golden/{fixture}{suffix}.png",
+                    env!("CARGO_MANIFEST_DIR")
+                );
+                (project, options, golden)
+            })
+        })
+        .collect()
+}
+
+fn read_golden(path: &str) -> Raster {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut bytes = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut bytes).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{path}"
+    );
+    Raster {
+        width: info.width,
+        height: info.height,
+        pixels: bytes[..info.buffer_size()]
+            .chunks_exact(4)
+            .map(|pixel| pixel.try_into().unwrap())
+            .collect(),
+    }
+}
+
+/// Checks that every channel of every pixel is within `tolerance` of the golden image.
+fn assert_matches(raster: &Raster, golden: &str, tolerance: u8) {
+    let expected = read_golden(golden);
+    assert_eq!(
+        (raster.width, raster.height),
+        (expected.width, expected.height),
+        "{golden}"
+    );
+    let mismatched = expected
+        .pixels
+        .iter()
+        .zip(&raster.pixels)
+        .position(|(a, b)| a.iter().zip(b).any(|(a, b)| a.abs_diff(*b) > tolerance));
+    if let Some(index) = mismatched {
+        let (x, y) = (index as u32 % raster.width, index as u32 / raster.width);
+        panic!(
+            "{golden}: pixel ({x}, {y}) is {:?}, but should be {:?}",
+            raster.pixels[index], expected.pixels[index]
+        );
+    }
+}
+
+#[test]
+fn cpu_export_matches_golden_images() {
+    for (project, options, golden) in cases() {
+        assert_matches(&rasterize(&project, options), &golden, 0);
+    }
+}
+
+#[test]
+#[ignore = "needs a Vulkan device; CI runs it under lavapipe"]
+fn gpu_matches_golden_images() {
+    let renderer = Renderer::new_headless()
+        .unwrap_or_else(|e| panic!("The GPU tests need a Vulkan device: {e}"));
+    for (project, options, golden) in cases() {
+        let gpu = renderer.render_offscreen(&project, options).unwrap();
+        // The GPU encodes to sRGB itself, which may round differently from `oklab_to_srgb8`, but nothing else may
+        // differ.
+        assert_matches(&gpu, &golden, 1);
+    }
+}
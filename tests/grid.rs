@@ -0,0 +1,246 @@
+//! Checks the hexagonal coordinate systems against each other and against the odd-q layout, over negative coordinates
+//! and odd columns as well as the canvas itself.
+use std::collections::HashSet;
+
+use hexil::app::{CanvasSize, GridType};
+use hexil::grid::{
+    AxialCoord, CubeCoord, FractionalCube, HexDirection, OffsetCoord, SquareConnectivity,
+};
+
+/// Every tile in a block around the origin, covering negative and odd rows and columns.
+fn tiles() -> impl Iterator<Item = OffsetCoord> {
+    (-9..=9).flat_map(|col| (-9..=9).map(move |row| OffsetCoord::new(col, row)))
+}
+
+/// The centre of a tile in tile space, where odd columns are shifted half a tile down.
+fn centre(tile: OffsetCoord) -> (f64, f64) {
+    let shift = if tile.is_shifted() { 0.5 } else { 0.0 };
+    (tile.col as f64, tile.row as f64 + shift)
+}
+
+fn sorted(tiles: impl IntoIterator<Item = OffsetCoord>) -> Vec<OffsetCoord> {
+    let mut tiles: Vec<_> = tiles.into_iter().collect();
+    tiles.sort();
+    tiles
+}
+
+fn offsets(tiles: &[(i32, i32)]) -> Vec<OffsetCoord> {
+    sorted(tiles.iter().map(|(col, row)| OffsetCoord::new(*col, *row)))
+}
+
+#[test]
+fn conversions_round_trip() {
+    for tile in tiles() {
+        let axial = AxialCoord::from(tile);
+        let cube = CubeCoord::from(tile);
+        assert_eq!(cube.q() + cube.r() + cube.s(), 0);
+        assert_eq!((cube.q(), cube.r()), (axial.q, axial.r));
+        assert_eq!(OffsetCoord::from(axial), tile);
+        assert_eq!(OffsetCoord::from(cube), tile);
+        assert_eq!(AxialCoord::from(cube), axial);
+        assert_eq!(CubeCoord::from(axial), cube);
+        // The centre of every tile is inside it.
+        let (x, y) = centre(tile);
+        assert_eq!(FractionalCube::from_tile_space(x, y).round(), cube);
+    }
+    // Negative odd columns are shifted too.
+    assert!(OffsetCoord::new(-1, 0).is_shifted());
+    assert!(!OffsetCoord::new(-2, 0).is_shifted());
+    assert_eq!(
+        AxialCoord::from(OffsetCoord::new(-3, 2)),
+        AxialCoord::new(-3, 4)
+    );
+}
+
+#[test]
+fn indices_round_trip_on_the_canvas_only() {
+    let size = CanvasSize {
+        width: 7,
+        height: 5,
+    };
+    for index in 0..35 {
+        let tile = OffsetCoord::from_index(index, size).unwrap();
+        assert!(tile.is_within(size));
+        assert_eq!(tile.to_index(size), Some(index));
+    }
+    assert_eq!(OffsetCoord::from_index(35, size), None);
+    for outside in [(-1, 0), (0, -1), (7, 0), (0, 5), (-1, -1)] {
+        let tile = OffsetCoord::new(outside.0, outside.1);
+        assert!(!tile.is_within(size));
+        assert_eq!(tile.to_index(size), None);
+    }
+    let empty = CanvasSize {
+        width: 0,
+        height: 5,
+    };
+    assert_eq!(OffsetCoord::from_index(0, empty), None);
+}
+
+#[test]
+fn neighbours_are_symmetric_and_touch() {
+    for tile in tiles() {
+        let cube = CubeCoord::from(tile);
+        let (x, y) = centre(tile);
+        for (direction, neighbor) in HexDirection::ALL.into_iter().zip(cube.neighbors()) {
+            assert_eq!(cube.neighbor(direction), neighbor);
+            assert_eq!(neighbor.neighbor(direction.opposite()), cube);
+            assert!(neighbor.neighbors().contains(&cube));
+            assert_eq!(cube.distance(neighbor), 1);
+            // Neighbours in the same column are a tile apart, and neighbours in the next column half a tile up or
+            // down.
+            let (nx, ny) = centre(OffsetCoord::from(neighbor));
+            let (dx, dy) = (nx - x, ny - y);
+            assert!(
+                (dx == 0.0 && dy.abs() == 1.0) || (dx.abs() == 1.0 && dy.abs() == 0.5),
+                "{tile:?} -> {:?}",
+                OffsetCoord::from(neighbor)
+            );
+        }
+    }
+}
+
+#[test]
+fn neighbours_follow_the_odd_q_layout() {
+    let neighbors = |col, row| {
+        sorted(
+            CubeCoord::from(OffsetCoord::new(col, row))
+                .neighbors()
+                .map(OffsetCoord::from),
+        )
+    };
+    // Even columns reach up into the columns beside them, and odd columns reach down.
+    assert_eq!(
+        neighbors(2, 2),
+        offsets(&[(1, 1), (1, 2), (2, 1), (2, 3), (3, 1), (3, 2)])
+    );
+    assert_eq!(
+        neighbors(1, 0),
+        offsets(&[(0, 0), (0, 1), (1, -1), (1, 1), (2, 0), (2, 1)])
+    );
+    assert_eq!(
+        neighbors(-1, 0),
+        offsets(&[(-2, 0), (-2, 1), (-1, -1), (-1, 1), (0, 0), (0, 1)])
+    );
+    assert_eq!(
+        CubeCoord::from(OffsetCoord::new(3, 3)).neighbor(HexDirection::Down),
+        CubeCoord::from(OffsetCoord::new(3, 4))
+    );
+}
+
+#[test]
+fn ranges_and_rings_have_the_right_tiles() {
+    for centre in [CubeCoord::new(0, 0), CubeCoord::new(-3, 7)] {
+        for radius in 0..7 {
+            let range: Vec<_> = centre.range(radius).collect();
+            assert_eq!(range.len() as u32, 3 * radius * (radius + 1) + 1);
+            assert_eq!(range.iter().collect::<HashSet<_>>().len(), range.len());
+            assert!(range.iter().all(|tile| tile.distance(centre) <= radius));
+
+            let ring: Vec<_> = centre.ring(radius).collect();
+            let expected = if radius == 0 { 1 } else { 6 * radius as usize };
+            assert_eq!(ring.len(), expected);
+            assert_eq!(ring.iter().collect::<HashSet<_>>().len(), ring.len());
+            assert!(ring.iter().all(|tile| tile.distance(centre) == radius));
+            // The ring is walked one step at a time, and closes up.
+            for pair in ring.windows(2) {
+                assert_eq!(pair[0].distance(pair[1]), 1);
+            }
+            if radius > 0 {
+                assert_eq!(ring[0].distance(ring[ring.len() - 1]), 1);
+            }
+
+            // A range is exactly the rings inside it.
+            let rings: HashSet<_> = (0..=radius).flat_map(|r| centre.ring(r)).collect();
+            assert_eq!(rings, range.iter().copied().collect());
+        }
+    }
+
+    let size = CanvasSize {
+        width: 4,
+        height: 3,
+    };
+    let corner: Vec<_> = CubeCoord::from(OffsetCoord::new(0, 0))
+        .range_within(1, size)
+        .map(OffsetCoord::from)
+        .collect();
+    assert_eq!(sorted(corner), offsets(&[(0, 0), (0, 1), (1, 0)]));
+}
+
+#[test]
+fn lines_are_contiguous_and_include_both_ends() {
+    let ends = [
+        (0, 0),
+        (5, 0),
+        (0, 5),
+        (-4, 3),
+        (3, -7),
+        (-6, -6),
+        (7, 2),
+        (1, 1),
+    ];
+    for from in ends {
+        for to in ends {
+            let (from, to) = (CubeCoord::new(from.0, from.1), CubeCoord::new(to.0, to.1));
+            let line: Vec<_> = from.line_to(to).collect();
+            assert_eq!(line.len() as u32, from.distance(to) + 1);
+            assert_eq!((line[0], line[line.len() - 1]), (from, to));
+            for pair in line.windows(2) {
+                assert_eq!(pair[0].distance(pair[1]), 1, "{from:?} to {to:?}");
+            }
+        }
+    }
+    // Lines along a direction are just steps in that direction.
+    for direction in HexDirection::ALL {
+        let start = CubeCoord::from(OffsetCoord::new(-1, 2));
+        let end = start.add(direction.offset().scale(5));
+        let line: Vec<_> = start.line_to(end).collect();
+        let steps: Vec<_> = (0..=5)
+            .map(|i| start.add(direction.offset().scale(i)))
+            .collect();
+        assert_eq!(line, steps);
+    }
+}
+
+#[test]
+fn adjacency_depends_on_the_grid() {
+    let adjacent =
+        |tile: OffsetCoord, gridtype, connectivity| sorted(tile.adjacent(gridtype, connectivity));
+    let tile = OffsetCoord::new(3, -2);
+    assert_eq!(
+        adjacent(tile, GridType::Square, SquareConnectivity::Four),
+        offsets(&[(2, -2), (3, -3), (3, -1), (4, -2)])
+    );
+    assert_eq!(
+        adjacent(tile, GridType::Square, SquareConnectivity::Eight),
+        offsets(&[
+            (2, -3),
+            (2, -2),
+            (2, -1),
+            (3, -3),
+            (3, -1),
+            (4, -3),
+            (4, -2),
+            (4, -1)
+        ])
+    );
+    // Connectivity only matters on square grids.
+    for connectivity in [SquareConnectivity::Four, SquareConnectivity::Eight] {
+        assert_eq!(
+            adjacent(tile, GridType::Hexagonal, connectivity),
+            sorted(CubeCoord::from(tile).neighbors().map(OffsetCoord::from))
+        );
+    }
+
+    for (gridtype, connectivity) in [
+        (GridType::Square, SquareConnectivity::Four),
+        (GridType::Square, SquareConnectivity::Eight),
+        (GridType::Hexagonal, SquareConnectivity::Four),
+    ] {
+        for tile in tiles() {
+            for other in tile.adjacent(gridtype, connectivity) {
+                assert_ne!(other, tile);
+                assert!(other.adjacent(gridtype, connectivity).contains(&tile));
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Checks that undoing every edit of a random sequence restores the project exactly, step by step, and that redoing
+//! them all replays it exactly. Tiles, palettes, the layer stack, the canvas size and the selection are all edited.
+use hexil::app::{
+    CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Project, Selection, SelectionMode,
+};
+use hexil::grid::{OffsetCoord, SquareConnectivity};
+use hexil::tools::{fill, fill_alpha, fill_shading, pencil, FillMode, FillOptions};
+
+mod common;
+use common::Lcg;
+
+/// Draws the parts of random edits.
+impl Lcg {
+    fn size(&mut self) -> CanvasSize {
+        CanvasSize {
+            width: 1 + self.below(7) as u64,
+            height: 1 + self.below(6) as u64,
+        }
+    }
+
+    fn tile(&mut self, size: CanvasSize) -> OffsetCoord {
+        OffsetCoord::new(
+            self.below(size.width as usize) as i32,
+            self.below(size.height as usize) as i32,
+        )
+    }
+
+    fn palette(&mut self) -> Vec<Color> {
+        (0..1 + self.below(4))
+            .map(|_| Color::new(self.unit(), self.unit() - 0.5, self.unit() - 0.5))
+            .collect()
+    }
+
+    fn fill_options(&mut self) -> FillOptions {
+        FillOptions {
+            mode: [FillMode::Contiguous, FillMode::Global][self.below(2)],
+            connectivity: [SquareConnectivity::Four, SquareConnectivity::Eight][self.below(2)],
+            tolerance: self.unit() / 4.0,
+        }
+    }
+
+    fn layer(&mut self, size: CanvasSize) -> LayerV2 {
+        let area = size.area() as usize;
+        let canvas = match self.below(3) {
+            0 => {
+                let palette = self.palette();
+                let canvas = (0..area)
+                    .map(|_| self.below(palette.len()) as u32)
+                    .collect::<Vec<_>>();
+                LayerV1Canvas::BaseColor {
+                    palette: palette.into(),
+                    canvas: canvas.into(),
+                }
+            }
+            1 => LayerV1Canvas::Alpha((0..area).map(|_| self.unit()).collect()),
+            _ => LayerV1Canvas::Shading((0..area).map(|_| self.below(9) as i32 - 4).collect()),
+        };
+        let name = match self.below(3) {
+            0 => None,
+            n => Some(format!("Layer {n}")),
+        };
+        LayerV2::new(name, size, canvas)
+    }
+}
+
+/// Everything about a project that an edit can change.
+fn snapshot(project: &Project) -> (Vec<u8>, Option<Selection>) {
+    let mut file = Vec::new();
+    project.write_to(&mut file).unwrap();
+    (file, project.selection().cloned())
+}
+
+/// Makes one random edit through the project's history. Edits that turn out to be impossible, such as painting with a
+/// palette index the layer doesn't have, are skipped.
+fn random_edit(project: &mut Project, rng: &mut Lcg) {
+    let size = project.size();
+    let gridtype = project.gridtype();
+    let selection = project.selection().cloned();
+    let layers = project.layers().len();
+    let layer = if layers == 0 { 0 } else { rng.below(layers) };
+    match rng.below(12) {
+        0 | 1 if layer < layers => {
+            let target = &project.layers()[layer];
+            let LayerV1Canvas::BaseColor { palette, .. } = target.canvas() else {
+                return;
+            };
+            let index = rng.below(palette.read().len() + 1) as u32;
+            let tile = rng.tile(size);
+            let changes = if rng.below(2) == 0 {
+                pencil(target, tile, index, selection.as_ref())
+            } else {
+                fill(
+                    target,
+                    gridtype,
+                    tile,
+                    index,
+                    rng.fill_options(),
+                    selection.as_ref(),
+                )
+            };
+            if let Ok(changes) = changes {
+                project.record_tile_changes(layer, changes).unwrap();
+            }
+        }
+        2 if layer < layers => {
+            let (tile, value, options) = (rng.tile(size), rng.unit(), rng.fill_options());
+            let target = project.layer_mut(layer).unwrap();
+            if let Ok(changes) =
+                fill_alpha(target, gridtype, tile, value, options, selection.as_ref())
+            {
+                project.record_alpha_changes(layer, changes).unwrap();
+            }
+        }
+        3 if layer < layers => {
+            let value = rng.below(9) as i32 - 4;
+            let (tile, options) = (rng.tile(size), rng.fill_options());
+            let target = project.layer_mut(layer).unwrap();
+            if let Ok(changes) =
+                fill_shading(target, gridtype, tile, value, options, selection.as_ref())
+            {
+                project.record_shading_changes(layer, changes).unwrap();
+            }
+        }
+        4 if layer < layers => {
+            let palette = rng.palette();
+            let _ = project.set_palette(layer, palette);
+        }
+        5 => {
+            let new = rng.layer(size);
+            project.insert_layer(rng.below(layers + 2), new).unwrap();
+        }
+        6 if layer < layers => project.remove_layer(layer).unwrap(),
+        7 if layer < layers => project.move_layer(layer, rng.below(layers)).unwrap(),
+        8 if layer < layers => {
+            if rng.below(2) == 0 {
+                let visible = !project.layers()[layer].visible();
+                project.set_layer_visible(layer, visible).unwrap();
+            } else {
+                project.set_layer_opacity(layer, rng.unit()).unwrap();
+            }
+        }
+        9 => {
+            let size = rng.size();
+            project.resize(size).unwrap();
+        }
+        10 => {
+            let mode = [
+                SelectionMode::Replace,
+                SelectionMode::Add,
+                SelectionMode::Subtract,
+                SelectionMode::Intersect,
+            ][rng.below(4)];
+            match rng.below(4) {
+                0 => project.clear_selection().unwrap(),
+                1 => project.invert_selection().unwrap(),
+                _ => {
+                    let shape =
+                        Selection::rectangle(size, gridtype, rng.tile(size), rng.tile(size));
+                    project.select(&shape, mode).unwrap();
+                }
+            }
+        }
+        11 => {
+            project.begin_edit_group();
+            for _ in 0..1 + rng.below(3) {
+                random_edit(project, rng);
+            }
+            project.end_edit_group();
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn random_edits_undo_and_redo_exactly() {
+    for seed in 0..64 {
+        let mut rng = Lcg(seed);
+        let size = rng.size();
+        let gridtype = [GridType::Square, GridType::Hexagonal][rng.below(2)];
+        let mut project = Project::new(format!("History {seed}"), size, gridtype);
+        for _ in 0..3 {
+            project.push_layer(rng.layer(size));
+        }
+
+        // Every state the history can step between, skipping edits that were recorded but changed nothing.
+        let mut states = vec![snapshot(&project)];
+        for _ in 0..60 {
+            random_edit(&mut project, &mut rng);
+            let state = snapshot(&project);
+            if states.last() != Some(&state) {
+                states.push(state);
+            }
+        }
+
+        let mut undone = vec![snapshot(&project)];
+        while project.undo() {
+            let state = snapshot(&project);
+            if undone.last() != Some(&state) {
+                undone.push(state);
+            }
+        }
+        undone.reverse();
+        assert!(
+            undone == states,
+            "seed {seed}: undo didn't retrace the edits"
+        );
+        assert!(!project.history().can_undo());
+
+        let mut redone = vec![snapshot(&project)];
+        while project.redo() {
+            let state = snapshot(&project);
+            if redone.last() != Some(&state) {
+                redone.push(state);
+            }
+        }
+        assert!(
+            redone == states,
+            "seed {seed}: redo didn't replay the edits"
+        );
+
+        // Undoing part of the way and then editing drops what could have been redone.
+        if states.len() > 2 {
+            assert!(project.undo());
+            project
+                .insert_layer(
+                    0,
+                    LayerV2::new(
+                        None,
+                        project.size(),
+                        LayerV1Canvas::Alpha(vec![0.5; project.size().area() as usize]),
+                    ),
+                )
+                .unwrap();
+            assert!(!project.history().can_redo());
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Checks that images sample onto the tiles under them, and that palettes are generated and dithered sensibly.
+use hexil::app::{CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Project};
+use hexil::export::png::write_raster;
+use hexil::export::{oklab_to_srgb8, rasterize, RasterOptions};
+use hexil::grid::{CubeCoord, OffsetCoord};
+use hexil::import::{
+    import_image, k_means, median_cut, read_image, Dither, ImportError, ImportOptions,
+    PaletteChoice,
+};
+use image::{Rgba, RgbaImage};
+
+mod common;
+use common::base_color_indices;
+
+fn indices(project: &Project) -> Vec<u32> {
+    base_color_indices(&project.layers()[0])
+}
+
+fn options(size: CanvasSize, gridtype: GridType, palette: PaletteChoice) -> ImportOptions {
+    ImportOptions {
+        size,
+        gridtype,
+        palette,
+        dither: Dither::None,
+    }
+}
+
+fn palette() -> Vec<Color> {
+    vec![
+        Color::new(0.2, 0.0, 0.0),
+        Color::new(0.6, 0.2, 0.1),
+        Color::new(0.5, -0.05, -0.2),
+        Color::new(0.85, -0.1, 0.15),
+        Color::new(1.0, 0.0, 0.0),
+    ]
+}
+
+fn pixel(color: Color) -> Rgba<u8> {
+    let [r, g, b] = oklab_to_srgb8(color);
+    Rgba([r, g, b, 255])
+}
+
+#[test]
+fn square_tiles_average_the_pixels_under_them() {
+    let palette = palette();
+    let image = RgbaImage::from_fn(6, 4, |x, y| pixel(palette[(x / 3 + 2 * (y / 2)) as usize]));
+    let size = CanvasSize {
+        width: 2,
+        height: 2,
+    };
+    let project = import_image(
+        "Quadrants".to_owned(),
+        &image,
+        &options(size, GridType::Square, PaletteChoice::Existing(palette)),
+    )
+    .unwrap();
+    assert_eq!(project.name(), "Quadrants");
+    assert_eq!(project.layers().len(), 1);
+    assert_eq!(indices(&project), [0, 1, 2, 3]);
+}
+
+#[test]
+fn hexagons_average_the_pixels_under_their_footprint() {
+    // A rasterised project imports back onto the same tiles, which only works if every hexagon samples the pixels
+    // drawn for it.
+    let palette = palette();
+    let size = CanvasSize {
+        width: 5,
+        height: 4,
+    };
+    let original: Vec<u32> = (0..20).map(|i| (i * 7 % 5) as u32).collect();
+    let mut project = Project::new("Hex".to_owned(), size, GridType::Hexagonal);
+    project.push_layer(LayerV2::new(
+        None,
+        size,
+        LayerV1Canvas::BaseColor {
+            palette: palette.clone().into(),
+            canvas: original.clone().into(),
+        },
+    ));
+    let raster = rasterize(
+        &project,
+        RasterOptions {
+            pixels_per_tile: 24,
+            background: Some([0, 0, 0, 255]),
+        },
+    );
+    let mut png = Vec::new();
+    write_raster(&raster, &mut png).unwrap();
+    let image = read_image(&png).unwrap();
+    let imported = import_image(
+        "Hex".to_owned(),
+        &image,
+        &options(size, GridType::Hexagonal, PaletteChoice::Existing(palette)),
+    )
+    .unwrap();
+    assert_eq!(indices(&imported), original);
+
+    // A tiny image still gives every tile a colour.
+    let tiny = RgbaImage::from_pixel(1, 1, pixel(Color::new(1.0, 0.0, 0.0)));
+    let imported = import_image(
+        "Tiny".to_owned(),
+        &tiny,
+        &options(
+            size,
+            GridType::Hexagonal,
+            PaletteChoice::Existing(self::palette()),
+        ),
+    )
+    .unwrap();
+    assert_eq!(indices(&imported), vec![4; 20]);
+}
+
+#[test]
+fn generated_palettes_find_the_clusters() {
+    let centres = [
+        Color::new(0.2, 0.1, 0.0),
+        Color::new(0.4, -0.1, 0.1),
+        Color::new(0.6, 0.0, -0.05),
+        Color::new(0.8, 0.05, 0.05),
+    ];
+    let colors: Vec<Color> = (0..80)
+        .map(|i| {
+            let jitter = (i % 5) as f32 * 0.004 - 0.008;
+            centres[i % 4] + Color::new(jitter, -jitter, jitter)
+        })
+        .collect();
+    for palette in [median_cut(&colors, 4), k_means(&colors, 4, 10)] {
+        assert_eq!(palette.len(), 4);
+        for (found, centre) in palette.iter().zip(&centres) {
+            assert!((found.l - centre.l).abs() < 0.01, "{found:?} {centre:?}");
+            assert!((found.a - centre.a).abs() < 0.01, "{found:?} {centre:?}");
+        }
+    }
+    // Median cut splits three equal clusters unevenly, but k-means moves the colours back onto them.
+    let three: Vec<Color> = colors
+        .iter()
+        .copied()
+        .filter(|color| color.l < 0.7)
+        .collect();
+    let palette = k_means(&three, 3, 10);
+    for (found, centre) in palette.iter().zip(&centres) {
+        assert!((found.l - centre.l).abs() < 0.01, "{found:?} {centre:?}");
+    }
+    // Never more colours than there are distinct colours.
+    assert_eq!(median_cut(&centres[..3], 8).len(), 3);
+    assert!(median_cut(&[], 4).is_empty());
+}
+
+#[test]
+fn dithering_mixes_the_nearest_colours() {
+    let black_and_white = vec![Color::new(0.0, 0.0, 0.0), Color::new(1.0, 0.0, 0.0)];
+    let grey = RgbaImage::from_pixel(32, 32, pixel(Color::new(0.5, 0.0, 0.0)));
+    let size = CanvasSize {
+        width: 14,
+        height: 14,
+    };
+    for gridtype in [GridType::Square, GridType::Hexagonal] {
+        for dither in [Dither::Ordered, Dither::ErrorDiffusion] {
+            let project = import_image(
+                "Grey".to_owned(),
+                &grey,
+                &ImportOptions {
+                    size,
+                    gridtype,
+                    palette: PaletteChoice::Existing(black_and_white.clone()),
+                    dither,
+                },
+            )
+            .unwrap();
+            let white = indices(&project)
+                .iter()
+                .filter(|index| **index == 1)
+                .count();
+            assert!(
+                (80..=116).contains(&white),
+                "{gridtype:?} {dither:?}: {white}"
+            );
+        }
+    }
+
+    // On hexagonal grids, every tile and its six neighbours cover all seven ordered thresholds, so mid grey is
+    // always a mix, never a clump.
+    let project = import_image(
+        "Grey".to_owned(),
+        &grey,
+        &ImportOptions {
+            size,
+            gridtype: GridType::Hexagonal,
+            palette: PaletteChoice::Existing(black_and_white.clone()),
+            dither: Dither::Ordered,
+        },
+    )
+    .unwrap();
+    let indices = indices(&project);
+    let centre = OffsetCoord::new(6, 6);
+    let neighborhood: Vec<u32> = CubeCoord::from(centre)
+        .range_within(1, size)
+        .map(|tile| indices[OffsetCoord::from(tile).to_index(size).unwrap()])
+        .collect();
+    assert_eq!(neighborhood.len(), 7);
+    assert!(neighborhood.contains(&0) && neighborhood.contains(&1));
+}
+
+#[test]
+fn transparency_becomes_an_alpha_layer() {
+    let image = RgbaImage::from_fn(4, 2, |x, _| {
+        if x < 2 {
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([0, 0, 0, 0])
+        }
+    });
+    let project = import_image(
+        "Half".to_owned(),
+        &image,
+        &options(
+            CanvasSize {
+                width: 2,
+                height: 1,
+            },
+            GridType::Square,
+            PaletteChoice::MedianCut(4),
+        ),
+    )
+    .unwrap();
+    assert_eq!(project.layers().len(), 2);
+    match project.layers()[1].canvas() {
+        LayerV1Canvas::Alpha(alpha) => assert_eq!(alpha, &[1.0, 0.0]),
+        _ => panic!("not an alpha layer"),
+    }
+    // Only the opaque colour is in the palette.
+    match project.layers()[0].canvas() {
+        LayerV1Canvas::BaseColor { palette, .. } => assert_eq!(palette.read().len(), 1),
+        _ => panic!("not a base colour layer"),
+    }
+}
+
+#[test]
+fn bad_imports_are_rejected() {
+    assert!(matches!(
+        read_image(b"GIF89a\x01\x00\x01\x00"),
+        Err(ImportError::UnsupportedFormat)
+    ));
+    assert!(matches!(
+        read_image(b"not an image"),
+        Err(ImportError::UnsupportedFormat)
+    ));
+    let image = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 0, 255]));
+    let empty = CanvasSize {
+        width: 0,
+        height: 3,
+    };
+    assert!(matches!(
+        import_image(
+            String::new(),
+            &image,
+            &options(empty, GridType::Square, PaletteChoice::MedianCut(2))
+        ),
+        Err(ImportError::EmptyCanvas)
+    ));
+    let size = CanvasSize {
+        width: 2,
+        height: 2,
+    };
+    assert!(matches!(
+        import_image(
+            String::new(),
+            &image,
+            &options(size, GridType::Square, PaletteChoice::Existing(Vec::new()))
+        ),
+        Err(ImportError::EmptyPalette)
+    ));
+    // Sizes whose area doesn't fit in a `u64` or in memory are too large, not an overflow or an abort.
+    for (width, height) in [
+        (u64::MAX, 2),
+        (1 << 32, 1 << 32),
+        (1 << 31, 1),
+        (1 << 30, 1 << 30),
+    ] {
+        let huge = CanvasSize { width, height };
+        assert!(matches!(
+            import_image(
+                String::new(),
+                &image,
+                &options(huge, GridType::Square, PaletteChoice::MedianCut(2))
+            ),
+            Err(ImportError::CanvasTooLarge(w, h)) if (w, h) == (width, height)
+        ));
+    }
+    let empty = CanvasSize {
+        width: 0,
+        height: u64::MAX,
+    };
+    assert!(matches!(
+        import_image(
+            String::new(),
+            &image,
+            &options(empty, GridType::Square, PaletteChoice::MedianCut(2))
+        ),
+        Err(ImportError::EmptyCanvas)
+    ));
+}
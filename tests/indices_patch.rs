@@ -0,0 +1,95 @@
+//! Checks that uploading only the regions an `IndicesPatch` asks for leaves the device copy of the canvas indices
+//! byte-identical to copying the whole host buffer. The regions are the ones the renderer records, copied on the CPU
+//! exactly as `copy_buffer` would copy them, so this runs without a GPU.
+use hexil::render::{IndicesPatch, MAX_MERGE_GAP};
+
+mod common;
+use common::Lcg;
+
+/// Writes `patch` into layer `layer` of `host`, where layers are `tiles` long, then copies the regions the renderer
+/// would from `host` to `device`, byte for byte.
+fn upload(patch: &IndicesPatch, layer: usize, tiles: usize, host: &mut [u32], device: &mut [u32]) {
+    patch.apply(&mut host[layer * tiles..(layer + 1) * tiles]);
+    let host_bytes: &[u8] = bytemuck::cast_slice(host);
+    let device_bytes: &mut [u8] = bytemuck::cast_slice_mut(device);
+    for region in patch.copy_regions(layer, tiles) {
+        let src = region.src_offset as usize..(region.src_offset + region.size) as usize;
+        let dst = region.dst_offset as usize..(region.dst_offset + region.size) as usize;
+        device_bytes[dst].copy_from_slice(&host_bytes[src]);
+    }
+}
+
+#[test]
+fn partial_uploads_match_full_copy() {
+    let mut rng = Lcg(0x48_6578_696c);
+    let tiles = 64 * 48;
+    let layers = 3;
+    let mut host: Vec<u32> = (0..tiles * layers).map(|_| rng.next() as u32 % 8).collect();
+    let mut device = host.clone();
+    for _ in 0..600 {
+        // Mix single tiles, strokes of neighbouring tiles, and the odd tile off the end of the layer.
+        let mut changes = Vec::new();
+        for _ in 0..rng.next() % 12 {
+            let start = rng.next() as usize % (tiles + 8);
+            let length = 1 + rng.next() as usize % 6;
+            for position in start..start + length {
+                changes.push((position, rng.next() as u32 % 8));
+            }
+        }
+        let layer = rng.next() as usize % layers;
+        upload(
+            &IndicesPatch::from_tiles(changes),
+            layer,
+            tiles,
+            &mut host,
+            &mut device,
+        );
+        assert_eq!(
+            bytemuck::cast_slice::<u32, u8>(&device),
+            bytemuck::cast_slice::<u32, u8>(&host)
+        );
+    }
+}
+
+#[test]
+fn regions_stay_inside_their_layer() {
+    let tiles = 100;
+    let patch =
+        IndicesPatch::from_tiles([(3, 1), (4, 1), (5, 1), (9, 2), (98, 3), (99, 3), (100, 3)]);
+    let regions: Vec<_> = patch
+        .copy_regions(2, tiles)
+        .iter()
+        .map(|region| (region.src_offset, region.dst_offset, region.size))
+        .collect();
+    // Tiles 3 to 9 are close enough to copy together, and the tile past the end of the layer is left out.
+    assert_eq!(regions, [(812, 812, 28), (1192, 1192, 8)]);
+    let far = IndicesPatch::from_tiles([(0, 1), (MAX_MERGE_GAP + 2, 1)]);
+    assert_eq!(far.copy_regions(0, tiles).len(), 2);
+    assert!(IndicesPatch::new().copy_regions(1, tiles).is_empty());
+}
+
+#[test]
+#[allow(clippy::single_range_in_vec_init)]
+fn adjacent_tiles_share_a_run() {
+    let patch = IndicesPatch::from_tiles([(5, 1), (3, 2), (4, 3), (9, 4), (5, 6)]);
+    let runs: Vec<_> = patch
+        .runs()
+        .iter()
+        .map(|run| (run.start, run.indices.clone()))
+        .collect();
+    assert_eq!(runs, [(3, vec![2, 3, 6]), (9, vec![4])]);
+    assert_eq!(patch.tile_count(), 4);
+    assert_eq!(patch.copy_ranges(0, 100), [3..6, 9..10]);
+    assert_eq!(patch.copy_ranges(3, 100), [3..10]);
+    assert_eq!(patch.copy_ranges(3, 8), [3..6]);
+}
+
+#[test]
+fn empty_patch_copies_nothing() {
+    let patch = IndicesPatch::from_tiles([]);
+    assert!(patch.is_empty());
+    assert!(patch.copy_ranges(MAX_MERGE_GAP, 10).is_empty());
+    assert!(IndicesPatch::from_tiles([(12, 0)])
+        .copy_ranges(MAX_MERGE_GAP, 10)
+        .is_empty());
+}
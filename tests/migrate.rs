@@ -0,0 +1,73 @@
+//! Checks that the version 1 fixture upgrades to the current project with nothing lost.
+use hexil::app::{
+    open_project, CanvasSize, Color, GridType, LayerV1Canvas, ProjectV2, VersionedProject,
+};
+
+const V1: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/projects/v1.hexil");
+
+#[test]
+fn v1_upgrades_one_version_at_a_time() {
+    let project = VersionedProject::load(V1).unwrap();
+    assert_eq!(project.version(), 1);
+    let project = project.upgrade_step().unwrap();
+    assert_eq!(project.version(), ProjectV2::FORMAT_VERSION);
+    assert!(project.upgrade_step().is_err());
+}
+
+#[test]
+fn v1_fixture_opens_as_the_current_project() {
+    let project = open_project(V1).unwrap();
+    assert_eq!(project.name(), "Fixture V1");
+    assert_eq!(
+        project.size(),
+        CanvasSize {
+            width: 4,
+            height: 3
+        }
+    );
+    assert_eq!(project.gridtype(), GridType::Hexagonal);
+
+    let layers = project.layers();
+    let names: Vec<_> = layers.iter().map(|layer| layer.name()).collect();
+    assert_eq!(names, [Some("Mask"), None, Some("Shade")]);
+    // Version 1 had no visibility or opacity, so every layer was drawn in full.
+    assert!(layers
+        .iter()
+        .all(|layer| layer.visible() && layer.opacity() == 1.0));
+
+    match layers[0].canvas() {
+        LayerV1Canvas::Alpha(alpha) => {
+            let expected: Vec<f32> = (0..12).map(|i| i as f32 / 11.0).collect();
+            assert_eq!(alpha, &expected);
+        }
+        _ => panic!("not an alpha layer"),
+    }
+    match layers[1].canvas() {
+        LayerV1Canvas::BaseColor { palette, canvas } => {
+            assert_eq!(
+                *palette.read(),
+                [
+                    Color::new(0.0, 0.0, 0.0),
+                    Color::new(1.0, 0.0, 0.0),
+                    Color::new(0.627955, 0.224863, 0.125846),
+                ]
+            );
+            assert_eq!(*canvas.read(), [0, 1, 2].repeat(4));
+        }
+        _ => panic!("not a base colour layer"),
+    }
+    match layers[2].canvas() {
+        LayerV1Canvas::Shading(shading) => {
+            assert_eq!(shading, &(-6..6).collect::<Vec<i32>>())
+        }
+        _ => panic!("not a shading layer"),
+    }
+}
+
+#[test]
+fn upgraded_projects_save_as_the_current_version() {
+    let mut file = Vec::new();
+    open_project(V1).unwrap().write_to(&mut file).unwrap();
+    let saved = VersionedProject::read_from(file.as_slice()).unwrap();
+    assert_eq!(saved.version(), ProjectV2::FORMAT_VERSION);
+}
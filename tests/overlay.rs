@@ -0,0 +1,26 @@
+//! Checks when the grid overlay fades out.
+use hexil::render::OverlaySettings;
+
+#[test]
+fn grid_fades_out_between_the_thresholds() {
+    let settings = OverlaySettings {
+        grid_fade_start: 20.0,
+        grid_fade_end: 10.0,
+        ..Default::default()
+    };
+    assert_eq!(settings.grid_fade(64.0), 1.0);
+    assert_eq!(settings.grid_fade(20.0), 1.0);
+    assert_eq!(settings.grid_fade(15.0), 0.5);
+    assert_eq!(settings.grid_fade(10.0), 0.0);
+    assert_eq!(settings.grid_fade(2.0), 0.0);
+    assert_eq!(settings.grid_fade(f32::NAN), 0.0);
+}
+
+#[test]
+fn hidden_grid_never_shows() {
+    let settings = OverlaySettings {
+        grid: false,
+        ..Default::default()
+    };
+    assert_eq!(settings.grid_fade(1000.0), 0.0);
+}
@@ -0,0 +1,177 @@
+//! Checks that palette edits keep every tile's colour, apply to every layer sharing the palette, and can be undone.
+use hexil::app::{
+    CanvasSize, Color, EditError, GridType, LayerV1Canvas, LayerV2, Palette, PaletteSortKey,
+    Project,
+};
+
+mod common;
+use common::{base_color_indices, base_color_layer, project_with_layers};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 4,
+    height: 1,
+};
+const WHITE: Color = Color::new(1.0, 0.0, 0.0);
+const RED: Color = Color::new(0.627955, 0.224863, 0.125846);
+const BLUE: Color = Color::new(0.452014, -0.032457, -0.311528);
+const DARK: Color = Color::new(0.2, 0.0, 0.0);
+
+fn base_color(palette: &[Color], indices: [u32; 4]) -> LayerV2 {
+    base_color_layer(SIZE, palette.to_vec(), indices.to_vec())
+}
+
+fn project(layers: impl IntoIterator<Item = LayerV2>) -> Project {
+    project_with_layers("Palette", SIZE, GridType::Square, layers)
+}
+
+fn palette(project: &Project, layer: usize) -> Palette {
+    match project.layers()[layer].canvas() {
+        LayerV1Canvas::BaseColor { palette, .. } => palette.read().clone(),
+        _ => panic!("layer {layer} is not a base colour layer"),
+    }
+}
+
+fn indices(project: &Project, layer: usize) -> Vec<u32> {
+    base_color_indices(&project.layers()[layer])
+}
+
+/// The colour of every tile of `layer`, or `None` for tiles out of range of the palette.
+fn colors(project: &Project, layer: usize) -> Vec<Option<Color>> {
+    let palette = palette(project, layer);
+    indices(project, layer)
+        .into_iter()
+        .map(|index| palette.get(index as usize).copied())
+        .collect()
+}
+
+#[test]
+fn only_layers_with_the_same_palette_are_shared() {
+    let project = project([
+        base_color(&[WHITE, RED, BLUE], [0, 1, 2, 1]),
+        base_color(&[WHITE, RED], [0, 1, 1, 1]),
+        base_color(&[WHITE, RED, BLUE], [2, 2, 0, 1]),
+    ]);
+    assert_eq!(project.layers_sharing_palette(0), Ok(vec![0, 2]));
+    assert_eq!(project.layers_sharing_palette(1), Ok(vec![1]));
+    assert_eq!(
+        project.layers_sharing_palette(3),
+        Err(EditError::NoSuchLayer(3))
+    );
+}
+
+#[test]
+fn inserting_keeps_every_tile_colour() {
+    let mut project = project([
+        base_color(&[WHITE, RED, BLUE], [0, 1, 2, 1]),
+        base_color(&[WHITE, RED, BLUE], [2, 2, 0, 1]),
+    ]);
+    let before = [colors(&project, 0), colors(&project, 1)];
+    project.insert_palette_color(1, 1, DARK).unwrap();
+    assert_eq!(palette(&project, 0), [WHITE, DARK, RED, BLUE]);
+    assert_eq!(palette(&project, 1), [WHITE, DARK, RED, BLUE]);
+    assert_eq!([colors(&project, 0), colors(&project, 1)], before);
+    assert_eq!(indices(&project, 0), [0, 2, 3, 2]);
+}
+
+#[test]
+fn deleting_repaints_with_the_replacement() {
+    let mut project = project([base_color(&[WHITE, RED, BLUE], [0, 1, 2, 1])]);
+    project.delete_palette_color(0, 1, 2).unwrap();
+    assert_eq!(palette(&project, 0), [WHITE, BLUE]);
+    assert_eq!(indices(&project, 0), [0, 1, 1, 1]);
+
+    assert_eq!(
+        project.delete_palette_color(0, 0, 0),
+        Err(EditError::ReplacedWithItself(0))
+    );
+    assert_eq!(
+        project.delete_palette_color(0, 0, 5),
+        Err(EditError::PaletteIndexOutOfRange { index: 5, len: 2 })
+    );
+}
+
+#[test]
+fn reordering_permutes_indices() {
+    let mut project = project([base_color(&[WHITE, RED, BLUE], [0, 1, 2, u32::MAX])]);
+    let before = colors(&project, 0);
+    project.reorder_palette(0, &[2, 0, 1]).unwrap();
+    assert_eq!(palette(&project, 0), [BLUE, WHITE, RED]);
+    assert_eq!(indices(&project, 0), [1, 2, 0, u32::MAX]);
+    assert_eq!(colors(&project, 0), before);
+
+    for order in [&[0, 1][..], &[0, 0, 1], &[0, 1, 3]] {
+        assert_eq!(
+            project.reorder_palette(0, order),
+            Err(EditError::NotAReordering(3))
+        );
+    }
+}
+
+#[test]
+fn merging_folds_near_duplicates_into_the_first() {
+    let almost_red = Color::new(RED.l + 0.001, RED.a, RED.b);
+    let mut project = project([base_color(&[WHITE, RED, BLUE, almost_red], [3, 1, 2, 0])]);
+    assert_eq!(project.merge_similar_palette_colors(0, 0.0001), Ok(0));
+    assert_eq!(project.merge_similar_palette_colors(0, 0.01), Ok(1));
+    assert_eq!(palette(&project, 0), [WHITE, RED, BLUE]);
+    assert_eq!(indices(&project, 0), [1, 1, 2, 0]);
+}
+
+#[test]
+fn sorting_keeps_the_background_first() {
+    let mut project = project([base_color(&[WHITE, RED, DARK, BLUE], [0, 1, 2, 3])]);
+    let before = colors(&project, 0);
+    project.sort_palette(0, PaletteSortKey::Lightness).unwrap();
+    assert_eq!(palette(&project, 0), [WHITE, DARK, BLUE, RED]);
+    assert_eq!(colors(&project, 0), before);
+
+    project.sort_palette(0, PaletteSortKey::Chroma).unwrap();
+    assert_eq!(palette(&project, 0), [WHITE, DARK, RED, BLUE]);
+    assert_eq!(colors(&project, 0), before);
+}
+
+#[test]
+fn every_edit_is_one_undo_step() {
+    let mut project = project([
+        base_color(&[WHITE, RED, BLUE], [0, 1, 2, 1]),
+        base_color(&[WHITE, RED, BLUE], [2, 2, 0, 1]),
+    ]);
+    let original = [
+        (palette(&project, 0), indices(&project, 0)),
+        (palette(&project, 1), indices(&project, 1)),
+    ];
+    project.reorder_palette(0, &[0, 2, 1]).unwrap();
+    project.delete_palette_color(1, 2, 0).unwrap();
+    let edited = [
+        (palette(&project, 0), indices(&project, 0)),
+        (palette(&project, 1), indices(&project, 1)),
+    ];
+    assert!(project.undo());
+    assert!(project.undo());
+    assert_eq!(
+        [
+            (palette(&project, 0), indices(&project, 0)),
+            (palette(&project, 1), indices(&project, 1)),
+        ],
+        original
+    );
+    assert!(!project.undo());
+    assert!(project.redo());
+    assert!(project.redo());
+    assert_eq!(
+        [
+            (palette(&project, 0), indices(&project, 0)),
+            (palette(&project, 1), indices(&project, 1)),
+        ],
+        edited
+    );
+}
+
+#[test]
+fn only_base_colour_layers_have_palettes() {
+    let mut project = project([LayerV2::new(None, SIZE, LayerV1Canvas::Shading(vec![0; 4]))]);
+    assert_eq!(
+        project.sort_palette(0, PaletteSortKey::Hue),
+        Err(EditError::NotBaseColor(0))
+    );
+}
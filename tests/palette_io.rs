@@ -0,0 +1,207 @@
+//! Checks that palettes survive being written to and read back from every supported format, and that files written by
+//! other tools can be read.
+use hexil::app::palette_io::PaletteEntry;
+use hexil::app::{Color, PaletteFile, PaletteFormat, PaletteIoError};
+use palette::{FromColor, Srgb};
+
+fn srgb8(r: u8, g: u8, b: u8) -> Color {
+    Color::from_color(Srgb::new(r, g, b).into_format::<f32>())
+}
+
+fn sample() -> PaletteFile {
+    let entry = |color, name: Option<&str>| PaletteEntry {
+        color,
+        name: name.map(str::to_owned),
+    };
+    PaletteFile {
+        name: Some("Sunset".to_owned()),
+        entries: vec![
+            entry(srgb8(0, 0, 0), Some("Black")),
+            entry(srgb8(255, 255, 255), None),
+            entry(srgb8(0xe0, 0x6b, 0x3c), Some("Burnt orange")),
+            entry(srgb8(0x12, 0x34, 0x56), Some("Déjà vu")),
+        ],
+    }
+}
+
+fn round_trip(file: &PaletteFile, format: PaletteFormat) -> PaletteFile {
+    let mut bytes = Vec::new();
+    file.write_to(format, &mut bytes).unwrap();
+    PaletteFile::read_from(format, bytes.as_slice()).unwrap()
+}
+
+fn assert_colors_close(a: &PaletteFile, b: &PaletteFile) {
+    assert_eq!(a.entries.len(), b.entries.len());
+    for (a, b) in a.entries.iter().zip(&b.entries) {
+        let distance = ((a.color.l - b.color.l).powi(2)
+            + (a.color.a - b.color.a).powi(2)
+            + (a.color.b - b.color.b).powi(2))
+        .sqrt();
+        assert!(distance < 1e-4, "{:?} != {:?}", a.color, b.color);
+    }
+}
+
+#[test]
+fn eight_bit_formats_round_trip_exactly() {
+    let file = sample();
+    for format in [
+        PaletteFormat::Gpl,
+        PaletteFormat::JascPal,
+        PaletteFormat::PaintNet,
+        PaletteFormat::Hex,
+    ] {
+        let read = round_trip(&file, format);
+        // Going through 8 bit sRGB twice lands on the same Oklab value both times.
+        assert_eq!(round_trip(&read, format), read, "{format:?}");
+        assert_colors_close(&read, &file);
+    }
+}
+
+#[test]
+fn names_survive_where_the_format_has_room() {
+    let file = sample();
+    let names = |file: &PaletteFile| {
+        file.entries
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let gpl = round_trip(&file, PaletteFormat::Gpl);
+    assert_eq!(gpl.name, file.name);
+    assert_eq!(names(&gpl), names(&file));
+
+    let ase = round_trip(&file, PaletteFormat::Ase);
+    assert_eq!(ase.name, file.name);
+    assert_eq!(names(&ase), names(&file));
+
+    let paint_net = round_trip(&file, PaletteFormat::PaintNet);
+    assert_eq!(paint_net.name, file.name);
+
+    for format in [PaletteFormat::JascPal, PaletteFormat::Hex] {
+        let read = round_trip(&file, format);
+        assert_eq!(read.name, None);
+        assert!(read.entries.iter().all(|entry| entry.name.is_none()));
+    }
+}
+
+#[test]
+fn ase_names_too_long_to_store_are_cut_short() {
+    // ASE strings hold at most 65535 UTF-16 code units, one of which is the terminating null.
+    let long = "a".repeat(70_000);
+    // The emoji needs two code units, but only one is left, so it's dropped whole rather than split.
+    let split = format!("{}\u{1f3a8}", "b".repeat(65_533));
+    let mut file = sample();
+    file.name = Some(long.clone());
+    file.entries[0].name = Some(split);
+    file.entries[1].name = Some("c".repeat(65_534));
+
+    let read = round_trip(&file, PaletteFormat::Ase);
+    assert_eq!(read.name.as_deref(), Some(&long[..65_534]));
+    assert_eq!(read.entries[0].name, Some("b".repeat(65_533)));
+    assert_eq!(read.entries[1].name, Some("c".repeat(65_534)));
+    assert_eq!(read.entries[2].name, file.entries[2].name);
+}
+
+#[test]
+fn ase_keeps_colours_between_eight_bit_steps() {
+    let file =
+        PaletteFile::from_colors(&[Color::new(0.5, 0.01, -0.02), Color::new(0.75, -0.05, 0.1)]);
+    let read = round_trip(&file, PaletteFormat::Ase);
+    assert_eq!(read.name, None);
+    assert_colors_close(&read, &file);
+}
+
+#[test]
+fn out_of_gamut_colours_are_clamped() {
+    let file = PaletteFile::from_colors(&[Color::new(0.7, 0.4, 0.0)]);
+    let read = round_trip(&file, PaletteFormat::Hex);
+    let srgb: Srgb<u8> = Srgb::from_color(read.entries[0].color).into_format();
+    assert_eq!(srgb.red, 255);
+    assert_eq!(srgb.green, 0);
+}
+
+#[test]
+fn reads_files_from_other_tools() {
+    let gpl = "GIMP Palette\nName: Tiny\nColumns: 4\n# A comment\n  0   0   0\tUntitled\n255 128  64 Warm glow\n";
+    let gpl = PaletteFile::read_from(PaletteFormat::Gpl, gpl.as_bytes()).unwrap();
+    assert_eq!(gpl.name.as_deref(), Some("Tiny"));
+    assert_eq!(gpl.entries.len(), 2);
+    assert_eq!(gpl.entries[1].color, srgb8(255, 128, 64));
+    assert_eq!(gpl.entries[1].name.as_deref(), Some("Warm glow"));
+
+    let jasc = "JASC-PAL\r\n0100\r\n2\r\n255 0 0\r\n0 0 255 255\r\n";
+    let jasc = PaletteFile::read_from(PaletteFormat::JascPal, jasc.as_bytes()).unwrap();
+    assert_eq!(jasc.colors(), vec![srgb8(255, 0, 0), srgb8(0, 0, 255)]);
+
+    let paint_net =
+        "\u{feff};paint.net Palette File\n;Palette Name: Lospec\n;Colors: 2\nFFff0000\n800000ff\n";
+    let paint_net = PaletteFile::read_from(PaletteFormat::PaintNet, paint_net.as_bytes()).unwrap();
+    assert_eq!(paint_net.name.as_deref(), Some("Lospec"));
+    assert_eq!(paint_net.colors(), vec![srgb8(255, 0, 0), srgb8(0, 0, 255)]);
+
+    let hex = "ff0000\n\n#0000FF\n";
+    let hex = PaletteFile::read_from(PaletteFormat::Hex, hex.as_bytes()).unwrap();
+    assert_eq!(hex.colors(), vec![srgb8(255, 0, 0), srgb8(0, 0, 255)]);
+}
+
+#[test]
+fn reads_ase_colour_models() {
+    fn block(bytes: &mut Vec<u8>, model: &[u8; 4], values: &[f32]) {
+        let mut data = vec![0, 1, 0, 0];
+        data.extend_from_slice(model);
+        for value in values {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(&2u16.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&data);
+    }
+    let mut bytes = b"ASEF\0\x01\0\0\0\0\0\x03".to_vec();
+    block(&mut bytes, b"Gray", &[1.0]);
+    block(&mut bytes, b"CMYK", &[0.0, 1.0, 1.0, 0.0]);
+    block(&mut bytes, b"LAB ", &[1.0, 0.0, 0.0]);
+    let file = PaletteFile::read_from(PaletteFormat::Ase, bytes.as_slice()).unwrap();
+    let white = srgb8(255, 255, 255);
+    let expected = PaletteFile::from_colors(&[white, srgb8(255, 0, 0), white]);
+    assert_colors_close(&file, &expected);
+}
+
+#[test]
+fn rejects_malformed_files() {
+    assert!(matches!(
+        PaletteFile::read_from(PaletteFormat::Gpl, "JASC-PAL\n".as_bytes()),
+        Err(PaletteIoError::BadHeader(PaletteFormat::Gpl))
+    ));
+    assert!(matches!(
+        PaletteFile::read_from(
+            PaletteFormat::JascPal,
+            "JASC-PAL\n0100\n3\n0 0 0\n".as_bytes()
+        ),
+        Err(PaletteIoError::Truncated)
+    ));
+    assert!(matches!(
+        PaletteFile::read_from(PaletteFormat::Hex, "ff0000\nnot a colour\n".as_bytes()),
+        Err(PaletteIoError::Syntax { line: 2, .. })
+    ));
+    assert!(matches!(
+        PaletteFile::read_from(
+            PaletteFormat::Ase,
+            b"ASEF\0\x01\0\0\0\0\0\x01\0\x01".as_slice()
+        ),
+        Err(PaletteIoError::Truncated)
+    ));
+}
+
+#[test]
+fn formats_come_from_extensions() {
+    for format in PaletteFormat::ALL {
+        let path = format!("palette.{}", format.extension().to_uppercase());
+        assert_eq!(
+            PaletteFormat::from_path(std::path::Path::new(&path)),
+            Some(format)
+        );
+    }
+    assert_eq!(PaletteFormat::from_extension("png"), None);
+}
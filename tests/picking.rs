@@ -0,0 +1,266 @@
+//! Checks that the tile under the cursor is found exactly, up to the edges and corners of hexagons, through any camera.
+use hexil::app::{CanvasSize, GridType};
+use hexil::camera::{Camera, ViewTransform, MAX_ZOOM, MIN_ZOOM};
+use hexil::grid::picking::{canvas_to_tile_space, pick_tile, tile_center, window_to_clip};
+use hexil::grid::OffsetCoord;
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 7,
+    height: 5,
+};
+const WINDOW: [u32; 2] = [800, 600];
+
+fn cameras() -> [Camera; 4] {
+    [
+        Camera::default(),
+        Camera {
+            pan: [0.2, -0.1],
+            zoom: 1.7,
+        },
+        Camera {
+            pan: [-0.8, 0.6],
+            zoom: 6.0,
+        },
+        Camera {
+            pan: [0.0, 0.0],
+            zoom: 0.3,
+        },
+    ]
+}
+
+/// The window position, in physical pixels, of a point in tile space (see `canvas_to_tile_space`).
+fn cursor(tile_space: [f64; 2], view: &ViewTransform) -> [f64; 2] {
+    let canvas = [
+        (tile_space[0] + 0.5) * 2.0 / SIZE.width as f64 - 1.0,
+        (tile_space[1] + 0.5) * 2.0 / SIZE.height as f64 - 1.0,
+    ];
+    let clip = view.apply(canvas);
+    [
+        (clip[0] + 1.0) / 2.0 * WINDOW[0] as f64,
+        (clip[1] + 1.0) / 2.0 * WINDOW[1] as f64,
+    ]
+}
+
+/// The tile at a point `[dx, dy]` in tile space from the centre of `tile`.
+fn pick_near(
+    tile: OffsetCoord,
+    [dx, dy]: [f64; 2],
+    gridtype: GridType,
+    camera: Camera,
+) -> Option<OffsetCoord> {
+    let view = camera.view_transform(WINDOW, SIZE, gridtype);
+    let shift = match gridtype {
+        GridType::Hexagonal if tile.is_shifted() => 0.5,
+        _ => 0.0,
+    };
+    let point = [tile.col as f64 + dx, tile.row as f64 + shift + dy];
+    pick_tile(cursor(point, &view), WINDOW, SIZE, gridtype, &view)
+}
+
+fn every_tile() -> impl Iterator<Item = OffsetCoord> {
+    (0..SIZE.area() as usize).map(|index| OffsetCoord::from_index(index, SIZE).unwrap())
+}
+
+#[test]
+fn centres_pick_their_own_tile() {
+    for camera in cameras() {
+        for gridtype in [GridType::Square, GridType::Hexagonal] {
+            let view = camera.view_transform(WINDOW, SIZE, gridtype);
+            for tile in every_tile() {
+                let clip = view.apply(tile_center(tile, SIZE, gridtype));
+                let cursor = [
+                    (clip[0] + 1.0) / 2.0 * WINDOW[0] as f64,
+                    (clip[1] + 1.0) / 2.0 * WINDOW[1] as f64,
+                ];
+                assert_eq!(pick_tile(cursor, WINDOW, SIZE, gridtype, &view), Some(tile));
+            }
+        }
+    }
+}
+
+#[test]
+fn hexagon_corners_and_edges_pick_the_right_side() {
+    // In tile space, hexagons are 4/3 of a column wide and a row tall, with corners at the left and right.
+    let corners = [
+        [2.0 / 3.0, 0.0],
+        [1.0 / 3.0, 0.5],
+        [-1.0 / 3.0, 0.5],
+        [-2.0 / 3.0, 0.0],
+        [-1.0 / 3.0, -0.5],
+        [1.0 / 3.0, -0.5],
+    ];
+    // The midpoint of each edge, and the neighbour across it.
+    let edges = [
+        ([0.0, 0.5], [0, 1]),
+        ([0.5, 0.25], [1, 0]),
+        ([0.5, -0.25], [1, -1]),
+        ([0.0, -0.5], [0, -1]),
+        ([-0.5, -0.25], [-1, -1]),
+        ([-0.5, 0.25], [-1, 0]),
+    ];
+    let inner = OffsetCoord::new(2, 2);
+    let shifted = OffsetCoord::new(3, 2);
+    for camera in cameras() {
+        for tile in [inner, shifted] {
+            for [x, y] in corners {
+                let inside = [x * 0.98, y * 0.98];
+                assert_eq!(
+                    pick_near(tile, inside, GridType::Hexagonal, camera),
+                    Some(tile),
+                    "{tile:?} {camera:?}"
+                );
+            }
+            for ([x, y], [dc, dr]) in edges {
+                assert_eq!(
+                    pick_near(tile, [x * 0.98, y * 0.98], GridType::Hexagonal, camera),
+                    Some(tile)
+                );
+                // Odd columns sit half a tile lower, so their neighbours in the next column are a row further down.
+                let dr = if tile.is_shifted() && dc != 0 {
+                    dr + 1
+                } else {
+                    dr
+                };
+                assert_eq!(
+                    pick_near(tile, [x * 1.02, y * 1.02], GridType::Hexagonal, camera),
+                    Some(OffsetCoord::new(tile.col + dc, tile.row + dr)),
+                    "{tile:?} across {x} {y}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn odd_columns_are_shifted_only_on_hexagonal_grids() {
+    let pick_at = |point, gridtype| {
+        let view = Camera::default().view_transform(WINDOW, SIZE, gridtype);
+        pick_tile(cursor(point, &view), WINDOW, SIZE, gridtype, &view)
+    };
+    let top = OffsetCoord::new(1, 0);
+    assert_eq!(
+        canvas_to_tile_space(tile_center(top, SIZE, GridType::Hexagonal), SIZE),
+        [1.0, 0.5]
+    );
+    // The top tile of an odd column starts half a tile down on hexagonal grids, so there's nothing above it.
+    assert_eq!(pick_at([1.0, -0.05], GridType::Hexagonal), None);
+    assert_eq!(pick_at([1.0, -0.05], GridType::Square), Some(top));
+    assert_eq!(pick_at([1.0, 0.95], GridType::Hexagonal), Some(top));
+    assert_eq!(
+        pick_at([1.0, 0.95], GridType::Square),
+        Some(OffsetCoord::new(1, 1))
+    );
+    // Even columns aren't shifted.
+    assert_eq!(
+        pick_at([2.0, -0.45], GridType::Hexagonal),
+        Some(OffsetCoord::new(2, 0))
+    );
+    // And the bottom of the odd columns overhangs the last row.
+    assert_eq!(
+        pick_at([1.0, 4.9], GridType::Hexagonal),
+        Some(OffsetCoord::new(1, 4))
+    );
+    assert_eq!(pick_at([1.0, 4.9], GridType::Square), None);
+    assert_eq!(pick_at([2.0, 4.55], GridType::Hexagonal), None);
+}
+
+#[test]
+fn nothing_is_picked_off_the_canvas() {
+    for camera in cameras() {
+        for gridtype in [GridType::Square, GridType::Hexagonal] {
+            for (tile, offset) in [
+                (OffsetCoord::new(0, 2), [-1.0, 0.0]),
+                (OffsetCoord::new(6, 2), [1.0, 0.0]),
+                (OffsetCoord::new(2, 0), [0.0, -1.0]),
+                (OffsetCoord::new(2, 4), [0.0, 1.0]),
+                (OffsetCoord::new(0, 0), [-5.0, -5.0]),
+            ] {
+                assert_eq!(pick_near(tile, offset, gridtype, camera), None);
+            }
+        }
+    }
+    let view = Camera::default().view_transform(WINDOW, SIZE, GridType::Square);
+    assert_eq!(
+        pick_tile([-10.0, 300.0], WINDOW, SIZE, GridType::Square, &view),
+        None
+    );
+    assert_eq!(
+        pick_tile([400.0, 300.0], [0, 600], SIZE, GridType::Square, &view),
+        None
+    );
+    let empty = CanvasSize {
+        width: 0,
+        height: 0,
+    };
+    assert_eq!(
+        pick_tile([400.0, 300.0], WINDOW, empty, GridType::Square, &view),
+        None
+    );
+}
+
+#[test]
+fn view_transforms_invert_exactly() {
+    let points = [
+        [0.0, 0.0],
+        [-1.0, -1.0],
+        [1.0, 1.0],
+        [0.37, -0.81],
+        [-3.5, 12.0],
+    ];
+    for gridtype in [GridType::Square, GridType::Hexagonal] {
+        for zoom in [MIN_ZOOM, 0.5, 1.0, 3.0, 40.0, MAX_ZOOM] {
+            for pan in [[0.0, 0.0], [0.4, -0.7], [-2.0, 3.0]] {
+                let view = Camera { pan, zoom }.view_transform(WINDOW, SIZE, gridtype);
+                for point in points {
+                    let back = view.invert(view.apply(point));
+                    assert!((back[0] - point[0]).abs() < 1e-9 && (back[1] - point[1]).abs() < 1e-9);
+                    let clip = view.invert(point);
+                    let again = view.apply(clip);
+                    assert!(
+                        (again[0] - point[0]).abs() < 1e-9 && (again[1] - point[1]).abs() < 1e-9
+                    );
+                }
+                // The pan is at the centre of the window, up to the rounding of the offset to `f32`.
+                let centre = view.apply([pan[0] as f64, pan[1] as f64]);
+                for axis in 0..2 {
+                    let rounding = view.offset[axis].abs() as f64 * f32::EPSILON as f64;
+                    assert!(centre[axis].abs() <= rounding, "{centre:?}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn zooming_and_panning_keep_the_cursor_on_the_canvas_point() {
+    for gridtype in [GridType::Square, GridType::Hexagonal] {
+        let mut camera = Camera::default();
+        for (factor, cursor) in [
+            (2.5, [123.0, 77.0]),
+            (0.2, [700.0, 500.0]),
+            (8.0, [400.0, 300.0]),
+        ] {
+            let before = camera
+                .view_transform(WINDOW, SIZE, gridtype)
+                .invert(window_to_clip(cursor, WINDOW));
+            camera.zoom_at(factor, cursor, WINDOW, SIZE, gridtype);
+            let after = camera
+                .view_transform(WINDOW, SIZE, gridtype)
+                .invert(window_to_clip(cursor, WINDOW));
+            assert!((before[0] - after[0]).abs() < 1e-5 && (before[1] - after[1]).abs() < 1e-5);
+
+            let view = camera.view_transform(WINDOW, SIZE, gridtype);
+            let start = self::cursor([2.0, 3.0], &view);
+            camera.pan_by_pixels([15.0, -9.0], WINDOW, SIZE, gridtype);
+            let view = camera.view_transform(WINDOW, SIZE, gridtype);
+            let end = self::cursor([2.0, 3.0], &view);
+            assert!(
+                (end[0] - start[0] - 15.0).abs() < 1e-2 && (end[1] - start[1] + 9.0).abs() < 1e-2
+            );
+        }
+        camera.zoom_at(1e9, [0.0, 0.0], WINDOW, SIZE, gridtype);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+        camera.zoom_at(0.0, [0.0, 0.0], WINDOW, SIZE, gridtype);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+    }
+}
@@ -0,0 +1,194 @@
+//! Checks that projects survive saving and loading exactly, and that damaged files are rejected with the right error.
+use hexil::app::project_file::{HEADER_LEN, MAGIC};
+use hexil::app::{
+    CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Project, ProjectFileError,
+    VersionedProject,
+};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 3,
+    height: 2,
+};
+
+fn project() -> Project {
+    let mut project = Project::new("Saved".to_owned(), SIZE, GridType::Hexagonal);
+    project.push_layer(LayerV2::new(
+        Some("Colours".to_owned()),
+        SIZE,
+        LayerV1Canvas::BaseColor {
+            palette: vec![Color::new(0.2, 0.0, 0.0), Color::new(0.7, 0.1, -0.1)].into(),
+            canvas: vec![0, 1, 1, 0, 1, 0].into(),
+        },
+    ));
+    project.push_layer(LayerV2::new(
+        None,
+        SIZE,
+        LayerV1Canvas::Shading(vec![-2, -1, 0, 1, 2, 3]),
+    ));
+    project.push_layer(
+        LayerV2::new(
+            None,
+            SIZE,
+            LayerV1Canvas::Alpha(vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0]),
+        )
+        .with_visible(false)
+        .with_opacity(0.5),
+    );
+    project
+}
+
+fn file() -> Vec<u8> {
+    let mut file = Vec::new();
+    project().write_to(&mut file).unwrap();
+    file
+}
+
+fn read(file: &[u8]) -> Result<Project, ProjectFileError> {
+    Project::read_from(file)
+}
+
+#[test]
+fn saved_projects_load_unchanged() {
+    let file = file();
+    assert_eq!(file[..8], MAGIC);
+    let loaded = read(&file).unwrap();
+    assert_eq!(loaded.name(), "Saved");
+    assert_eq!(loaded.size(), SIZE);
+    assert_eq!(loaded.gridtype(), GridType::Hexagonal);
+    assert_eq!(loaded.layers()[0].name(), Some("Colours"));
+    assert!(!loaded.layers()[2].visible());
+    assert_eq!(loaded.layers()[2].opacity(), 0.5);
+    // Everything that is saved is the same, so saving again gives the same file.
+    let mut again = Vec::new();
+    loaded.write_to(&mut again).unwrap();
+    assert_eq!(again, file);
+
+    let path = std::env::temp_dir().join(format!("hexil-{}.hexil", std::process::id()));
+    project().save(&path).unwrap();
+    let loaded = Project::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let mut again = Vec::new();
+    loaded.unwrap().write_to(&mut again).unwrap();
+    assert_eq!(again, file);
+}
+
+#[test]
+fn saves_replace_files_whole_or_not_at_all() {
+    let dir = std::env::temp_dir().join(format!("hexil-save-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("project.hexil");
+    std::fs::write(&path, b"an older save").unwrap();
+    project().save(&path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), file());
+
+    // Nothing can be renamed over a directory, so this save fails after the new file has been written.
+    let blocked = dir.join("blocked.hexil");
+    std::fs::create_dir(&blocked).unwrap();
+    std::fs::write(blocked.join("keep"), b"untouched").unwrap();
+    assert!(matches!(
+        project().save(&blocked),
+        Err(ProjectFileError::Io(_))
+    ));
+    assert_eq!(std::fs::read(blocked.join("keep")).unwrap(), b"untouched");
+
+    // Neither save leaves its temporary file behind.
+    let mut names: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(names, ["blocked.hexil", "project.hexil"]);
+}
+
+#[test]
+fn bad_magic_is_rejected() {
+    let mut file = file();
+    file[0] = b'h';
+    assert!(matches!(read(&file), Err(ProjectFileError::BadMagic)));
+    // A file converted to Unix newlines loses a byte of the magic.
+    let mut converted = file.clone();
+    converted[0] = b'H';
+    converted.remove(6);
+    assert!(matches!(read(&converted), Err(ProjectFileError::BadMagic)));
+    assert!(matches!(
+        read(b"not a project file at all, but long enough"),
+        Err(ProjectFileError::BadMagic)
+    ));
+}
+
+#[test]
+fn unknown_versions_are_rejected() {
+    let mut file = file();
+    file[8..12].copy_from_slice(&99u32.to_le_bytes());
+    assert!(matches!(
+        read(&file),
+        Err(ProjectFileError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        VersionedProject::read_from(file.as_slice()),
+        Err(ProjectFileError::UnsupportedVersion(99))
+    ));
+    // Older versions open through `VersionedProject`, but aren't the current version.
+    file[8..12].copy_from_slice(&1u32.to_le_bytes());
+    assert!(matches!(
+        read(&file),
+        Err(ProjectFileError::UnsupportedVersion(1))
+    ));
+}
+
+#[test]
+fn truncated_files_are_rejected() {
+    let file = file();
+    for len in [0, 7, HEADER_LEN - 1, HEADER_LEN, file.len() - 1] {
+        assert!(
+            matches!(read(&file[..len]), Err(ProjectFileError::Truncated)),
+            "{len} of {} bytes",
+            file.len()
+        );
+    }
+}
+
+#[test]
+fn trailing_bytes_are_rejected() {
+    let mut file = file();
+    file.extend_from_slice(&[0, 1, 2]);
+    assert!(matches!(
+        read(&file),
+        Err(ProjectFileError::TrailingBytes(3))
+    ));
+}
+
+#[test]
+fn corrupt_payloads_fail_the_checksum() {
+    let mut file = file();
+    let last = file.len() - 1;
+    file[last] ^= 1;
+    assert!(matches!(
+        read(&file),
+        Err(ProjectFileError::ChecksumMismatch)
+    ));
+
+    let mut file = self::file();
+    file[20] ^= 1;
+    assert!(matches!(
+        read(&file),
+        Err(ProjectFileError::ChecksumMismatch)
+    ));
+}
+
+#[test]
+fn huge_payload_lengths_are_truncation_not_allocation() {
+    let mut file = file();
+    file[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(read(&file), Err(ProjectFileError::Truncated)));
+
+    // A shorter length leaves the rest of the payload as trailing bytes.
+    let mut file = self::file();
+    let payload_len = (file.len() - HEADER_LEN) as u64;
+    file[12..20].copy_from_slice(&(payload_len - 4).to_le_bytes());
+    assert!(matches!(
+        read(&file),
+        Err(ProjectFileError::TrailingBytes(4))
+    ));
+}
@@ -0,0 +1,295 @@
+//! Checks selection shapes, how they combine, that tools stay inside them, and that they survive undo and redo.
+use hexil::app::{
+    CanvasSize, Color, EditError, GridType, LayerV2, Project, Selection, SelectionMode,
+};
+use hexil::grid::OffsetCoord;
+use hexil::tools::{fill, pencil, FillMode, FillOptions};
+
+mod common;
+use common::{base_color_indices as indices, base_color_layer};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 5,
+    height: 4,
+};
+
+fn base_color(indices: Vec<u32>) -> LayerV2 {
+    let palette = vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0)];
+    base_color_layer(SIZE, palette, indices)
+}
+
+fn tiles(selection: &Selection) -> Vec<(i32, i32)> {
+    selection
+        .positions()
+        .map(|position| {
+            let tile = OffsetCoord::from_index(position, SIZE).unwrap();
+            (tile.col, tile.row)
+        })
+        .collect()
+}
+
+#[test]
+fn rectangles_are_cut_to_the_canvas() {
+    let selection = Selection::rectangle(
+        SIZE,
+        GridType::Square,
+        OffsetCoord::new(3, 2),
+        OffsetCoord::new(9, -1),
+    );
+    assert_eq!(
+        tiles(&selection),
+        [(3, 0), (4, 0), (3, 1), (4, 1), (3, 2), (4, 2)]
+    );
+}
+
+#[test]
+fn ranges_follow_the_grid() {
+    let centre = OffsetCoord::new(2, 1);
+    let hex = Selection::range(SIZE, GridType::Hexagonal, centre, 1);
+    assert_eq!(hex.len(), 7);
+    for neighbor in centre.adjacent(GridType::Hexagonal, Default::default()) {
+        assert!(hex.contains_tile(neighbor));
+    }
+    assert_eq!(Selection::range(SIZE, GridType::Square, centre, 1).len(), 9);
+    assert_eq!(
+        Selection::range(SIZE, GridType::Hexagonal, OffsetCoord::new(0, 0), 0).len(),
+        1
+    );
+}
+
+#[test]
+fn lassos_select_tiles_with_centres_inside() {
+    let triangle = [[-0.5, -0.5], [4.5, -0.5], [-0.5, 4.5]];
+    let selection = Selection::lasso(SIZE, GridType::Square, &triangle);
+    assert!(selection.contains_tile(OffsetCoord::new(0, 0)));
+    assert!(selection.contains_tile(OffsetCoord::new(3, 0)));
+    assert!(!selection.contains_tile(OffsetCoord::new(3, 2)));
+    assert!(!selection.contains_tile(OffsetCoord::new(4, 3)));
+    assert!(Selection::lasso(SIZE, GridType::Square, &triangle[..2]).is_empty());
+}
+
+#[test]
+fn magic_wand_matches_palette_indices() {
+    #[rustfmt::skip]
+    let layer = base_color(vec![
+        1, 1, 0, 1, 1,
+        0, 1, 0, 0, 0,
+        0, 0, 0, 1, 0,
+        0, 0, 0, 0, 0,
+    ]);
+    let contiguous = Selection::magic_wand(
+        &layer,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        FillOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(tiles(&contiguous), [(0, 0), (1, 0), (1, 1)]);
+    let global = Selection::magic_wand(
+        &layer,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        FillOptions {
+            mode: FillMode::Global,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(global.len(), 6);
+}
+
+#[test]
+fn selections_combine_and_invert() {
+    let left = Selection::rectangle(
+        SIZE,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        OffsetCoord::new(2, 0),
+    );
+    let right = Selection::rectangle(
+        SIZE,
+        GridType::Square,
+        OffsetCoord::new(2, 0),
+        OffsetCoord::new(4, 0),
+    );
+    let combined = |mode| {
+        let mut selection = left.clone();
+        selection.combine(&right, mode).unwrap();
+        tiles(&selection)
+    };
+    assert_eq!(combined(SelectionMode::Replace), [(2, 0), (3, 0), (4, 0)]);
+    assert_eq!(combined(SelectionMode::Add).len(), 5);
+    assert_eq!(combined(SelectionMode::Subtract), [(0, 0), (1, 0)]);
+    assert_eq!(combined(SelectionMode::Intersect), [(2, 0)]);
+
+    let mut inverted = left.clone();
+    inverted.invert();
+    assert_eq!(inverted.len(), 20 - 3);
+    assert!(!inverted.contains_tile(OffsetCoord::new(1, 0)));
+
+    let mut hex = Selection::none(SIZE, GridType::Hexagonal);
+    assert!(hex.combine(&left, SelectionMode::Add).is_err());
+}
+
+#[test]
+fn tools_stay_inside_the_selection() {
+    let layer = base_color(vec![0; 20]);
+    let selection = Selection::rectangle(
+        SIZE,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        OffsetCoord::new(1, 3),
+    );
+    let outside = pencil(&layer, OffsetCoord::new(3, 3), 1, Some(&selection)).unwrap();
+    assert!(outside.is_empty());
+    let changes = fill(
+        &layer,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        1,
+        FillOptions::default(),
+        Some(&selection),
+    )
+    .unwrap();
+    assert_eq!(changes.len(), 8);
+    let painted: Vec<bool> = indices(&layer).iter().map(|index| *index == 1).collect();
+    let selected: Vec<bool> = (0..20).map(|i| selection.contains(i)).collect();
+    assert_eq!(painted, selected);
+
+    // A fill started outside the selection does nothing at all.
+    let changes = fill(
+        &layer,
+        GridType::Square,
+        OffsetCoord::new(4, 0),
+        1,
+        FillOptions::default(),
+        Some(&selection),
+    )
+    .unwrap();
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn selections_are_undone_and_redone() {
+    let mut project = Project::new("Selection".to_owned(), SIZE, GridType::Hexagonal);
+    project.push_layer(base_color(vec![0; 20]));
+    let range = Selection::range(SIZE, GridType::Hexagonal, OffsetCoord::new(2, 2), 1);
+    project.select(&range, SelectionMode::Replace).unwrap();
+    project.invert_selection().unwrap();
+    assert_eq!(project.selection().map(Selection::len), Some(13));
+
+    assert!(project.undo());
+    assert_eq!(project.selection(), Some(&range));
+    assert!(project.undo());
+    assert_eq!(project.selection(), None);
+    assert!(project.redo());
+    assert_eq!(project.selection(), Some(&range));
+
+    // Subtracting everything leaves an empty selection active, rather than none at all.
+    project.select(&range, SelectionMode::Subtract).unwrap();
+    assert_eq!(project.selection().map(Selection::len), Some(0));
+    project.clear_selection().unwrap();
+    assert_eq!(project.selection(), None);
+
+    let square = Selection::all(SIZE, GridType::Square);
+    assert!(matches!(
+        project.select(&square, SelectionMode::Add),
+        Err(EditError::SelectionMismatch(..))
+    ));
+}
+
+#[test]
+fn without_a_selection_subtracting_and_intersecting_start_from_every_tile() {
+    let mut project = Project::new("Selection".to_owned(), SIZE, GridType::Square);
+    project.push_layer(base_color(vec![0; 20]));
+    let left = Selection::rectangle(
+        SIZE,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        OffsetCoord::new(1, 3),
+    );
+
+    project.select(&left, SelectionMode::Subtract).unwrap();
+    let mut right = left.clone();
+    right.invert();
+    assert_eq!(project.selection(), Some(&right));
+
+    project.clear_selection().unwrap();
+    project.select(&left, SelectionMode::Intersect).unwrap();
+    assert_eq!(project.selection(), Some(&left));
+}
+
+#[test]
+fn resizing_clears_the_selection_until_undone() {
+    let mut project = Project::new("Selection".to_owned(), SIZE, GridType::Square);
+    project.push_layer(base_color(vec![0; 20]));
+    let all = Selection::all(SIZE, GridType::Square);
+    project.set_selection(Some(all.clone())).unwrap();
+    project
+        .resize(CanvasSize {
+            width: 2,
+            height: 2,
+        })
+        .unwrap();
+    assert_eq!(project.selection(), None);
+    assert!(project.undo());
+    assert_eq!(project.size(), SIZE);
+    assert_eq!(project.selection(), Some(&all));
+}
+
+#[test]
+fn empty_selections_keep_every_tile_from_being_edited() {
+    let mut project = Project::new("Selection".to_owned(), SIZE, GridType::Square);
+    project.push_layer(base_color(vec![0; 20]));
+    let left = Selection::rectangle(
+        SIZE,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        OffsetCoord::new(1, 3),
+    );
+    let right = Selection::rectangle(
+        SIZE,
+        GridType::Square,
+        OffsetCoord::new(3, 0),
+        OffsetCoord::new(4, 3),
+    );
+    let all = Selection::all(SIZE, GridType::Square);
+
+    let mut emptied = Vec::new();
+    project.select(&left, SelectionMode::Replace).unwrap();
+    project.select(&right, SelectionMode::Intersect).unwrap();
+    emptied.push(project.selection().cloned());
+    project.select(&all, SelectionMode::Replace).unwrap();
+    project.select(&all, SelectionMode::Subtract).unwrap();
+    emptied.push(project.selection().cloned());
+    project.select(&all, SelectionMode::Replace).unwrap();
+    project.invert_selection().unwrap();
+    emptied.push(project.selection().cloned());
+
+    let empty = Selection::none(SIZE, GridType::Square);
+    for selection in emptied {
+        assert_eq!(selection.as_ref(), Some(&empty));
+    }
+    let layer = &project.layers()[0];
+    let selection = project.selection();
+    assert!(pencil(layer, OffsetCoord::new(2, 2), 1, selection)
+        .unwrap()
+        .is_empty());
+    assert!(fill(
+        layer,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        1,
+        FillOptions::default(),
+        selection,
+    )
+    .unwrap()
+    .is_empty());
+    assert_eq!(indices(layer), vec![0; 20]);
+
+    // Undoing steps back through the empty selection like any other.
+    assert!(project.undo());
+    assert_eq!(project.selection(), Some(&all));
+    assert!(project.redo());
+    assert_eq!(project.selection(), Some(&empty));
+}
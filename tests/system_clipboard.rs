@@ -0,0 +1,229 @@
+//! Checks that tiles go through the clipboard intact, come with an image for other applications, and that images from
+//! other applications paste as the nearest palette colours.
+use hexil::app::clipboard::system::{
+    decode_tiles, encode_tiles, rasterize_floating, ClipboardBackend, ClipboardError,
+    ClipboardItem, MemoryClipboard, MAX_PASTED_COORD, PNG_MIME, TILES_MIME,
+};
+use hexil::app::{
+    CanvasSize, Color, EditError, FloatingSelection, GridType, LayerV1Canvas, LayerV2, Project,
+    Selection, SelectionMode,
+};
+use hexil::export::png::write_raster;
+use hexil::export::{raster_size, Raster};
+use hexil::grid::OffsetCoord;
+
+mod common;
+use common::{base_color_indices, base_color_layer};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 4,
+    height: 3,
+};
+
+fn base_color(palette: Vec<Color>, indices: Vec<u32>) -> LayerV2 {
+    base_color_layer(SIZE, palette, indices)
+}
+
+fn indices(project: &Project) -> Vec<u32> {
+    base_color_indices(&project.layers()[0])
+}
+
+fn png(raster: &Raster) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_raster(raster, &mut data).unwrap();
+    data
+}
+
+#[test]
+fn tiles_round_trip_between_projects() {
+    let palette = vec![Color::new(0.0, 0.0, 0.0), Color::new(0.7, 0.1, 0.1)];
+    let mut source = Project::new("Source".to_owned(), SIZE, GridType::Hexagonal);
+    source.push_layer(base_color(
+        palette.clone(),
+        vec![1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+    ));
+    let selected = Selection::from_positions(SIZE, GridType::Hexagonal, [0, 1, 5]);
+    source.select(&selected, SelectionMode::Replace).unwrap();
+
+    let copied = source.copy_selection(0).unwrap();
+    let mut clipboard = MemoryClipboard::new();
+    source.cut_to_clipboard(0, &mut clipboard).unwrap();
+    let mimes: Vec<&str> = clipboard
+        .items()
+        .iter()
+        .map(|item| item.mime.as_str())
+        .collect();
+    assert_eq!(mimes, [TILES_MIME, PNG_MIME]);
+    assert!(indices(&source).iter().all(|index| *index == 0));
+
+    let mut target = Project::new("Target".to_owned(), SIZE, GridType::Hexagonal);
+    target.push_layer(base_color(palette, vec![0; 12]));
+    let pasted = target.paste_from_clipboard(0, &mut clipboard).unwrap();
+    assert_eq!(pasted, copied);
+    assert_eq!(indices(&target), [1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn the_image_covers_just_the_copied_tiles() {
+    let mut project = Project::new("Image".to_owned(), SIZE, GridType::Hexagonal);
+    project.push_layer(base_color(
+        vec![Color::new(0.0, 0.0, 0.0), Color::new(1.0, 0.0, 0.0)],
+        vec![1; 12],
+    ));
+    // Starts on an odd column, so the image includes the even column before it to keep the shifting right.
+    let selected = Selection::rectangle(
+        SIZE,
+        GridType::Hexagonal,
+        OffsetCoord::new(1, 1),
+        OffsetCoord::new(2, 2),
+    );
+    project.select(&selected, SelectionMode::Replace).unwrap();
+    let floating = project.copy_selection(0).unwrap();
+    let raster = rasterize_floating(&floating, 8);
+    let size = CanvasSize {
+        width: 3,
+        height: 2,
+    };
+    assert_eq!(
+        (raster.width, raster.height),
+        raster_size(size, GridType::Hexagonal, 8)
+    );
+    let opaque = raster.pixels.iter().filter(|pixel| pixel[3] == 255).count();
+    assert!(opaque > 0 && opaque < raster.pixels.len());
+    assert!(raster
+        .pixels
+        .iter()
+        .all(|pixel| pixel[3] == 0 || *pixel == [255; 4]));
+
+    let mut clipboard = MemoryClipboard::new();
+    project.copy_to_clipboard(0, &mut clipboard).unwrap();
+    let image = image::load_from_memory(&clipboard.read(PNG_MIME).unwrap().unwrap()).unwrap();
+    assert_eq!(
+        (image.width(), image.height()),
+        raster_size(size, GridType::Hexagonal, 16)
+    );
+}
+
+#[test]
+fn images_paste_as_the_nearest_colours() {
+    let red = [200, 30, 40, 255];
+    let blue = [20, 40, 210, 255];
+    let raster = Raster {
+        width: 3,
+        height: 2,
+        pixels: vec![
+            red,
+            blue,
+            [255, 255, 255, 0],
+            blue,
+            [250, 250, 245, 255],
+            red,
+        ],
+    };
+    let mut clipboard = MemoryClipboard::new();
+    clipboard
+        .write(vec![ClipboardItem::new(PNG_MIME, png(&raster))])
+        .unwrap();
+
+    let palette = vec![
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(0.45, -0.03, -0.25),
+        Color::new(0.55, 0.18, 0.08),
+        Color::new(1.0, 0.0, 0.0),
+    ];
+    let mut project = Project::new("Image".to_owned(), SIZE, GridType::Square);
+    project.push_layer(base_color(palette, vec![0; 12]));
+    let pasted = project.paste_from_clipboard(0, &mut clipboard).unwrap();
+    assert_eq!(pasted.len(), 5);
+    // The transparent pixel is left out.
+    assert_eq!(indices(&project), [2, 1, 0, 0, 1, 3, 2, 0, 0, 0, 0, 0]);
+    assert_eq!(project.selection().map(Selection::len), Some(5));
+
+    project.push_layer(LayerV2::new(
+        None,
+        SIZE,
+        LayerV1Canvas::Alpha(vec![1.0; 12]),
+    ));
+    assert!(matches!(
+        project.paste_from_clipboard(1, &mut clipboard),
+        Err(ClipboardError::Edit(EditError::NotBaseColor(1)))
+    ));
+}
+
+#[test]
+fn bad_clipboards_are_rejected() {
+    let mut project = Project::new("Bad".to_owned(), SIZE, GridType::Square);
+    project.push_layer(base_color(vec![Color::new(0.0, 0.0, 0.0)], vec![0; 12]));
+    let mut clipboard = MemoryClipboard::new();
+    assert!(matches!(
+        project.paste_from_clipboard(0, &mut clipboard),
+        Err(ClipboardError::NothingToPaste)
+    ));
+
+    let mut data = encode_tiles(&project.copy_selection(0).unwrap()).unwrap();
+    assert!(decode_tiles(&data).is_ok());
+    assert!(matches!(
+        decode_tiles(&data[..data.len() - 1]),
+        Err(ClipboardError::Decode(_))
+    ));
+    data[8] = 2;
+    assert!(matches!(
+        decode_tiles(&data),
+        Err(ClipboardError::UnsupportedVersion(2))
+    ));
+    assert!(matches!(
+        decode_tiles(b"not tiles at all"),
+        Err(ClipboardError::Corrupt)
+    ));
+}
+
+#[test]
+fn far_away_tiles_are_rejected_before_they_overflow() {
+    let mut project = Project::new("Far".to_owned(), SIZE, GridType::Hexagonal);
+    project.push_layer(base_color(vec![Color::new(0.0, 0.0, 0.0)], vec![0; 12]));
+    let copied = project.copy_selection(0).unwrap();
+    // The header, the grid type, the anchor and the number of offsets come before the first offset.
+    let first_offset = 12 + 4 + 8 + 8;
+    let with_offset = |floating: &FloatingSelection, [col, row]: [i32; 2]| {
+        let mut data = encode_tiles(floating).unwrap();
+        data[first_offset..first_offset + 4].copy_from_slice(&col.to_le_bytes());
+        data[first_offset + 4..first_offset + 8].copy_from_slice(&row.to_le_bytes());
+        data
+    };
+
+    // As far as anything is allowed to be, every tile can still be placed, however the tiles are turned.
+    let mut furthest = copied.clone();
+    furthest.move_to(OffsetCoord::new(MAX_PASTED_COORD, -MAX_PASTED_COORD));
+    let data = with_offset(&furthest, [MAX_PASTED_COORD, MAX_PASTED_COORD]);
+    let mut floating = decode_tiles(&data).unwrap();
+    for _ in 0..6 {
+        floating.rotate_clockwise();
+        assert_eq!(floating.tiles().count(), 12);
+    }
+    floating.flip_horizontally();
+    floating.flip_vertically();
+    assert_eq!(floating.tiles().count(), 12);
+    project.paste(0, &floating).unwrap();
+
+    for anchor in [
+        OffsetCoord::new(MAX_PASTED_COORD + 1, 0),
+        OffsetCoord::new(0, i32::MIN),
+    ] {
+        let mut moved = copied.clone();
+        moved.move_to(anchor);
+        assert!(matches!(
+            decode_tiles(&encode_tiles(&moved).unwrap()),
+            Err(ClipboardError::Corrupt)
+        ));
+    }
+    for offset in [
+        [i32::MAX, 0],
+        [0, -MAX_PASTED_COORD - 1],
+        [i32::MIN, i32::MIN],
+    ] {
+        assert!(matches!(
+            decode_tiles(&with_offset(&copied, offset)),
+            Err(ClipboardError::Corrupt)
+        ));
+    }
+}
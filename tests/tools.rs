@@ -0,0 +1,199 @@
+//! Checks the pencil, eraser and line tools, and that lines are unbroken on both grid types.
+use std::collections::HashSet;
+
+use hexil::app::{CanvasSize, Color, GridType, LayerV1Canvas, LayerV2};
+use hexil::grid::{CubeCoord, HexDirection, OffsetCoord};
+use hexil::tools::{eraser, line, line_tiles, pencil, TileChange, ToolError, BACKGROUND_INDEX};
+
+mod common;
+use common::{base_color_indices as indices, base_color_layer};
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 4,
+    height: 3,
+};
+
+fn layer() -> LayerV2 {
+    let palette = vec![
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(0.5, 0.1, 0.0),
+        Color::new(1.0, 0.0, 0.0),
+    ];
+    base_color_layer(SIZE, palette, vec![1; 12])
+}
+
+/// Checks that `tiles` runs from `from` to `to` without repeating a tile, where `touching` says whether consecutive
+/// tiles are allowed to follow each other.
+fn assert_unbroken(
+    tiles: &[OffsetCoord],
+    from: OffsetCoord,
+    to: OffsetCoord,
+    touching: impl Fn(OffsetCoord, OffsetCoord) -> bool,
+) {
+    assert_eq!(tiles.first(), Some(&from));
+    assert_eq!(tiles.last(), Some(&to));
+    assert_eq!(tiles.iter().collect::<HashSet<_>>().len(), tiles.len());
+    for pair in tiles.windows(2) {
+        assert!(touching(pair[0], pair[1]), "{from:?} to {to:?}: {tiles:?}");
+    }
+}
+
+#[test]
+fn hex_lines_run_in_all_six_directions() {
+    let hex_touching =
+        |a: OffsetCoord, b: OffsetCoord| CubeCoord::from(a).distance(CubeCoord::from(b)) == 1;
+    for start in [OffsetCoord::new(0, 0), OffsetCoord::new(3, -2)] {
+        for direction in HexDirection::ALL {
+            for length in 1..8 {
+                let end =
+                    OffsetCoord::from(CubeCoord::from(start).add(direction.offset().scale(length)));
+                let tiles = line_tiles(start, end, GridType::Hexagonal);
+                assert_eq!(tiles.len(), length as usize + 1);
+                assert_unbroken(&tiles, start, end, hex_touching);
+            }
+        }
+    }
+    // Lines between tiles that aren't in a straight line still never skip a tile.
+    for end in [(7, 3), (-5, 2), (2, -9), (-4, -4), (1, 6)] {
+        let (from, to) = (OffsetCoord::new(1, 1), OffsetCoord::new(end.0, end.1));
+        let tiles = line_tiles(from, to, GridType::Hexagonal);
+        assert_eq!(
+            tiles.len() as u32,
+            CubeCoord::from(from).distance(CubeCoord::from(to)) + 1
+        );
+        assert_unbroken(&tiles, from, to, hex_touching);
+    }
+}
+
+#[test]
+fn square_lines_touch_at_least_at_corners() {
+    let square_touching =
+        |a: OffsetCoord, b: OffsetCoord| (a.col - b.col).abs().max((a.row - b.row).abs()) == 1;
+    let from = OffsetCoord::new(2, 2);
+    for (dc, dr) in [
+        (5, 5),
+        (-5, 5),
+        (5, -5),
+        (-5, -5),
+        (7, 2),
+        (-2, 7),
+        (3, -8),
+        (-6, -1),
+        (4, 0),
+        (0, -4),
+    ] {
+        let to = OffsetCoord::new(from.col + dc, from.row + dr);
+        let tiles = line_tiles(from, to, GridType::Square);
+        assert_eq!(tiles.len() as i32, dc.abs().max(dr.abs()) + 1);
+        assert_unbroken(&tiles, from, to, square_touching);
+    }
+    // Diagonals are exactly diagonal.
+    let diagonal = line_tiles(from, OffsetCoord::new(-1, 5), GridType::Square);
+    assert_eq!(
+        diagonal,
+        [(2, 2), (1, 3), (0, 4), (-1, 5)].map(|(col, row)| OffsetCoord::new(col, row))
+    );
+    assert_eq!(line_tiles(from, from, GridType::Square), [from]);
+    assert_eq!(line_tiles(from, from, GridType::Hexagonal), [from]);
+}
+
+#[test]
+fn pencil_paints_one_tile() {
+    let layer = layer();
+    let changes = pencil(&layer, OffsetCoord::new(2, 1), 2, None).unwrap();
+    assert_eq!(
+        changes.iter().copied().collect::<Vec<_>>(),
+        [TileChange {
+            index: 6,
+            old: 1,
+            new: 2
+        }]
+    );
+    let mut expected = vec![1; 12];
+    expected[6] = 2;
+    assert_eq!(indices(&layer), expected);
+    // Painting what's already there changes nothing.
+    assert!(pencil(&layer, OffsetCoord::new(2, 1), 2, None)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn eraser_paints_the_background() {
+    let layer = layer();
+    let changes = eraser(&layer, OffsetCoord::new(3, 2), None).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(indices(&layer)[11], BACKGROUND_INDEX);
+    assert!(eraser(&layer, OffsetCoord::new(3, 2), None)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn lines_paint_every_tile_on_them() {
+    let layer = layer();
+    let changes = line(
+        &layer,
+        GridType::Square,
+        OffsetCoord::new(0, 0),
+        OffsetCoord::new(3, 2),
+        0,
+        None,
+    )
+    .unwrap();
+    let painted: Vec<usize> = changes.indices().collect();
+    let expected: Vec<usize> = line_tiles(
+        OffsetCoord::new(0, 0),
+        OffsetCoord::new(3, 2),
+        GridType::Square,
+    )
+    .into_iter()
+    .map(|tile| tile.to_index(SIZE).unwrap())
+    .collect();
+    assert_eq!(painted, expected);
+    assert_eq!(
+        indices(&layer).iter().filter(|index| **index == 0).count(),
+        4
+    );
+}
+
+#[test]
+fn failed_tools_change_nothing() {
+    let layer = layer();
+    let off_canvas = OffsetCoord::new(4, 0);
+    assert_eq!(
+        pencil(&layer, off_canvas, 2, None),
+        Err(ToolError::OutOfBounds(off_canvas))
+    );
+    assert_eq!(
+        eraser(&layer, OffsetCoord::new(-1, 0), None),
+        Err(ToolError::OutOfBounds(OffsetCoord::new(-1, 0)))
+    );
+    // Lines check every tile before painting any of them.
+    assert_eq!(
+        line(
+            &layer,
+            GridType::Hexagonal,
+            OffsetCoord::new(0, 0),
+            OffsetCoord::new(5, 0),
+            2,
+            None
+        ),
+        Err(ToolError::OutOfBounds(off_canvas))
+    );
+    assert_eq!(
+        pencil(&layer, OffsetCoord::new(0, 0), 3, None),
+        Err(ToolError::PaletteIndexOutOfRange { index: 3, len: 3 })
+    );
+    assert_eq!(indices(&layer), vec![1; 12]);
+
+    let alpha = LayerV2::new(None, SIZE, LayerV1Canvas::Alpha(vec![1.0; 12]));
+    assert_eq!(
+        pencil(&alpha, OffsetCoord::new(0, 0), 0, None),
+        Err(ToolError::NotBaseColor)
+    );
+    assert_eq!(
+        eraser(&alpha, OffsetCoord::new(0, 0), None),
+        Err(ToolError::NotBaseColor)
+    );
+}
@@ -0,0 +1,72 @@
+//! Checks what validation reports, and that layers that don't fit their project never get as far as validation.
+use hexil::app::{
+    CanvasSize, Color, GridType, LayerV1Canvas, LayerV2, Project, ProjectFileError, ValidationIssue,
+};
+
+mod common;
+use common::project_with_layers;
+
+const SIZE: CanvasSize = CanvasSize {
+    width: 2,
+    height: 2,
+};
+
+fn project(layers: impl IntoIterator<Item = LayerV2>) -> Project {
+    project_with_layers("Validated", SIZE, GridType::Square, layers)
+}
+
+#[test]
+fn bad_tiles_are_reported() {
+    let project = project([
+        LayerV2::new(
+            None,
+            SIZE,
+            LayerV1Canvas::BaseColor {
+                palette: vec![Color::new(0.0, 0.0, 0.0), Color::new(1.0, 0.0, 0.0)].into(),
+                canvas: vec![0, 5, 1, 2].into(),
+            },
+        ),
+        LayerV2::new(None, SIZE, LayerV1Canvas::Alpha(vec![0.5, 1.0, 0.0, 0.25])),
+        LayerV2::new(
+            None,
+            SIZE,
+            LayerV1Canvas::Alpha(vec![0.5, f32::NAN, -0.1, 0.25]),
+        ),
+    ]);
+    assert_eq!(
+        project.validate(),
+        [
+            ValidationIssue::IndicesOutOfRange {
+                layer: 0,
+                count: 2,
+                first_tile: 1,
+                largest_index: 5,
+                palette_len: 2,
+            },
+            ValidationIssue::AlphaOutOfRange {
+                layer: 2,
+                count: 2,
+                first_tile: 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn layers_that_dont_fit_are_rejected_on_load() {
+    let wrong_size = CanvasSize {
+        width: 4,
+        height: 1,
+    };
+    for layer in [
+        LayerV2::new(None, wrong_size, LayerV1Canvas::Shading(vec![0; 4])),
+        LayerV2::new(None, SIZE, LayerV1Canvas::Shading(vec![0; 3])),
+    ] {
+        let mut file = Vec::new();
+        project([layer]).write_to(&mut file).unwrap();
+        assert!(matches!(
+            Project::read_from(file.as_slice()),
+            Err(ProjectFileError::Inconsistent(_))
+        ));
+    }
+}